@@ -4,7 +4,12 @@
 //
 
 fn main() {
-    let protos = ["src/proto/signal_service.proto"];
+    let protos = [
+        "src/proto/signal_service.proto",
+        "src/proto/device_name.proto",
+        "src/proto/provisioning.proto",
+        "src/proto/stickers.proto",
+    ];
     prost_build::compile_protos(&protos, &["src"]).expect("Protobufs in src are valid");
     for proto in &protos {
         println!("cargo:rerun-if-changed={}", proto);
@@ -1,60 +1,113 @@
-use std::convert::TryInto;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::path::{Path, PathBuf};
 
-use hyper::Method;
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use hyper::{Method, StatusCode};
 use libsignal_protocol::{
-    message_encrypt, process_prekey_bundle, DeviceId, IdentityKeyStore, PreKeyBundle, PreKeyStore,
-    ProtocolAddress, SessionStore, SignedPreKeyStore,
+    message_decrypt_prekey, message_decrypt_signal, message_encrypt, process_prekey_bundle,
+    sealed_sender_decrypt, DeviceId, IdentityKey, IdentityKeyStore, PreKeyBundle,
+    PreKeySignalMessage, PreKeyStore, ProtocolAddress, PublicKey, SessionStore, SignalMessage,
+    SignalProtocolError, SignedPreKeyStore,
 };
+use prost::Message;
 use rand::{CryptoRng, Rng};
+use tracing::{debug, info, instrument, warn};
+use url::Url;
+use uuid::Uuid;
+use zkgroup::groups::{GroupMasterKey, GroupSecretParams};
 
+use crate::account::attributes::AccountAttributes;
+use crate::account::device_name::{decrypt_device_name, encrypt_device_name};
+use crate::account::link_device::{encrypt_provision_message, ProvisioningUrl};
 use crate::account::messages::{
-    MessageResponse200, MessageResponse409, MessagesWrapper, SendMetadata,
+    AttachmentUploadAttributes, AvatarUploadAttributes, DeviceInfo, DeviceNameRequest,
+    DevicesResponse, IncomingMessageList, MessageResponse200, MessageResponse409,
+    MessageResponse410, MessagesWrapper, ProfileResponse, ProfileWriteRequest,
+    ProvisioningCodeResponse, ProvisioningMessageRequest, ReceivedMessage, SendMetadata,
+    StickerPackUploadAttributes,
 };
 use crate::account::pre_keys::DeviceKeys;
 use crate::common::{ApiConfig, ApiPath};
 use crate::error::{Error, Result};
-use crate::store::SledStateStore;
-use crate::utils::HttpClient;
+use crate::logging::Secret;
+use crate::proto::signal_service::attachment_pointer::AttachmentIdentifier;
+use crate::proto::signal_service::data_message;
+use crate::proto::signal_service::envelope::Type as EnvelopeType;
+use crate::proto::signal_service::sync_message::sticker_pack_operation::Type as StickerPackOperationType;
+use crate::proto::signal_service::sync_message::{self, request::Type as RequestType};
+use crate::proto::signal_service::{
+    sticker_pack, AttachmentPointer, Content, DataMessage, Envelope, ProvisionMessage, StickerPack,
+    SyncMessage,
+};
+use crate::send::{Attachment, Quote, SendOptions, SendOutcome};
+use crate::store::{
+    AccountRegistry, Contact, InboxEntry, InboxMessage, Profile, SledStateStore, StoredMessage,
+    StoredStickerPack, ViewOnceMedia,
+};
+use crate::utils::{timestamp_millis, HttpClient, WebClient};
 
+use super::attachments::{content_type_of, decrypt_attachment, encrypt_attachment};
+use super::body_ranges::{parse_markdown, relocate_mentions, substitute_mentions};
+use super::link_preview::{find_url, parse_page};
+use super::outbox::{gives_up, retry_delay};
+use super::padding::{add_padding, strip_padding};
 use super::pre_keys::{generate_pre_keys, generate_signed_pre_key, PreKeyState};
+use super::profile::{
+    decrypt_profile_data, decrypt_profile_string, encrypt_profile_data, encrypt_profile_string,
+    profile_key_commitment, profile_key_version, ABOUT_PADDING, EMOJI_PADDING, NAME_PADDING,
+};
+use super::stickers::{
+    decode_hex, decrypt_sticker_data, encode_hex, encrypt_sticker_data, sticker_pack_url,
+    MAX_PACK_SIZE,
+};
+use super::sync::{read_contact_details, read_group_details};
+use super::thumbnail::{create_thumbnail, THUMBNAIL_CONTENT_TYPE};
+use super::vcard::{parse_vcards, write_vcard, VCard};
 
-pub(crate) struct AccountManager<'r, R: Rng + CryptoRng + Clone> {
+pub(crate) struct AccountManager<R: Rng + CryptoRng + Clone> {
     http_client: HttpClient,
     state: SledStateStore,
-    csprng: &'r mut R,
+    csprng: R,
+    trust_root: PublicKey,
+    api_config: ApiConfig,
 }
 
-impl<'r, R: Rng + CryptoRng + Clone> AccountManager<'r, R> {
+impl<R: Rng + CryptoRng + Clone> AccountManager<R> {
     pub(crate) fn new(
         data_dir: PathBuf,
-        csprng: &'r mut R,
+        account: Option<&str>,
+        csprng: R,
         api_config: &ApiConfig,
     ) -> Result<Self> {
-        let state = SledStateStore::new(data_dir)?;
+        let state = AccountRegistry::load(data_dir)?.open(account)?;
         Self::with_store(state, csprng, api_config)
     }
 
     pub(crate) fn with_store(
         state: SledStateStore,
-        csprng: &'r mut R,
+        csprng: R,
         api_config: &ApiConfig,
     ) -> Result<Self> {
         let username = state.api_username()?;
         let password = state.api_password()?;
         let http_client = HttpClient::new(&username, &password, api_config)?;
+        let trust_root = PublicKey::deserialize(&api_config.trust_root)?;
 
         Ok(Self {
             http_client,
             state,
             csprng,
+            trust_root,
+            api_config: api_config.clone(),
         })
     }
 
     pub async fn initialize_pre_keys(&mut self) -> Result<()> {
-        let pre_keys = generate_pre_keys(100, self.csprng);
+        let pre_keys = generate_pre_keys(100, &mut self.csprng);
         let identity_key_pair = self.state.get_identity_key_pair(None).await?;
-        let signed_pre_key = generate_signed_pre_key(&identity_key_pair, 1.into(), self.csprng)?;
+        let signed_pre_key =
+            generate_signed_pre_key(&identity_key_pair, 1.into(), &mut self.csprng)?;
 
         for pre_key in pre_keys.iter() {
             self.state
@@ -76,14 +129,154 @@ impl<'r, R: Rng + CryptoRng + Clone> AccountManager<'r, R> {
         Ok(())
     }
 
+    pub async fn list_devices(&self) -> Result<Vec<DeviceInfo>> {
+        let response: DevicesResponse = self
+            .http_client
+            .send(Method::GET, ApiPath::Devices)
+            .await?
+            .json()
+            .await?;
+        let identity_key_pair = self.state.get_identity_key_pair(None).await?;
+
+        Ok(response
+            .devices
+            .into_iter()
+            .map(|mut device| {
+                // Devices registered by older clients have plaintext names
+                device.name = device
+                    .name
+                    .map(|name| decrypt_device_name(&name, &identity_key_pair).unwrap_or(name));
+                device
+            })
+            .collect())
+    }
+
+    pub fn device_id(&self) -> Result<u32> {
+        Ok(self.state.address()?.device_id().into())
+    }
+
+    pub async fn remove_device(&self, device_id: u32) -> Result<()> {
+        self.http_client
+            .send(Method::DELETE, ApiPath::RemoveDevice { device_id })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn rename_device(&mut self, name: &str) -> Result<()> {
+        let identity_key_pair = self.state.get_identity_key_pair(None).await?;
+        let request = DeviceNameRequest {
+            device_name: encrypt_device_name(
+                name,
+                identity_key_pair.identity_key(),
+                &mut self.csprng,
+            )?,
+        };
+        self.http_client
+            .send_json(Method::PUT, ApiPath::DeviceName, &request)
+            .await?;
+        self.state.set_device_name(name)?;
+        Ok(())
+    }
+
+    /// Links new secondary device to this account. Only primary device can do this.
+    pub async fn link_device(&mut self, provisioning_url: &str) -> Result<()> {
+        let provisioning_url = ProvisioningUrl::parse(provisioning_url)?;
+        if self.device_id()? != 1 {
+            return Err(Error::NotPrimaryDevice);
+        }
+
+        let ProvisioningCodeResponse { verification_code } = self
+            .http_client
+            .send(Method::GET, ApiPath::ProvisioningCode)
+            .await?
+            .json()
+            .await?;
+
+        let identity_key_pair = self.state.get_identity_key_pair(None).await?;
+        let message = ProvisionMessage {
+            aci_identity_key_public: Some(identity_key_pair.public_key().serialize().into_vec()),
+            aci_identity_key_private: Some(identity_key_pair.private_key().serialize()),
+            aci: Some(self.state.address()?.name().to_string()),
+            number: self.state.number()?,
+            provisioning_code: Some(verification_code),
+            profile_key: self.state.profile_key()?.map(|key| key.to_vec()),
+            read_receipts: Some(false),
+            provisioning_version: Some(1),
+            ..Default::default()
+        };
+        let envelope =
+            encrypt_provision_message(&message, &provisioning_url.public_key, &mut self.csprng)?;
+
+        let request = ProvisioningMessageRequest {
+            body: STANDARD.encode(envelope.encode_to_vec()),
+        };
+        self.http_client
+            .send_json(
+                Method::PUT,
+                ApiPath::Provisioning {
+                    destination: &provisioning_url.uuid,
+                },
+                &request,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Refreshes attributes of this device on the server, including advertised capabilities.
+    /// Requires the profile key, since the unidentified access key derived from it is part
+    /// of the attributes.
+    pub async fn update_attributes(&self) -> Result<()> {
+        let identity_key_pair = self.state.get_identity_key_pair(None).await?;
+        let registration_id = self.state.get_local_registration_id(None).await?;
+        let name = match self.state.device_name()? {
+            Some(name) => Some(encrypt_device_name(
+                &name,
+                identity_key_pair.identity_key(),
+                &mut self.csprng.clone(),
+            )?),
+            None => None,
+        };
+
+        let profile_key = self.local_profile_key()?;
+        let attributes =
+            AccountAttributes::new(name, registration_id).with_profile_key(&profile_key);
+        self.http_client
+            .send_json(Method::PUT, ApiPath::AccountAttributes, &attributes)
+            .await?;
+        Ok(())
+    }
+
+    /// Removes this device from the account. When called on primary device,
+    /// the whole account is deleted.
+    pub async fn unregister(&self) -> Result<()> {
+        let device_id = self.device_id()?;
+        let path = if device_id == 1 {
+            ApiPath::Account
+        } else {
+            ApiPath::RemoveDevice { device_id }
+        };
+        self.http_client.send(Method::DELETE, path).await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
     pub async fn create_sessions(
         &self,
         recipient: &str,
         device_id: Option<DeviceId>,
     ) -> Result<Vec<(ProtocolAddress, u32)>> {
+        let bundles = self.fetch_pre_key_bundles(recipient, device_id).await?;
+        self.process_pre_key_bundles(recipient, bundles).await
+    }
+
+    async fn fetch_pre_key_bundles(
+        &self,
+        recipient: &str,
+        device_id: Option<DeviceId>,
+    ) -> Result<Vec<PreKeyBundle>> {
         let device_id = &device_id.map_or(String::from("*"), |x| x.to_string());
 
-        let response: DeviceKeys = self
+        let response: DeviceKeys = match self
             .http_client
             .send(
                 Method::GET,
@@ -92,26 +285,43 @@ impl<'r, R: Rng + CryptoRng + Clone> AccountManager<'r, R> {
                     device_id,
                 },
             )
-            .await?
-            .json()
-            .await?;
-
-        eprintln!("{:?}", response);
+            .await
+        {
+            Ok(response) => response.json().await?,
+            Err(Error::HttpError(status_code, _)) if status_code == 404 => {
+                return Err(Error::UnregisteredRecipient(recipient.to_string()));
+            }
+            Err(err) => return Err(err),
+        };
 
         let bundles: Vec<PreKeyBundle> = response.try_into()?;
+        debug!(devices = bundles.len(), "fetched pre key bundles");
+        Ok(bundles)
+    }
+
+    async fn process_pre_key_bundles(
+        &self,
+        recipient: &str,
+        bundles: Vec<PreKeyBundle>,
+    ) -> Result<Vec<(ProtocolAddress, u32)>> {
         let mut addrs = Vec::with_capacity(bundles.len());
+        let own_address = self.state.address()?;
 
         for bundle in bundles {
             let remote_address = ProtocolAddress::new(
                 recipient.to_string(),
                 bundle.device_id().expect("Impl doesn't return Err"),
             );
+            // Sync messages are sent to our own account, but never to this device
+            if remote_address == own_address {
+                continue;
+            }
 
             let mut session_store = self.state.session_store.clone();
             let mut identity_store = self.state.identity_store.clone();
             let mut csprng = self.csprng.clone();
 
-            process_prekey_bundle(
+            match process_prekey_bundle(
                 &remote_address,
                 &mut session_store,
                 &mut identity_store,
@@ -119,7 +329,17 @@ impl<'r, R: Rng + CryptoRng + Clone> AccountManager<'r, R> {
                 &mut csprng,
                 None,
             )
-            .await?;
+            .await
+            {
+                Ok(()) => {}
+                Err(SignalProtocolError::UntrustedIdentity(address)) => {
+                    return Err(Error::UntrustedIdentity {
+                        address,
+                        identity_key: *bundle.identity_key()?,
+                    });
+                }
+                Err(err) => return Err(err.into()),
+            }
             addrs.push((remote_address, bundle.registration_id()?));
         }
 
@@ -137,7 +357,7 @@ impl<'r, R: Rng + CryptoRng + Clone> AccountManager<'r, R> {
     async fn load_sessions(&self, recipient: &str) -> Result<Vec<(ProtocolAddress, u32)>> {
         self.state
             .session_store
-            .load_sessions_by_prefix(recipient)
+            .load_sessions_by_name(recipient)
             .await?
             .into_iter()
             // TODO: Is this filter correct?
@@ -158,8 +378,568 @@ impl<'r, R: Rng + CryptoRng + Clone> AccountManager<'r, R> {
         }
     }
 
-    pub async fn send_message(&self, recipient: &str, message: &str) -> Result<()> {
-        loop {
+    #[instrument(skip(self, message, options), fields(body = %Secret(message)))]
+    pub async fn send_message(
+        &self,
+        recipient: &str,
+        message: &str,
+        options: &SendOptions,
+    ) -> Result<SendOutcome> {
+        let recipient = &self.state.resolve_recipient(recipient)?;
+        if !options.allow_blocked && self.state.is_blocked(recipient)? {
+            return Err(Error::BlockedRecipient(recipient.to_string()));
+        }
+        if options.view_once {
+            check_view_once(message, &options.attachments)?;
+        }
+
+        let mentions = options
+            .mentions
+            .iter()
+            .map(|mention| {
+                let uuid = self.resolve_uuid(&mention.recipient)?;
+                Ok((mention.start, mention.length, uuid))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (body, mut body_ranges) = substitute_mentions(message, &mentions)?;
+        let body = if options.markdown {
+            let (body, styles) = parse_markdown(&body);
+            relocate_mentions(&body, &mut body_ranges);
+            body_ranges.extend(styles);
+            body
+        } else {
+            body
+        };
+        let quote = match &options.quote {
+            Some(quote) => Some(self.build_quote(recipient, quote).await?),
+            None => None,
+        };
+
+        let preview = if options.link_preview {
+            self.link_preview(&body).await?.into_iter().collect()
+        } else {
+            Vec::new()
+        };
+        let mut attachments = Vec::with_capacity(options.attachments.len());
+        for attachment in &options.attachments {
+            let file_name = attachment.file_name.as_deref();
+            attachments.push(
+                self.upload_attachment(&attachment.data, attachment_type(attachment), file_name)
+                    .await?,
+            );
+        }
+        let mut contacts = Vec::new();
+        for text in &options.contacts {
+            for vcard in parse_vcards(text)? {
+                contacts.push(self.shared_contact(vcard).await?);
+            }
+        }
+
+        let expire_timer = self
+            .state
+            .find_contact(recipient)?
+            .and_then(|contact| contact.expire_timer)
+            .filter(|&timer| timer > 0);
+        let timestamp = timestamp_millis();
+        // Shared contacts and attachments don't need accompanying text
+        let body = Some(body)
+            .filter(|body| !body.is_empty() || (contacts.is_empty() && attachments.is_empty()));
+        let content = Content {
+            data_message: Some(DataMessage {
+                body: body.clone(),
+                timestamp: Some(timestamp),
+                expire_timer,
+                profile_key: self.state.profile_key()?.map(Vec::from),
+                quote,
+                required_protocol_version: (!mentions.is_empty())
+                    .then(|| data_message::ProtocolVersion::Mentions as u32),
+                body_ranges: body_ranges.clone(),
+                preview,
+                contact: contacts,
+                attachments: attachments.clone(),
+                is_view_once: options.view_once.then_some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let history = StoredMessage {
+            conversation: recipient.to_string(),
+            author: self.state.address()?.name().to_string(),
+            timestamp,
+            body,
+            body_ranges: body_ranges.iter().map(Into::into).collect(),
+            // View-once media is not kept after sending
+            attachments: if options.view_once {
+                Vec::new()
+            } else {
+                attachments.iter().map(Into::into).collect()
+            },
+            expires_at: expires_at(expire_timer, timestamp),
+        };
+        self.send_queued(recipient, &content, timestamp, Some(history))
+            .await
+    }
+
+    /// Resolves the contact to its UUID, which is required by mentions and quotes.
+    fn resolve_uuid(&self, recipient: &str) -> Result<String> {
+        let identifier = self.state.resolve_recipient(recipient)?;
+        match Uuid::parse_str(&identifier) {
+            Ok(uuid) => Ok(uuid.to_string()),
+            Err(_) => Err(Error::InvalidContact(format!(
+                "UUID of {} is unknown",
+                recipient
+            ))),
+        }
+    }
+
+    /// Turns contact read from vCard into the shared contact, uploading its photo as the avatar.
+    async fn shared_contact(&self, vcard: VCard) -> Result<data_message::Contact> {
+        let mut contact = vcard.contact;
+        if let Some((photo, content_type)) = vcard.photo {
+            contact.avatar = Some(data_message::contact::Avatar {
+                avatar: Some(self.upload_attachment(&photo, &content_type, None).await?),
+                is_profile: Some(false),
+            });
+        }
+        Ok(contact)
+    }
+
+    /// Writes the contact shared with us as vCard, embedding its downloaded avatar.
+    pub async fn shared_contact_vcard(&self, contact: &data_message::Contact) -> Result<String> {
+        let avatar = contact
+            .avatar
+            .as_ref()
+            .and_then(|avatar| avatar.avatar.as_ref());
+        let photo = match avatar {
+            Some(pointer) => Some((
+                self.download_attachment(pointer).await?,
+                pointer.content_type(),
+            )),
+            None => None,
+        };
+        Ok(write_vcard(
+            contact,
+            photo
+                .as_ref()
+                .map(|(data, content_type)| (data.as_slice(), *content_type)),
+        ))
+    }
+
+    /// Encrypts and uploads the attachment to the CDN, returning the pointer to be sent.
+    pub(crate) async fn upload_attachment(
+        &self,
+        data: &[u8],
+        content_type: &str,
+        file_name: Option<&str>,
+    ) -> Result<AttachmentPointer> {
+        let form: AttachmentUploadAttributes = self
+            .http_client
+            .send(Method::GET, ApiPath::AttachmentUploadForm)
+            .await?
+            .json()
+            .await?;
+        let encrypted = encrypt_attachment(data, &mut self.csprng.clone());
+        let fields = [
+            ("acl", form.acl.as_str()),
+            ("key", form.key.as_str()),
+            ("policy", form.policy.as_str()),
+            ("Content-Type", "application/octet-stream"),
+            ("x-amz-algorithm", form.algorithm.as_str()),
+            ("x-amz-credential", form.credential.as_str()),
+            ("x-amz-date", form.date.as_str()),
+            ("x-amz-signature", form.signature.as_str()),
+        ];
+        HttpClient::cdn(&self.api_config, 0)?
+            .send_multipart(
+                Method::POST,
+                ApiPath::AttachmentUpload,
+                &fields,
+                &encrypted.data,
+            )
+            .await?;
+
+        Ok(AttachmentPointer {
+            attachment_identifier: Some(AttachmentIdentifier::CdnId(form.attachment_id)),
+            content_type: Some(content_type.to_string()),
+            key: Some(encrypted.key),
+            size: Some(data.len() as u32),
+            digest: Some(encrypted.digest),
+            file_name: file_name.map(str::to_string),
+            upload_timestamp: Some(timestamp_millis()),
+            cdn_number: Some(0),
+            ..Default::default()
+        })
+    }
+
+    /// Builds preview of the first link in the body. Failure only skips the preview,
+    /// since the message is worth sending without it.
+    async fn link_preview(&self, body: &str) -> Result<Option<data_message::Preview>> {
+        if !self.state.settings()?.link_previews {
+            return Ok(None);
+        }
+        let url = match find_url(body) {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        match self.fetch_link_preview(url).await {
+            Ok(preview) => Ok(Some(preview)),
+            Err(err) => {
+                warn!(%url, error = %err, "failed to generate link preview");
+                Ok(None)
+            }
+        }
+    }
+
+    async fn fetch_link_preview(&self, url: &str) -> Result<data_message::Preview> {
+        let invalid = |reason: &str| Error::LinkPreviewError(reason.to_string());
+        let web_client = WebClient::new(&self.api_config.web)?;
+
+        let page = web_client
+            .get(&Url::parse(url).map_err(|_| invalid("invalid URL"))?)
+            .await?;
+        let is_html = page
+            .content_type
+            .as_deref()
+            .map_or(false, |content_type| content_type.starts_with("text/html"));
+        if !is_html {
+            return Err(invalid("not a HTML page"));
+        }
+        let metadata = parse_page(&String::from_utf8_lossy(&page.data));
+
+        let image_url = metadata
+            .image
+            .as_deref()
+            .and_then(|image| page.url.join(image).ok());
+        let image = match image_url {
+            Some(image_url) => match self.fetch_preview_image(&web_client, &image_url).await {
+                Ok(image) => Some(image),
+                Err(err) => {
+                    warn!(url = %image_url, error = %err, "skipping preview image");
+                    None
+                }
+            },
+            None => None,
+        };
+        if metadata.title.is_none() && image.is_none() {
+            return Err(invalid("page has neither title nor image"));
+        }
+
+        Ok(data_message::Preview {
+            url: Some(url.to_string()),
+            title: metadata.title,
+            image,
+            description: metadata.description,
+            date: None,
+        })
+    }
+
+    async fn fetch_preview_image(
+        &self,
+        web_client: &WebClient,
+        url: &Url,
+    ) -> Result<AttachmentPointer> {
+        let image = web_client.get(url).await?;
+        let content_type = image
+            .content_type
+            .filter(|content_type| content_type.starts_with("image/"))
+            .ok_or_else(|| Error::LinkPreviewError(String::from("not an image")))?;
+        self.upload_attachment(&image.data, &content_type, None)
+            .await
+    }
+
+    /// Quotes the message from the local history of the conversation. Message missing
+    /// in the history is quoted without its text, which clients show as not found.
+    async fn build_quote(&self, conversation: &str, quote: &Quote) -> Result<data_message::Quote> {
+        let author = self.resolve_uuid(&quote.author)?;
+        let quoted = self
+            .state
+            .message(conversation, quote.timestamp)?
+            .filter(|message| message.author == author);
+        if quoted.is_none() {
+            warn!(
+                timestamp = quote.timestamp,
+                "quoted message is not in the local history"
+            );
+        }
+
+        let (text, body_ranges, attachments) = match quoted {
+            Some(message) => (message.body, message.body_ranges, message.attachments),
+            None => (None, Vec::new(), Vec::new()),
+        };
+        let mut quoted_attachments = Vec::with_capacity(attachments.len());
+        for attachment in &attachments {
+            // Clients show thumbnails only of images
+            let is_image = attachment
+                .content_type
+                .as_deref()
+                .map_or(false, |content_type| content_type.starts_with("image/"));
+            let thumbnail = if is_image {
+                let pointer = AttachmentPointer::decode(attachment.pointer.as_slice())?;
+                match self.upload_thumbnail(&pointer).await {
+                    Ok(thumbnail) => Some(thumbnail),
+                    Err(err) => {
+                        warn!(error = %err, "quoting attachment without thumbnail");
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            quoted_attachments.push(data_message::quote::QuotedAttachment {
+                content_type: attachment.content_type.clone(),
+                file_name: attachment.file_name.clone(),
+                thumbnail,
+            });
+        }
+
+        Ok(data_message::Quote {
+            id: Some(quote.timestamp),
+            author_uuid: Some(author),
+            text,
+            attachments: quoted_attachments,
+            body_ranges: body_ranges.iter().map(Into::into).collect(),
+        })
+    }
+
+    /// Downloads the quoted image and uploads its scaled down copy.
+    async fn upload_thumbnail(&self, pointer: &AttachmentPointer) -> Result<AttachmentPointer> {
+        let thumbnail = create_thumbnail(&self.download_attachment(pointer).await?)?;
+        self.upload_attachment(&thumbnail, THUMBNAIL_CONTENT_TYPE, None)
+            .await
+    }
+
+    /// Changes disappearing messages timer of the conversation and notifies the recipient.
+    /// Zero seconds turns disappearing messages off.
+    pub async fn set_expire_timer(&self, recipient: &str, seconds: u32) -> Result<SendOutcome> {
+        let recipient = &self.state.resolve_recipient(recipient)?;
+        self.save_expire_timer(recipient, seconds)?;
+
+        let timestamp = timestamp_millis();
+        let content = Content {
+            data_message: Some(DataMessage {
+                flags: Some(data_message::Flags::ExpirationTimerUpdate as u32),
+                expire_timer: Some(seconds),
+                timestamp: Some(timestamp),
+                profile_key: self.state.profile_key()?.map(Vec::from),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        self.send_queued(recipient, &content, timestamp, None).await
+    }
+
+    fn save_expire_timer(&self, recipient: &str, seconds: u32) -> Result<()> {
+        let mut contact = match self.state.find_contact(recipient)? {
+            Some(contact) => contact,
+            None if recipient.starts_with('+') => Contact {
+                number: Some(recipient.to_string()),
+                ..Default::default()
+            },
+            None => Contact {
+                uuid: Some(recipient.to_string()),
+                ..Default::default()
+            },
+        };
+        contact.expire_timer = Some(seconds);
+        self.state.save_contact(&contact)?;
+        Ok(())
+    }
+
+    /// Removes messages with elapsed disappearing timer from the local history.
+    /// Messages of conversations without the timer are kept.
+    pub fn purge_expired_messages(&self) -> Result<()> {
+        let count = self.state.purge_expired_messages(timestamp_millis())?;
+        if count > 0 {
+            info!(count, "purged expired messages");
+        }
+        Ok(())
+    }
+
+    /// Asks the primary device to send us contacts, groups, blocked list and configuration.
+    /// Keys are not requested, they carry only the storage service key and this client
+    /// doesn't use the storage service.
+    pub async fn request_sync(&self) -> Result<()> {
+        let own_uuid = self.state.address()?.name().to_string();
+        for request_type in [
+            RequestType::Contacts,
+            RequestType::Groups,
+            RequestType::Blocked,
+            RequestType::Configuration,
+        ] {
+            let content = Content {
+                sync_message: Some(SyncMessage {
+                    request: Some(sync_message::Request {
+                        r#type: Some(request_type as i32),
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            self.send_content(&own_uuid, &content, timestamp_millis())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Blocks or unblocks the contact given by UUID, phone number or name
+    /// and syncs the block list to our other devices.
+    pub async fn set_contact_blocked(&self, recipient: &str, blocked: bool) -> Result<()> {
+        let identifier = self.state.resolve_recipient(recipient)?;
+        let contact = self.state.find_contact(&identifier)?.unwrap_or_else(|| {
+            if identifier.starts_with('+') {
+                Contact {
+                    number: Some(identifier.clone()),
+                    ..Default::default()
+                }
+            } else {
+                Contact {
+                    uuid: Some(identifier.clone()),
+                    ..Default::default()
+                }
+            }
+        });
+
+        let mut list = self.state.blocked_list()?;
+        if blocked {
+            list.add(contact.uuid.as_deref(), contact.number.as_deref());
+        } else {
+            list.remove(contact.uuid.as_deref(), contact.number.as_deref());
+        }
+        self.state.set_blocked_list(&list)?;
+        self.sync_blocked_list().await
+    }
+
+    pub async fn set_group_blocked(&self, group_id: &[u8], blocked: bool) -> Result<()> {
+        let mut list = self.state.blocked_list()?;
+        if blocked {
+            list.add_group(group_id);
+        } else {
+            list.remove_group(group_id);
+        }
+        self.state.set_blocked_list(&list)?;
+        self.sync_blocked_list().await
+    }
+
+    async fn sync_blocked_list(&self) -> Result<()> {
+        let own_uuid = self.state.address()?.name().to_string();
+        let content = Content {
+            sync_message: Some(SyncMessage {
+                blocked: Some((&self.state.blocked_list()?).into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        self.send_content(&own_uuid, &content, timestamp_millis())
+            .await
+    }
+
+    /// Queues the message in the outbox and delivers what is queued for the recipient.
+    /// Message which failed to be delivered for a temporary reason stays queued and
+    /// is retried later; only permanent failures are returned. The history message
+    /// is saved once the message is delivered.
+    async fn send_queued(
+        &self,
+        recipient: &str,
+        content: &Content,
+        timestamp: u64,
+        history: Option<StoredMessage>,
+    ) -> Result<SendOutcome> {
+        let recipient = &self.state.resolve_recipient(recipient)?;
+        let entry =
+            self.state
+                .queue_message(recipient, content.encode_to_vec(), timestamp, history)?;
+
+        let mut failures = self.deliver_queued(Some(recipient), false).await?;
+        let failure = failures.remove(&entry.id);
+        for (id, err) in failures {
+            warn!(id, error = %err, "dropped undeliverable queued message");
+        }
+        if let Some(err) = failure {
+            return Err(err);
+        }
+        match self.state.queued_message(recipient, entry.id)? {
+            Some(entry) => {
+                warn!(
+                    id = entry.id,
+                    reason = entry
+                        .last_error
+                        .as_deref()
+                        .unwrap_or("earlier messages are pending"),
+                    "message queued for retry"
+                );
+                Ok(SendOutcome::Queued { id: entry.id })
+            }
+            None => Ok(SendOutcome::Sent),
+        }
+    }
+
+    /// Delivers queued messages whose retry is due, or all of them if `force` is set.
+    /// Returns the number of messages still queued.
+    pub async fn flush_outbox(&self, force: bool) -> Result<usize> {
+        for (id, err) in self.deliver_queued(None, force).await? {
+            warn!(id, error = %err, "dropped undeliverable queued message");
+        }
+        Ok(self.state.outbox(None)?.len())
+    }
+
+    /// Attempts delivery of queued messages in order. Messages to the same recipient
+    /// are never delivered out of order, so the first message which is not due yet
+    /// or fails again holds back those queued after it. Messages which failed
+    /// permanently, or too many times, are removed and their errors returned by their ids.
+    async fn deliver_queued(
+        &self,
+        recipient: Option<&str>,
+        force: bool,
+    ) -> Result<HashMap<u64, Error>> {
+        let now = timestamp_millis();
+        let mut failures = HashMap::new();
+        let mut held_back: Option<String> = None;
+
+        for mut entry in self.state.outbox(recipient)? {
+            if held_back.as_deref() == Some(entry.recipient.as_str()) {
+                continue;
+            }
+            if !force && entry.next_attempt > now {
+                held_back = Some(entry.recipient);
+                continue;
+            }
+
+            let content = Content::decode(&*entry.content)?;
+            match self
+                .send_content(&entry.recipient, &content, entry.timestamp)
+                .await
+            {
+                Ok(()) => {
+                    if let Some(history) = &entry.history {
+                        self.state.save_message(history)?;
+                    }
+                    self.state.remove_queued_message(&entry)?;
+                }
+                Err(err) => match retry_delay(&err, entry.attempts)
+                    .filter(|_| !gives_up(entry.attempts, entry.timestamp, now))
+                {
+                    Some(delay) => {
+                        entry.attempts += 1;
+                        entry.next_attempt = now + delay.as_millis() as u64;
+                        entry.last_error = Some(err.to_string());
+                        self.state.save_queued_message(&entry)?;
+                        held_back = Some(entry.recipient);
+                    }
+                    None => {
+                        self.state.remove_queued_message(&entry)?;
+                        failures.insert(entry.id, err);
+                    }
+                },
+            }
+        }
+        Ok(failures)
+    }
+
+    #[instrument(skip(self, content))]
+    async fn send_content(&self, recipient: &str, content: &Content, timestamp: u64) -> Result<()> {
+        let recipient = &self.state.resolve_recipient(recipient)?;
+        let plaintext = add_padding(content.encode_to_vec());
+        for _ in 0..MAX_SEND_ATTEMPTS {
             let addrs = self.load_or_create_sessions(recipient).await?;
 
             let mut send_metadata = Vec::with_capacity(addrs.len());
@@ -169,14 +949,21 @@ impl<'r, R: Rng + CryptoRng + Clone> AccountManager<'r, R> {
                 let mut session_store = self.state.session_store.clone();
                 let mut identity_store = self.state.identity_store.clone();
 
-                let ciphertext_message = message_encrypt(
-                    message.as_bytes(),
+                let ciphertext_message = match message_encrypt(
+                    &plaintext,
                     &addr,
                     &mut session_store,
                     &mut identity_store,
                     None,
                 )
-                .await?;
+                .await
+                {
+                    Ok(message) => message,
+                    Err(SignalProtocolError::UntrustedIdentity(address)) => {
+                        return Err(self.untrusted_identity(address).await?);
+                    }
+                    Err(err) => return Err(err.into()),
+                };
 
                 send_metadata.push(SendMetadata::new(
                     ciphertext_message,
@@ -185,7 +972,7 @@ impl<'r, R: Rng + CryptoRng + Clone> AccountManager<'r, R> {
                 ));
             }
 
-            let body = MessagesWrapper::new(send_metadata);
+            let body = MessagesWrapper::new(send_metadata, timestamp);
 
             let response_result = self
                 .http_client
@@ -204,35 +991,1089 @@ impl<'r, R: Rng + CryptoRng + Clone> AccountManager<'r, R> {
                         self.create_sessions(recipient, Some(device_id)).await?;
                     }
                     for device_id in extra_devices {
-                        let addr = ProtocolAddress::new(recipient.to_string(), device_id);
-                        let mut session = self
-                            .state
-                            .load_session(&addr, None)
-                            .await?
-                            .expect("Extra session should be still present.");
                         // TODO: is archiving enough? Shouldn't we delete it?
-                        session.archive_current_state()?;
-
-                        // Clone is cheap, since our store is just a wrapped Arc.
-                        // This way we don't require &mut self and &self is enough.
-                        self.state
-                            .session_store
-                            .clone()
-                            .store_session(&addr, &session, None)
-                            .await?;
+                        self.archive_session(recipient, device_id).await?;
                     }
                     continue;
                 }
+                Err(Error::HttpError(status_code, value)) if status_code == 410 => {
+                    let MessageResponse410 { stale_devices } = serde_json::from_str(&value)?;
+
+                    for device_id in stale_devices {
+                        self.archive_session(recipient, device_id).await?;
+                        self.create_sessions(recipient, Some(device_id)).await?;
+                    }
+                    continue;
+                }
+                Err(Error::HttpError(status_code, _)) if status_code == 404 => {
+                    return Err(Error::UnregisteredRecipient(recipient.to_string()));
+                }
                 Err(err) => {
                     return Err(err);
                 }
             };
 
-            eprintln!("{:?}", response);
+            debug!(needs_sync = response.needs_sync, "message sent");
+
+            // TODO: send sent transcript to our other devices
+
+            return Ok(());
+        }
+        Err(Error::TooManySendAttempts(recipient.to_string()))
+    }
+
+    #[instrument(skip(self))]
+    async fn archive_session(&self, recipient: &str, device_id: DeviceId) -> Result<()> {
+        let addr = ProtocolAddress::new(recipient.to_string(), device_id);
+        if let Some(mut session) = self.state.load_session(&addr, None).await? {
+            session.archive_current_state()?;
+
+            // Clone is cheap, since our store is just a wrapped Arc.
+            // This way we don't require &mut self and &self is enough.
+            self.state
+                .session_store
+                .clone()
+                .store_session(&addr, &session, None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Error for the address whose session carries identity key we don't trust.
+    async fn untrusted_identity(&self, address: ProtocolAddress) -> Result<Error> {
+        let identity_key = self
+            .state
+            .load_session(&address, None)
+            .await?
+            .map(|session| session.remote_identity_key_bytes())
+            .transpose()?
+            .flatten();
+        match identity_key {
+            Some(bytes) => Ok(Error::UntrustedIdentity {
+                address,
+                identity_key: IdentityKey::try_from(&*bytes)?,
+            }),
+            None => Ok(SignalProtocolError::UntrustedIdentity(address).into()),
+        }
+    }
+
+    /// Accepts the changed identity key of the recipient and starts new sessions with it.
+    /// The key currently published by the recipient is trusted, but only if it is
+    /// the `expected` one when given. Returns the trusted key.
+    pub async fn trust_identity(
+        &self,
+        recipient: &str,
+        expected: Option<&IdentityKey>,
+    ) -> Result<IdentityKey> {
+        let recipient = &self.state.resolve_recipient(recipient)?;
+        let bundles = self.fetch_pre_key_bundles(recipient, None).await?;
+        let identity_key = match bundles.first() {
+            Some(bundle) => *bundle.identity_key()?,
+            None => return Err(Error::UnregisteredRecipient(recipient.to_string())),
+        };
+        if expected.is_some() && expected != Some(&identity_key) {
+            return Err(Error::IdentityKeyMismatch(recipient.to_string()));
+        }
+
+        let devices = bundles
+            .iter()
+            .map(|bundle| bundle.device_id())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        self.state
+            .trust_identity(recipient, &devices, &identity_key)?;
+        self.process_pre_key_bundles(recipient, bundles).await?;
+        info!(%recipient, "trusted new identity key");
+        Ok(identity_key)
+    }
+
+    /// Takes messages off the server into the inbox and processes them, including those
+    /// which failed before. Returned messages stay in the inbox until acknowledged by
+    /// [`Self::acknowledge_received`], while failed ones are kept for a retry.
+    #[instrument(skip(self))]
+    pub async fn receive_messages(&self) -> Result<Vec<ReceivedMessage>> {
+        self.purge_expired_messages()?;
+        self.fetch_messages().await?;
+        self.process_inbox(false).await
+    }
+
+    /// Server deletes envelopes once acknowledged, so they are acknowledged only after
+    /// being stored in the inbox.
+    async fn fetch_messages(&self) -> Result<()> {
+        loop {
+            let response: IncomingMessageList = self
+                .http_client
+                .send(Method::GET, ApiPath::Messages)
+                .await?
+                .json()
+                .await?;
+
+            for entity in response.messages {
+                let guid = entity.guid.clone();
+                let envelope = Envelope::from(entity);
+                self.state
+                    .push_inbox_entry(&guid, envelope.encode_to_vec())?;
+                self.http_client
+                    .send(Method::DELETE, ApiPath::AcknowledgeMessage { guid: &guid })
+                    .await?;
+            }
+
+            if !response.more {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Processes messages of the inbox in the order they were received. Messages which
+    /// failed [`MAX_INBOX_ATTEMPTS`] times are skipped unless `retry_failed` is set.
+    pub(crate) async fn process_inbox(&self, retry_failed: bool) -> Result<Vec<ReceivedMessage>> {
+        let mut received = Vec::new();
+        for mut entry in self.state.inbox()? {
+            if entry.attempts >= MAX_INBOX_ATTEMPTS && !retry_failed {
+                continue;
+            }
+            match self.process_inbox_entry(&mut entry).await {
+                Ok(Some(message)) => received.push(message),
+                Ok(None) => {
+                    self.state.remove_inbox_entry(entry.id)?;
+                }
+                Err(err) => {
+                    entry.attempts += 1;
+                    entry.last_error = Some(err.to_string());
+                    self.state.save_inbox_entry(&entry)?;
+                    if entry.attempts >= MAX_INBOX_ATTEMPTS {
+                        warn!(
+                            id = entry.id,
+                            guid = %entry.guid,
+                            attempts = entry.attempts,
+                            error = %err,
+                            "giving up on message, it is kept in the inbox"
+                        );
+                    } else {
+                        warn!(
+                            id = entry.id,
+                            guid = %entry.guid,
+                            error = %err,
+                            "failed to process message"
+                        );
+                    }
+                }
+            }
+        }
+        Ok(received)
+    }
+
+    /// Decrypts and processes the entry. Returns `None` for entries without anything
+    /// for the user, which can be removed from the inbox.
+    async fn process_inbox_entry(&self, entry: &mut InboxEntry) -> Result<Option<ReceivedMessage>> {
+        let message = match &entry.message {
+            Some(message) => ReceivedMessage {
+                id: entry.id,
+                sender: ProtocolAddress::new(message.sender.clone(), message.sender_device.into()),
+                sender_name: message.sender_name.clone(),
+                timestamp: message.timestamp,
+                content: Content::decode(message.content.as_slice())?,
+            },
+            None => {
+                let envelope = Envelope::decode(entry.envelope.as_deref().unwrap_or_default())?;
+                let message = match self.decrypt_envelope(entry.id, envelope).await? {
+                    Some(message) => message,
+                    None => return Ok(None),
+                };
+                // Session state already moved on, so the envelope can't be decrypted again
+                entry.envelope = None;
+                entry.message = Some(InboxMessage {
+                    sender: message.sender.name().to_string(),
+                    sender_device: message.sender.device_id().into(),
+                    sender_name: message.sender_name.clone(),
+                    timestamp: message.timestamp,
+                    content: message.content.encode_to_vec(),
+                });
+                self.state.save_inbox_entry(entry)?;
+                message
+            }
+        };
+
+        if !entry.processed {
+            if self.is_blocked_sender(&message)? {
+                return Ok(None);
+            }
+            self.process_message(&message).await?;
+            entry.processed = true;
+            self.state.save_inbox_entry(entry)?;
+        }
+        Ok(Some(message))
+    }
+
+    /// Removes the message handed over by [`Self::receive_messages`] from the inbox.
+    pub(crate) fn acknowledge_received(&self, message: &ReceivedMessage) -> Result<()> {
+        self.state.remove_inbox_entry(message.id)?;
+        Ok(())
+    }
+
+    async fn decrypt_envelope(
+        &self,
+        id: u64,
+        envelope: Envelope,
+    ) -> Result<Option<ReceivedMessage>> {
+        let ciphertext = match envelope.content.as_deref() {
+            Some(ciphertext) => ciphertext,
+            // Server delivery receipts don't carry any content
+            None => return Ok(None),
+        };
+
+        // Clone is cheap, since our store is just a wrapped Arc.
+        let mut session_store = self.state.session_store.clone();
+        let mut identity_store = self.state.identity_store.clone();
+        let mut pre_key_store = self.state.pre_key_store.clone();
+        let mut signed_pre_key_store = self.state.signed_pre_key_store.clone();
+        let mut csprng = self.csprng.clone();
+
+        let (sender, sender_e164, plaintext) = match envelope.r#type() {
+            EnvelopeType::Ciphertext => {
+                let sender = Self::envelope_sender(&envelope)?;
+                let message = SignalMessage::try_from(ciphertext)?;
+                let plaintext = message_decrypt_signal(
+                    &message,
+                    &sender,
+                    &mut session_store,
+                    &mut identity_store,
+                    &mut csprng,
+                    None,
+                )
+                .await?;
+                (sender, envelope.source.clone(), plaintext)
+            }
+            EnvelopeType::PrekeyBundle => {
+                let sender = Self::envelope_sender(&envelope)?;
+                let message = PreKeySignalMessage::try_from(ciphertext)?;
+                let plaintext = message_decrypt_prekey(
+                    &message,
+                    &sender,
+                    &mut session_store,
+                    &mut identity_store,
+                    &mut pre_key_store,
+                    &mut signed_pre_key_store,
+                    &mut csprng,
+                    None,
+                )
+                .await?;
+                (sender, envelope.source.clone(), plaintext)
+            }
+            EnvelopeType::UnidentifiedSender => {
+                let address = self.state.address()?;
+                let result = sealed_sender_decrypt(
+                    ciphertext,
+                    &self.trust_root,
+                    envelope.timestamp(),
+                    self.state.number()?,
+                    address.name().to_string(),
+                    address.device_id(),
+                    &mut identity_store,
+                    &mut session_store,
+                    &mut pre_key_store,
+                    &mut signed_pre_key_store,
+                    None,
+                )
+                .await?;
+                let sender = ProtocolAddress::new(result.sender_uuid, result.device_id);
+                (sender, result.sender_e164, result.message)
+            }
+            other => {
+                warn!(envelope_type = ?other, "skipping envelope of unsupported type");
+                return Ok(None);
+            }
+        };
+
+        let contact = self.state.save_contact(&Contact {
+            uuid: Some(sender.name().to_string()),
+            number: sender_e164,
+            ..Default::default()
+        })?;
+        let content = Content::decode(strip_padding(&plaintext))?;
+
+        Ok(Some(ReceivedMessage {
+            id,
+            sender,
+            sender_name: contact.display_name().to_string(),
+            timestamp: envelope.timestamp(),
+            content,
+        }))
+    }
+
+    fn envelope_sender(envelope: &Envelope) -> Result<ProtocolAddress> {
+        let name = envelope
+            .source_uuid
+            .clone()
+            .ok_or_else(|| Error::InvalidEnvelope(String::from("Missing source of envelope")))?;
+        Ok(ProtocolAddress::new(name, envelope.source_device().into()))
+    }
+
+    /// Messages from blocked contacts and groups are dropped without any notice.
+    fn is_blocked_sender(&self, message: &ReceivedMessage) -> Result<bool> {
+        if self.state.is_blocked(message.sender.name())? {
+            return Ok(true);
+        }
+        let group_id = match &message.content.data_message {
+            Some(data_message) => group_id(data_message)?,
+            None => None,
+        };
+        match group_id {
+            Some(group_id) => Ok(self.state.blocked_list()?.contains_group(&group_id)),
+            None => Ok(false),
+        }
+    }
+
+    async fn process_message(&self, message: &ReceivedMessage) -> Result<()> {
+        let own_message = message.sender.name() == self.state.address()?.name();
+        if let Some(data_message) = &message.content.data_message {
+            if !own_message {
+                self.process_data_message(message.sender.name(), data_message)
+                    .await?;
+            }
+        }
+        if let Some(sync_message) = &message.content.sync_message {
+            // Only our own devices are allowed to change our state
+            if own_message {
+                self.process_sync_message(sync_message).await?;
+            } else {
+                warn!(sender = %message.sender_name, "ignoring sync message");
+            }
+        }
+        Ok(())
+    }
+
+    #[instrument(
+        skip(self, data_message),
+        fields(timestamp = data_message.timestamp, body = ?Secret(&data_message.body))
+    )]
+    async fn process_data_message(&self, sender: &str, data_message: &DataMessage) -> Result<()> {
+        if let Some(profile_key) = &data_message.profile_key {
+            self.update_profile_key(sender, profile_key).await?;
+        }
+
+        // Timer of group conversation is the group's, the one with the sender stays as is
+        let conversation = match group_id(data_message)? {
+            Some(group_id) => STANDARD.encode(group_id),
+            None => {
+                // Other side changed the timer, either explicitly or from another of its devices
+                let timer = data_message.expire_timer();
+                let current = self
+                    .state
+                    .find_contact(sender)?
+                    .and_then(|contact| contact.expire_timer)
+                    .unwrap_or(0);
+                if is_expire_timer_update(data_message) || timer != current {
+                    self.save_expire_timer(sender, timer)?;
+                }
+                sender.to_string()
+            }
+        };
+
+        // View-once media is held apart from the history, until it is opened
+        let attachments = if data_message.is_view_once() {
+            if let Some(pointer) = data_message.attachments.first() {
+                self.state.save_view_once_media(&ViewOnceMedia {
+                    sender: sender.to_string(),
+                    timestamp: data_message.timestamp(),
+                    content_type: pointer.content_type.clone(),
+                    file_name: pointer.file_name.clone(),
+                    data: self.download_attachment(pointer).await?,
+                })?;
+            }
+            &[][..]
+        } else {
+            &data_message.attachments[..]
+        };
+        let sticker = data_message
+            .sticker
+            .as_ref()
+            .and_then(|sticker| sticker.data.as_ref());
+        if data_message.body.is_some() || !attachments.is_empty() || sticker.is_some() {
+            self.state.save_message(&StoredMessage {
+                conversation,
+                author: sender.to_string(),
+                timestamp: data_message.timestamp(),
+                body: data_message.body.clone(),
+                body_ranges: data_message.body_ranges.iter().map(Into::into).collect(),
+                attachments: attachments.iter().chain(sticker).map(Into::into).collect(),
+                expires_at: expires_at(data_message.expire_timer, timestamp_millis()),
+            })?;
+        }
+        Ok(())
+    }
+
+    async fn process_sync_message(&self, sync_message: &SyncMessage) -> Result<()> {
+        if let Some(sent) = &sync_message.sent {
+            let destination = sent.destination_uuid.as_ref().or(sent.destination.as_ref());
+            if let (Some(destination), Some(message)) = (destination, &sent.message) {
+                if is_expire_timer_update(message) {
+                    self.save_expire_timer(destination, message.expire_timer())?;
+                }
+            }
+        }
+        if let Some(blob) = sync_message
+            .contacts
+            .as_ref()
+            .and_then(|contacts| contacts.blob.as_ref())
+        {
+            // Sync carries all contacts, so blocked contacts are replaced as a whole
+            // to also drop those unblocked on the primary device. Groups are kept.
+            let mut blocked = self.state.blocked_list()?;
+            blocked.uuids.clear();
+            blocked.numbers.clear();
+            for details in read_contact_details(&self.download_attachment(blob).await?)? {
+                if details.uuid.is_none() && details.number.is_none() {
+                    continue;
+                }
+                if details.blocked() {
+                    blocked.add(details.uuid.as_deref(), details.number.as_deref());
+                }
+                self.state.save_contact(&details.into())?;
+            }
+            self.state.set_blocked_list(&blocked)?;
+            info!("synced contacts");
+        }
+        if let Some(blob) = sync_message
+            .groups
+            .as_ref()
+            .and_then(|groups| groups.blob.as_ref())
+        {
+            for details in read_group_details(&self.download_attachment(blob).await?)? {
+                if details.id.is_some() {
+                    self.state.save_group(&details.into())?;
+                }
+            }
+            info!("synced groups");
+        }
+        if let Some(blocked) = &sync_message.blocked {
+            self.state.set_blocked_list(&blocked.clone().into())?;
+            info!("synced blocked contacts and groups");
+        }
+        if let Some(configuration) = &sync_message.configuration {
+            let mut settings = self.state.settings()?;
+            settings.update(configuration);
+            self.state.set_settings(&settings)?;
+            info!("synced configuration");
+        }
+        if let Some(open) = &sync_message.view_once_open {
+            let sender = open.sender_uuid.as_ref().or(open.sender.as_ref());
+            if let Some(sender) = sender {
+                let sender = self.state.resolve_recipient(sender)?;
+                if self
+                    .state
+                    .remove_view_once_media(&sender, open.timestamp())?
+                {
+                    info!("view-once message was opened on another device");
+                }
+            }
+        }
+        for operation in &sync_message.sticker_pack_operation {
+            if let Err(err) = self.process_sticker_pack_operation(operation).await {
+                warn!(error = %err, "failed to sync sticker pack");
+            }
+        }
+        Ok(())
+    }
+
+    /// Profile key of our account. Primary device generates it at registration. Linked
+    /// devices may not know it and must not make up their own, since the key is shared
+    /// by the whole account. Messages are sent without it then.
+    fn local_profile_key(&self) -> Result<[u8; 32]> {
+        self.state.profile_key()?.ok_or(Error::NotPrimaryDevice)
+    }
+
+    /// Encrypts and uploads new version of our profile. Fields which are not given
+    /// keep values of the previously uploaded profile, empty ones are cleared.
+    pub async fn set_profile(
+        &self,
+        given_name: Option<&str>,
+        family_name: Option<&str>,
+        about: Option<&str>,
+        avatar: Option<&[u8]>,
+    ) -> Result<()> {
+        let profile_key = self.local_profile_key()?;
+        let uuid = Uuid::parse_str(self.state.address()?.name())?;
+        let mut csprng = self.csprng.clone();
+
+        // Other devices may have changed the profile since this one last uploaded it
+        let mut profile = match self.fetch_versioned_profile(&uuid, &profile_key).await {
+            Ok(profile) => profile,
+            Err(Error::HttpError(status, _)) if status == StatusCode::NOT_FOUND => {
+                Profile::default()
+            }
+            Err(err) => return Err(err),
+        };
+        if let Some(given_name) = given_name {
+            profile.given_name = Some(given_name.to_string()).filter(|name| !name.is_empty());
+        }
+        if let Some(family_name) = family_name {
+            profile.family_name = Some(family_name.to_string()).filter(|name| !name.is_empty());
+        }
+        if let Some(about) = about {
+            profile.about = Some(about.to_string()).filter(|about| !about.is_empty());
+        }
+        if let Some(avatar) = avatar {
+            profile.avatar = Some(avatar.to_vec());
+        }
+        // Name is encrypted as given and family name separated by zero byte
+        let full_name = match (&profile.given_name, &profile.family_name) {
+            (Some(given), Some(family)) => Some(format!("{}\0{}", given, family)),
+            (given, _) => given.clone(),
+        };
+
+        let encrypt = |value: &Option<String>, padding: &[usize], csprng: &mut R| {
+            value
+                .as_deref()
+                .map(|value| encrypt_profile_string(&profile_key, value, padding, csprng))
+                .transpose()
+        };
+        let request = ProfileWriteRequest {
+            version: profile_key_version(&profile_key, &uuid)?,
+            name: encrypt(&full_name, NAME_PADDING, &mut csprng)?,
+            about: encrypt(&profile.about, ABOUT_PADDING, &mut csprng)?,
+            about_emoji: encrypt(&profile.about_emoji, EMOJI_PADDING, &mut csprng)?,
+            payment_address: None,
+            avatar: avatar.is_some(),
+            same_avatar: avatar.is_none(),
+            commitment: profile_key_commitment(&profile_key, &uuid)?,
+            badge_ids: Vec::new(),
+        };
+        let response = self
+            .http_client
+            .send_json(Method::PUT, ApiPath::SetProfile, &request)
+            .await?;
+
+        if let Some(avatar) = avatar {
+            let form: AvatarUploadAttributes = response.json().await?;
+            let encrypted = encrypt_profile_data(&profile_key, avatar, &mut csprng)?;
+            let fields = [
+                ("acl", form.acl.as_str()),
+                ("key", form.key.as_str()),
+                ("policy", form.policy.as_str()),
+                ("Content-Type", "application/octet-stream"),
+                ("x-amz-algorithm", form.algorithm.as_str()),
+                ("x-amz-credential", form.credential.as_str()),
+                ("x-amz-date", form.date.as_str()),
+                ("x-amz-signature", form.signature.as_str()),
+            ];
+            HttpClient::cdn(&self.api_config, 0)?
+                .send_multipart(
+                    Method::POST,
+                    ApiPath::ProfileAvatarUpload,
+                    &fields,
+                    &encrypted,
+                )
+                .await?;
+        }
 
-            // TODO: send sync message
+        profile.fetched = timestamp_millis();
+        self.state.save_profile(&uuid.to_string(), &profile)?;
+        Ok(())
+    }
 
+    /// Remembers the profile key of the contact and refreshes its profile, when the key changed.
+    async fn update_profile_key(&self, uuid: &str, profile_key: &[u8]) -> Result<()> {
+        let known = self
+            .state
+            .find_contact(uuid)?
+            .and_then(|contact| contact.profile_key);
+        if known.as_deref() == Some(profile_key) {
             return Ok(());
         }
+        self.state.save_contact(&Contact {
+            uuid: Some(uuid.to_string()),
+            profile_key: Some(profile_key.to_vec()),
+            ..Default::default()
+        })?;
+        if let Err(err) = self.fetch_profile(uuid).await {
+            warn!(%uuid, error = %err, "failed to fetch profile");
+        }
+        Ok(())
+    }
+
+    /// Fetches the versioned profile of the contact, decrypts it with the known profile key
+    /// and caches it in the store.
+    pub async fn fetch_profile(&self, recipient: &str) -> Result<Profile> {
+        let contact = self
+            .state
+            .find_contact(recipient)?
+            .ok_or_else(|| Error::UnknownContact(recipient.to_string()))?;
+        let uuid = contact
+            .uuid
+            .as_deref()
+            .and_then(|uuid| Uuid::parse_str(uuid).ok())
+            .ok_or_else(|| Error::InvalidContact(format!("UUID of {} is unknown", recipient)))?;
+        let profile_key = contact
+            .profile_key
+            .as_deref()
+            .ok_or_else(|| Error::MissingProfileKey(recipient.to_string()))?;
+        self.fetch_versioned_profile(&uuid, profile_key).await
     }
+
+    async fn fetch_versioned_profile(&self, uuid: &Uuid, profile_key: &[u8]) -> Result<Profile> {
+        let version = profile_key_version(profile_key, uuid)?;
+        let uuid = uuid.to_string();
+        let response: ProfileResponse = self
+            .http_client
+            .send(
+                Method::GET,
+                ApiPath::Profile {
+                    uuid: &uuid,
+                    version: &version,
+                },
+            )
+            .await?
+            .json()
+            .await?;
+
+        let decrypt = |field: &Option<Vec<u8>>| {
+            field
+                .as_deref()
+                .map(|data| decrypt_profile_string(profile_key, data))
+                .transpose()
+                .map(|value| value.filter(|value| !value.is_empty()))
+        };
+        // Name is encrypted as given and family name separated by zero byte
+        let (given_name, family_name) = match decrypt(&response.name)? {
+            Some(name) => match name.split_once('\0') {
+                Some((given, family)) => (
+                    Some(given.to_string()).filter(|name| !name.is_empty()),
+                    Some(family.to_string()).filter(|name| !name.is_empty()),
+                ),
+                None => (Some(name), None),
+            },
+            None => (None, None),
+        };
+        let avatar = match response.avatar.as_deref().filter(|path| !path.is_empty()) {
+            Some(path) => {
+                let data = HttpClient::cdn(&self.api_config, 0)?
+                    .send(Method::GET, ApiPath::ProfileAvatar { path })
+                    .await?
+                    .data()
+                    .await?;
+                Some(decrypt_profile_data(profile_key, &data)?)
+            }
+            None => None,
+        };
+
+        let profile = Profile {
+            given_name,
+            family_name,
+            about: decrypt(&response.about)?,
+            about_emoji: decrypt(&response.about_emoji)?,
+            avatar,
+            fetched: timestamp_millis(),
+        };
+        self.state.save_profile(&uuid, &profile)?;
+        Ok(profile)
+    }
+
+    async fn download_attachment(&self, pointer: &AttachmentPointer) -> Result<Vec<u8>> {
+        let identifier = match &pointer.attachment_identifier {
+            Some(AttachmentIdentifier::CdnId(id)) => id.to_string(),
+            Some(AttachmentIdentifier::CdnKey(key)) => key.clone(),
+            None => {
+                return Err(Error::AttachmentError(String::from(
+                    "Missing attachment identifier",
+                )))
+            }
+        };
+        let data = HttpClient::cdn(&self.api_config, pointer.cdn_number())?
+            .send(
+                Method::GET,
+                ApiPath::Attachment {
+                    identifier: &identifier,
+                },
+            )
+            .await?
+            .data()
+            .await?;
+        decrypt_attachment(pointer, &data)
+    }
+
+    /// Unopened view-once media. It is kept until `view_once_opened` is called,
+    /// so it isn't lost when it can't be shown.
+    pub fn unopened_view_once(&self, sender: &str, timestamp: u64) -> Result<ViewOnceMedia> {
+        let sender = self.state.resolve_recipient(sender)?;
+        self.state
+            .view_once_media_of(&sender, timestamp)?
+            .ok_or_else(|| {
+                Error::InvalidViewOnce(format!(
+                    "No unopened view-once message from {} sent at {}",
+                    sender, timestamp
+                ))
+            })
+    }
+
+    /// Deletes the opened view-once media and tells our other devices, so they delete it
+    /// as well. Media is deleted first, so it can't be opened again when the sync fails;
+    /// the sync is queued and retried then.
+    pub async fn view_once_opened(&self, media: &ViewOnceMedia) -> Result<SendOutcome> {
+        let sender = media.sender.clone();
+        let timestamp = media.timestamp;
+        self.state.remove_view_once_media(&sender, timestamp)?;
+
+        let contact = self.state.find_contact(&sender)?;
+        let own_uuid = self.state.address()?.name().to_string();
+        let content = Content {
+            sync_message: Some(SyncMessage {
+                view_once_open: Some(sync_message::ViewOnceOpen {
+                    sender: contact.as_ref().and_then(|contact| contact.number.clone()),
+                    sender_uuid: contact
+                        .and_then(|contact| contact.uuid)
+                        .or_else(|| Some(sender).filter(|sender| !sender.starts_with('+'))),
+                    timestamp: Some(timestamp),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        self.send_queued(&own_uuid, &content, timestamp_millis(), None)
+            .await
+    }
+
+    /// Installs the sticker pack given by hex encoded id and key
+    /// and tells our other devices to install it too.
+    pub async fn install_sticker_pack(
+        &self,
+        pack_id: &str,
+        pack_key: &str,
+    ) -> Result<StoredStickerPack> {
+        let pack_id = decode_hex(pack_id)?;
+        let pack_key = decode_hex(pack_key)?;
+        let pack = self.download_sticker_pack(&pack_id, &pack_key).await?;
+        self.sync_sticker_pack_operation(pack_id, pack_key, StickerPackOperationType::Install)
+            .await?;
+        Ok(pack)
+    }
+
+    /// Removes the installed sticker pack here and on our other devices.
+    pub async fn remove_sticker_pack(&self, pack_id: &str) -> Result<()> {
+        let pack = self
+            .state
+            .sticker_pack(&pack_id.to_lowercase())?
+            .ok_or_else(|| Error::StickerError(format!("Pack {} is not installed", pack_id)))?;
+        self.state.remove_sticker_pack(&pack.id)?;
+        self.sync_sticker_pack_operation(
+            decode_hex(&pack.id)?,
+            decode_hex(&pack.key)?,
+            StickerPackOperationType::Remove,
+        )
+        .await
+    }
+
+    async fn sync_sticker_pack_operation(
+        &self,
+        pack_id: Vec<u8>,
+        pack_key: Vec<u8>,
+        operation: StickerPackOperationType,
+    ) -> Result<()> {
+        let own_uuid = self.state.address()?.name().to_string();
+        let content = Content {
+            sync_message: Some(SyncMessage {
+                sticker_pack_operation: vec![sync_message::StickerPackOperation {
+                    pack_id: Some(pack_id),
+                    pack_key: Some(pack_key),
+                    r#type: Some(operation as i32),
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        self.send_content(&own_uuid, &content, timestamp_millis())
+            .await
+    }
+
+    /// Downloads and decrypts the manifest and stickers of the pack and stores them.
+    async fn download_sticker_pack(
+        &self,
+        pack_id: &[u8],
+        pack_key: &[u8],
+    ) -> Result<StoredStickerPack> {
+        let pack_id = encode_hex(pack_id);
+        let cdn = HttpClient::cdn(&self.api_config, 0)?;
+        let data = cdn
+            .send(Method::GET, ApiPath::StickerManifest { pack_id: &pack_id })
+            .await?
+            .data()
+            .await?;
+        let manifest = StickerPack::decode(&*decrypt_sticker_data(pack_key, &data)?)?;
+
+        // Cover is usually one of the stickers, but it doesn't have to be
+        let mut sticker_ids = manifest
+            .stickers
+            .iter()
+            .chain(&manifest.cover)
+            .map(|sticker| sticker.id())
+            .collect::<Vec<_>>();
+        sticker_ids.sort_unstable();
+        sticker_ids.dedup();
+
+        let mut images = Vec::with_capacity(sticker_ids.len());
+        for sticker_id in sticker_ids {
+            let data = cdn
+                .send(
+                    Method::GET,
+                    ApiPath::Sticker {
+                        pack_id: &pack_id,
+                        sticker_id,
+                    },
+                )
+                .await?
+                .data()
+                .await?;
+            images.push((sticker_id, decrypt_sticker_data(pack_key, &data)?));
+        }
+
+        let pack = StoredStickerPack::new(pack_id, encode_hex(pack_key), &manifest);
+        self.state.save_sticker_pack(&pack, &images)?;
+        Ok(pack)
+    }
+
+    /// Applies pack installed or removed on another of our devices.
+    async fn process_sticker_pack_operation(
+        &self,
+        operation: &sync_message::StickerPackOperation,
+    ) -> Result<()> {
+        let pack_id = match &operation.pack_id {
+            Some(pack_id) => pack_id,
+            None => return Ok(()),
+        };
+        match operation.r#type() {
+            StickerPackOperationType::Install => {
+                let pack_key = operation.pack_key.as_deref().ok_or_else(|| {
+                    Error::StickerError(String::from("Missing key of the installed pack"))
+                })?;
+                let pack = self.download_sticker_pack(pack_id, pack_key).await?;
+                info!(pack = %pack.id, title = ?pack.title, "installed sticker pack");
+            }
+            StickerPackOperationType::Remove => {
+                if self.state.remove_sticker_pack(&encode_hex(pack_id))? {
+                    info!(pack = %encode_hex(pack_id), "removed sticker pack");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends sticker of an installed pack. The sticker is uploaded as a regular attachment,
+    /// so that the recipient can show it without installing the pack.
+    pub async fn send_sticker(
+        &self,
+        recipient: &str,
+        pack_id: &str,
+        sticker_id: u32,
+        allow_blocked: bool,
+    ) -> Result<SendOutcome> {
+        let recipient = &self.state.resolve_recipient(recipient)?;
+        if !allow_blocked && self.state.is_blocked(recipient)? {
+            return Err(Error::BlockedRecipient(recipient.to_string()));
+        }
+
+        let unknown = || {
+            Error::StickerError(format!(
+                "Unknown sticker {} of pack {}",
+                sticker_id, pack_id
+            ))
+        };
+        let pack = self
+            .state
+            .sticker_pack(&pack_id.to_lowercase())?
+            .ok_or_else(|| Error::StickerError(format!("Pack {} is not installed", pack_id)))?;
+        let sticker = pack.sticker(sticker_id).ok_or_else(unknown)?;
+        let image = self
+            .state
+            .sticker_image(&pack.id, sticker_id)?
+            .ok_or_else(unknown)?;
+        let content_type = sticker
+            .content_type
+            .as_deref()
+            .unwrap_or(STICKER_CONTENT_TYPE);
+        let data = self.upload_attachment(&image, content_type, None).await?;
+
+        let expire_timer = self
+            .state
+            .find_contact(recipient)?
+            .and_then(|contact| contact.expire_timer)
+            .filter(|&timer| timer > 0);
+        let timestamp = timestamp_millis();
+        let content = Content {
+            data_message: Some(DataMessage {
+                timestamp: Some(timestamp),
+                expire_timer,
+                profile_key: self.state.profile_key()?.map(Vec::from),
+                sticker: Some(data_message::Sticker {
+                    pack_id: Some(decode_hex(&pack.id)?),
+                    pack_key: Some(decode_hex(&pack.key)?),
+                    sticker_id: Some(sticker_id),
+                    data: Some(data.clone()),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let history = StoredMessage {
+            conversation: recipient.to_string(),
+            author: self.state.address()?.name().to_string(),
+            timestamp,
+            body: None,
+            body_ranges: Vec::new(),
+            attachments: vec![(&data).into()],
+            expires_at: expires_at(expire_timer, timestamp),
+        };
+        self.send_queued(recipient, &content, timestamp, Some(history))
+            .await
+    }
+
+    /// Encrypts and uploads a new sticker pack made of the given WebP images and installs it.
+    /// The first sticker becomes the cover. Returns the link for sharing the pack.
+    pub async fn upload_sticker_pack(
+        &self,
+        title: &str,
+        author: &str,
+        stickers: &[Vec<u8>],
+    ) -> Result<String> {
+        if stickers.is_empty() || stickers.len() > MAX_PACK_SIZE {
+            return Err(Error::StickerError(format!(
+                "Pack must have between 1 and {} stickers",
+                MAX_PACK_SIZE
+            )));
+        }
+        let form: StickerPackUploadAttributes = self
+            .http_client
+            .send(
+                Method::GET,
+                ApiPath::StickerPackUploadForm {
+                    count: stickers.len(),
+                },
+            )
+            .await?
+            .json()
+            .await?;
+        if form.stickers.len() != stickers.len() {
+            return Err(Error::StickerError(String::from(
+                "Server returned wrong number of upload forms",
+            )));
+        }
+
+        let mut csprng = self.csprng.clone();
+        let mut pack_key = [0u8; 32];
+        csprng.fill_bytes(&mut pack_key);
+
+        let manifest_stickers = (0..stickers.len() as u32)
+            .map(|id| sticker_pack::Sticker {
+                id: Some(id),
+                emoji: None,
+                content_type: Some(STICKER_CONTENT_TYPE.to_string()),
+            })
+            .collect::<Vec<_>>();
+        let manifest = StickerPack {
+            title: Some(title.to_string()),
+            author: Some(author.to_string()),
+            cover: manifest_stickers.first().cloned(),
+            stickers: manifest_stickers,
+        };
+
+        let cdn = HttpClient::cdn(&self.api_config, 0)?;
+        let mut uploads = vec![(&form.manifest, manifest.encode_to_vec())];
+        for upload in &form.stickers {
+            let image = stickers.get(upload.id as usize).ok_or_else(|| {
+                Error::StickerError(format!("Unexpected sticker {} in upload form", upload.id))
+            })?;
+            uploads.push((upload, image.clone()));
+        }
+        for (upload, plaintext) in uploads {
+            let encrypted = encrypt_sticker_data(&pack_key, &plaintext, &mut csprng);
+            let fields = [
+                ("acl", upload.acl.as_str()),
+                ("key", upload.key.as_str()),
+                ("policy", upload.policy.as_str()),
+                ("Content-Type", "application/octet-stream"),
+                ("x-amz-algorithm", upload.algorithm.as_str()),
+                ("x-amz-credential", upload.credential.as_str()),
+                ("x-amz-date", upload.date.as_str()),
+                ("x-amz-signature", upload.signature.as_str()),
+            ];
+            cdn.send_multipart(Method::POST, ApiPath::StickerUpload, &fields, &encrypted)
+                .await?;
+        }
+
+        let pack_id = decode_hex(&form.pack_id)?;
+        let pack = StoredStickerPack::new(encode_hex(&pack_id), encode_hex(&pack_key), &manifest);
+        let images = (0u32..).zip(stickers.iter().cloned()).collect::<Vec<_>>();
+        self.state.save_sticker_pack(&pack, &images)?;
+        self.sync_sticker_pack_operation(
+            pack_id.clone(),
+            pack_key.to_vec(),
+            StickerPackOperationType::Install,
+        )
+        .await?;
+        Ok(sticker_pack_url(&pack_id, &pack_key))
+    }
+}
+
+/// Attempts of sending one message, each after the device list of the recipient was corrected
+const MAX_SEND_ATTEMPTS: usize = 3;
+
+/// Failed messages are kept in the inbox, but not retried automatically after so many attempts.
+const MAX_INBOX_ATTEMPTS: u32 = 5;
+
+/// Content type of stickers, which Signal apps require to be WebP images
+const STICKER_CONTENT_TYPE: &str = "image/webp";
+
+/// View-once message consists of a single image or video and nothing else.
+fn check_view_once(body: &str, attachments: &[Attachment]) -> Result<()> {
+    let invalid = |reason: &str| Err(Error::InvalidViewOnce(reason.to_string()));
+    if !body.is_empty() {
+        return invalid("View-once message can't have a body");
+    }
+    match attachments {
+        [attachment] => {
+            let content_type = attachment_type(attachment);
+            if content_type.starts_with("image/") || content_type.starts_with("video/") {
+                Ok(())
+            } else {
+                invalid("View-once attachment has to be an image or video")
+            }
+        }
+        _ => invalid("View-once message needs exactly one attachment"),
+    }
+}
+
+fn attachment_type(attachment: &Attachment) -> &'static str {
+    content_type_of(Path::new(
+        attachment.file_name.as_deref().unwrap_or_default(),
+    ))
+}
+
+/// Identifier of the group the message was sent to. Identifier of v2 groups isn't sent,
+/// it is derived from the master key.
+fn group_id(data_message: &DataMessage) -> Result<Option<Vec<u8>>> {
+    let master_key = data_message
+        .group_v2
+        .as_ref()
+        .and_then(|group| group.master_key.as_deref());
+    if let Some(master_key) = master_key {
+        let master_key = master_key
+            .try_into()
+            .map_err(|_| Error::InvalidEnvelope(String::from("Invalid group master key")))?;
+        let secret_params =
+            GroupSecretParams::derive_from_master_key(GroupMasterKey::new(master_key));
+        return Ok(Some(secret_params.get_group_identifier().to_vec()));
+    }
+    Ok(data_message
+        .group
+        .as_ref()
+        .and_then(|group| group.id.clone()))
+}
+
+fn is_expire_timer_update(data_message: &DataMessage) -> bool {
+    data_message.flags() & data_message::Flags::ExpirationTimerUpdate as u32 != 0
+}
+
+/// Time when message sent or received at `start` disappears, if the timer is set.
+fn expires_at(expire_timer: Option<u32>, start: u64) -> Option<u64> {
+    expire_timer
+        .filter(|&timer| timer > 0)
+        .map(|timer| start + u64::from(timer) * 1000)
 }
@@ -0,0 +1,138 @@
+use std::path::Path;
+
+use aes::Aes256;
+use block_modes::block_padding::Pkcs7;
+use block_modes::{BlockMode, Cbc};
+use hmac::{Hmac, Mac, NewMac};
+use rand::{CryptoRng, Rng};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::error::{Error, Result};
+use crate::proto::signal_service::AttachmentPointer;
+
+const IV_LEN: usize = 16;
+const MAC_LEN: usize = 32;
+const MIN_PADDED_SIZE: usize = 541;
+
+/// Attachment encrypted for the upload to the CDN
+pub(crate) struct EncryptedAttachment {
+    pub(crate) data: Vec<u8>,
+    /// AES and HMAC keys, 64 bytes in total
+    pub(crate) key: Vec<u8>,
+    /// SHA256 of the encrypted data
+    pub(crate) digest: Vec<u8>,
+}
+
+/// Size the attachment is padded to before encryption. Sizes grow exponentially,
+/// so only the rough size of the attachment leaks.
+fn padded_size(size: usize) -> usize {
+    let exponent = (size.max(1) as f64).ln() / 1.05f64.ln();
+    MIN_PADDED_SIZE.max(1.05f64.powf(exponent.ceil()).floor() as usize)
+}
+
+/// Content type of the attachment guessed from the extension of its file name.
+pub(crate) fn content_type_of(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    match extension.as_deref() {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("heic") => "image/heic",
+        Some("mp4") => "video/mp4",
+        Some("mov") => "video/quicktime",
+        Some("webm") => "video/webm",
+        Some("mp3") => "audio/mpeg",
+        Some("m4a") | Some("aac") => "audio/aac",
+        Some("ogg") | Some("oga") => "audio/ogg",
+        Some("pdf") => "application/pdf",
+        Some("txt") => "text/plain",
+        Some("vcf") => "text/x-vcard",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Pads and encrypts the attachment, the inverse of `decrypt_attachment`.
+pub(crate) fn encrypt_attachment<R: Rng + CryptoRng>(
+    plaintext: &[u8],
+    csprng: &mut R,
+) -> EncryptedAttachment {
+    let mut key = vec![0u8; 64];
+    csprng.fill_bytes(&mut key);
+
+    let mut padded = plaintext.to_vec();
+    padded.resize(padded_size(plaintext.len()), 0);
+    let data = encrypt_blob(&key, &padded, csprng);
+    let digest = Sha256::digest(&data).to_vec();
+
+    EncryptedAttachment { data, key, digest }
+}
+
+/// Decrypts attachment downloaded from the CDN and verifies its digest.
+pub(crate) fn decrypt_attachment(pointer: &AttachmentPointer, data: &[u8]) -> Result<Vec<u8>> {
+    if let Some(digest) = &pointer.digest {
+        if !bool::from(Sha256::digest(data)[..].ct_eq(digest)) {
+            return Err(Error::AttachmentError(String::from("digest mismatch")));
+        }
+    }
+    let mut plaintext = decrypt_blob(pointer.key(), data)?;
+
+    // Attachments are padded before encryption to hide their size
+    if let Some(size) = pointer.size {
+        plaintext.truncate(size as usize);
+    }
+    Ok(plaintext)
+}
+
+/// Encrypts data stored on the CDN into IV, AES-256-CBC ciphertext and HMAC-SHA256 of both,
+/// keyed by the two halves of the 64 bytes long key.
+pub(crate) fn encrypt_blob<R: Rng + CryptoRng>(
+    key: &[u8],
+    plaintext: &[u8],
+    csprng: &mut R,
+) -> Vec<u8> {
+    let mut iv = [0u8; IV_LEN];
+    csprng.fill_bytes(&mut iv);
+    let (cipher_key, mac_key) = key.split_at(32);
+
+    let ciphertext = Cbc::<Aes256, Pkcs7>::new_from_slices(cipher_key, &iv)
+        .expect("Key and IV have valid size")
+        .encrypt_vec(plaintext);
+
+    let mut data = [&iv[..], &ciphertext].concat();
+    let mut mac = Hmac::<Sha256>::new_from_slice(mac_key).expect("HMAC can take key of any size");
+    mac.update(&data);
+    data.extend_from_slice(&mac.finalize().into_bytes());
+    data
+}
+
+/// Decrypts data encrypted by `encrypt_blob`.
+pub(crate) fn decrypt_blob(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let invalid = |reason: &str| Error::AttachmentError(reason.to_string());
+
+    if key.len() != 64 {
+        return Err(invalid("invalid key length"));
+    }
+    if data.len() < IV_LEN + MAC_LEN {
+        return Err(invalid("attachment too short"));
+    }
+    let (cipher_key, mac_key) = key.split_at(32);
+    let (body, their_mac) = data.split_at(data.len() - MAC_LEN);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(mac_key).expect("HMAC can take key of any size");
+    mac.update(body);
+    if !bool::from(mac.finalize().into_bytes()[..].ct_eq(their_mac)) {
+        return Err(invalid("MAC mismatch"));
+    }
+
+    let (iv, ciphertext) = body.split_at(IV_LEN);
+    Cbc::<Aes256, Pkcs7>::new_from_slices(cipher_key, iv)
+        .expect("Key and IV have valid size")
+        .decrypt_vec(ciphertext)
+        .map_err(|_| invalid("invalid padding"))
+}
@@ -0,0 +1,87 @@
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use serde::Serialize;
+
+/// Capabilities advertised to the server. Other clients decide what content they send to us
+/// based on these flags, so a flag may be enabled only once the matching subsystem is
+/// implemented by this client.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub(crate) struct Capabilities {
+    #[serde(rename = "gv2-3")]
+    gv2: bool,
+    #[serde(rename = "gv1-migration")]
+    gv1_migration: bool,
+    #[serde(rename = "announcementGroup")]
+    announcement_group: bool,
+    #[serde(rename = "senderKey")]
+    sender_key: bool,
+    #[serde(rename = "changeNumber")]
+    change_number: bool,
+    #[serde(rename = "giftBadges")]
+    gift_badges: bool,
+    stories: bool,
+}
+
+impl Capabilities {
+    pub(crate) fn supported() -> Self {
+        Self {
+            // Group state is never fetched, v2 group ids only key conversations
+            gv2: false,
+            // Migration from v1 groups is part of groups v2
+            gv1_migration: false,
+            // Announcement-only groups are part of groups v2
+            announcement_group: false,
+            // Messages are encrypted only with 1:1 sessions, sender key distribution is missing
+            sender_key: false,
+            // Number change sync messages are not processed
+            change_number: false,
+            // Badges are not rendered anywhere
+            gift_badges: false,
+            // Story messages are not processed
+            stories: false,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct AccountAttributes {
+    capabilities: Capabilities,
+    #[serde(rename = "fetchesMessages")]
+    fetches_messages: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "registrationId")]
+    registration_id: u32,
+    #[serde(rename = "supportsSms")]
+    supports_sms: bool,
+    #[serde(rename = "unidentifiedAccessKey")]
+    unidentified_access_key: Option<String>,
+    #[serde(rename = "unrestrictedUnidentifiedAccess")]
+    unrestricted_unidentified_access: bool,
+}
+
+impl AccountAttributes {
+    /// Creates attributes with the encrypted name of the device.
+    pub(crate) fn new(name: Option<String>, registration_id: u32) -> Self {
+        Self {
+            capabilities: Capabilities::supported(),
+            fetches_messages: true,
+            name,
+            registration_id,
+            supports_sms: false,
+            unidentified_access_key: None,
+            unrestricted_unidentified_access: false,
+        }
+    }
+
+    /// Sets unidentified access key derived from the profile key, so other users
+    /// knowing our profile key can send us sealed sender messages.
+    pub(crate) fn with_profile_key(mut self, profile_key: &[u8; 32]) -> Self {
+        let access_key = Aes256Gcm::new(Key::from_slice(profile_key))
+            .encrypt(Nonce::from_slice(&[0u8; 12]), &[0u8; 16][..])
+            .expect("Encryption of fixed size input succeeds");
+        self.unidentified_access_key = Some(STANDARD.encode(&access_key[..16]));
+        self
+    }
+}
@@ -0,0 +1,343 @@
+use crate::error::{Error, Result};
+use crate::proto::signal_service::data_message::body_range::Style;
+use crate::proto::signal_service::data_message::BodyRange;
+
+/// Character official clients expect in the body in place of each mention
+const MENTION_PLACEHOLDER: char = '\u{FFFC}';
+
+/// Length of the text in UTF-16 code units, in which Signal measures body ranges.
+fn utf16_len(text: &str) -> u32 {
+    text.encode_utf16().count() as u32
+}
+
+/// Replaces mentioned spans of the body, given as character offset, character length
+/// and UUID of the mentioned contact, by placeholders. Returns the resulting body
+/// with mention ranges.
+pub(super) fn substitute_mentions(
+    body: &str,
+    mentions: &[(usize, usize, String)],
+) -> Result<(String, Vec<BodyRange>)> {
+    let mut mentions: Vec<_> = mentions.iter().collect();
+    mentions.sort_by_key(|(start, _, _)| *start);
+
+    let chars: Vec<char> = body.chars().collect();
+    let mut result = String::with_capacity(body.len());
+    let mut ranges = Vec::with_capacity(mentions.len());
+    let mut position = 0;
+    for (start, length, uuid) in mentions {
+        let end = start + length;
+        if *start < position || end > chars.len() || *length == 0 {
+            return Err(Error::InvalidMention(format!(
+                "Mention {}:{} doesn't fit the body or overlaps another mention",
+                start, length
+            )));
+        }
+        result.extend(&chars[position..*start]);
+        ranges.push(BodyRange {
+            start: Some(utf16_len(&result)),
+            length: Some(1),
+            mention_uuid: Some(uuid.clone()),
+            style: None,
+        });
+        result.push(MENTION_PLACEHOLDER);
+        position = end;
+    }
+    result.extend(&chars[position..]);
+    Ok((result, ranges))
+}
+
+/// Markdown delimiters with their styles. Longer delimiters go first,
+/// so that `**` is not taken for two italic delimiters.
+const DELIMITERS: &[(&str, Style)] = &[
+    ("```", Style::Monospace),
+    ("**", Style::Bold),
+    ("~~", Style::Strikethrough),
+    ("||", Style::Spoiler),
+    ("`", Style::Monospace),
+    ("*", Style::Italic),
+    ("_", Style::Italic),
+];
+
+struct OpenStyle<'a> {
+    delimiter: &'static str,
+    style: Style,
+    start: u32,
+    /// Markdown text consumed by the delimiter, restored when the style isn't closed
+    syntax: &'a str,
+    /// Byte offset of the style in the body
+    index: usize,
+}
+
+/// Delimiters other than code have to touch the styled text, so that e.g. `2 * 3 * 4`
+/// is kept as is.
+fn touches_text(style: Style) -> bool {
+    style != Style::Monospace
+}
+
+/// Checks whether the text following the opening delimiter contains its closing pair.
+fn has_closing(after: &str, delimiter: &str, style: Style) -> bool {
+    after.match_indices(delimiter).any(|(index, _)| {
+        index > 0 && (!touches_text(style) || !after[..index].ends_with(char::is_whitespace))
+    })
+}
+
+/// Parses Markdown subset (`**bold**`, `*italic*` or `_italic_`, `~~strikethrough~~`,
+/// `||spoiler||` and `` `monospace` `` or fenced code) into the plain body with style ranges.
+/// Delimiter without its closing pair and any character escaped by a backslash are kept as is.
+pub(super) fn parse_markdown(text: &str) -> (String, Vec<BodyRange>) {
+    let mut body = String::with_capacity(text.len());
+    let mut ranges = Vec::new();
+    let mut open: Vec<OpenStyle<'_>> = Vec::new();
+    let mut rest = text;
+
+    'outer: while let Some(current) = rest.chars().next() {
+        // Content of code is taken literally up to its closing delimiter
+        let code_delimiter = open
+            .last()
+            .filter(|open| open.style == Style::Monospace)
+            .map(|open| open.delimiter);
+        let in_code = code_delimiter.is_some();
+
+        if current == '\\' && !in_code {
+            if let Some(escaped) = rest[1..]
+                .chars()
+                .next()
+                .filter(|c| c.is_ascii_punctuation())
+            {
+                body.push(escaped);
+                rest = &rest[1 + escaped.len_utf8()..];
+                continue;
+            }
+        }
+
+        for &(delimiter, style) in DELIMITERS {
+            if !rest.starts_with(delimiter)
+                || code_delimiter.map_or(false, |code| code != delimiter)
+            {
+                continue;
+            }
+            let after = &rest[delimiter.len()..];
+            if let Some(index) = open.iter().rposition(|open| open.delimiter == delimiter) {
+                let closes = (delimiter != "_" || !after.starts_with(char::is_alphanumeric))
+                    && (!touches_text(style) || !body.ends_with(char::is_whitespace));
+                if closes {
+                    let open = open.remove(index);
+                    let length = utf16_len(&body) - open.start;
+                    if length > 0 {
+                        ranges.push(BodyRange {
+                            start: Some(open.start),
+                            length: Some(length),
+                            mention_uuid: None,
+                            style: Some(open.style as i32),
+                        });
+                    }
+                    rest = after;
+                    continue 'outer;
+                }
+            } else if !in_code
+                && (!touches_text(style) || after.starts_with(|c: char| !c.is_whitespace()))
+                && has_closing(after, delimiter, style)
+            {
+                // Underscores inside words, e.g. in snake_case identifiers, are not delimiters
+                let in_word = delimiter == "_" && body.ends_with(char::is_alphanumeric);
+                if !in_word {
+                    // Line break after opening fence belongs to the Markdown syntax
+                    let content = match delimiter {
+                        "```" => after.strip_prefix('\n').unwrap_or(after),
+                        _ => after,
+                    };
+                    open.push(OpenStyle {
+                        delimiter,
+                        style,
+                        start: utf16_len(&body),
+                        syntax: &rest[..rest.len() - content.len()],
+                        index: body.len(),
+                    });
+                    rest = content;
+                    continue 'outer;
+                }
+            }
+            // Delimiter which neither opens nor closes a style is plain text, including
+            // its shorter prefixes, e.g. `*` of unpaired `**`
+            body.push_str(delimiter);
+            rest = after;
+            continue 'outer;
+        }
+
+        body.push(current);
+        rest = &rest[current.len_utf8()..];
+    }
+
+    // Closing pair may still be refused, e.g. underscore followed by a letter, so styles
+    // left open get their delimiters back and ranges after them are shifted
+    for open in open.into_iter().rev() {
+        body.insert_str(open.index, open.syntax);
+        let shift = utf16_len(open.syntax);
+        for range in &mut ranges {
+            if range.start() >= open.start {
+                range.start = Some(range.start() + shift);
+            } else if range.start() + range.length() > open.start {
+                range.length = Some(range.length() + shift);
+            }
+        }
+    }
+
+    (body, ranges)
+}
+
+/// Moves mention ranges onto placeholders of the body, which shifted when the body was
+/// rewritten after mentions were substituted.
+pub(super) fn relocate_mentions(body: &str, mentions: &mut [BodyRange]) {
+    let mut offset = 0;
+    let mut placeholders = Vec::new();
+    for c in body.chars() {
+        if c == MENTION_PLACEHOLDER {
+            placeholders.push(offset);
+        }
+        offset += c.len_utf16() as u32;
+    }
+    for (mention, start) in mentions.iter_mut().zip(placeholders) {
+        mention.start = Some(start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u32, length: u32, style: Style) -> (u32, u32, Option<i32>) {
+        (start, length, Some(style as i32))
+    }
+
+    fn parse(text: &str) -> (String, Vec<(u32, u32, Option<i32>)>) {
+        let (body, ranges) = parse_markdown(text);
+        let ranges = ranges
+            .iter()
+            .map(|range| (range.start(), range.length(), range.style))
+            .collect();
+        (body, ranges)
+    }
+
+    #[test]
+    fn mentions_are_replaced_by_placeholders() {
+        let mentions = [
+            (4, 6, String::from("uuid-1")),
+            (0, 2, String::from("uuid-2")),
+        ];
+        let (body, ranges) = substitute_mentions("Hi, @Alice and @Bob", &mentions).unwrap();
+        assert_eq!(body, "\u{FFFC}, \u{FFFC} and @Bob");
+        let ranges: Vec<_> = ranges
+            .iter()
+            .map(|range| (range.start(), range.length(), range.mention_uuid.as_deref()))
+            .collect();
+        assert_eq!(ranges, [(0, 1, Some("uuid-2")), (3, 1, Some("uuid-1"))]);
+    }
+
+    #[test]
+    fn mention_offsets_are_utf16() {
+        // Emoji outside of the basic plane takes two UTF-16 code units
+        let mentions = [(2, 3, String::from("uuid"))];
+        let (body, ranges) = substitute_mentions("\u{1F600} Bob!", &mentions).unwrap();
+        assert_eq!(body, "\u{1F600} \u{FFFC}!");
+        assert_eq!(ranges[0].start(), 3);
+    }
+
+    #[test]
+    fn invalid_mentions_are_rejected() {
+        let overlapping = [(0, 3, String::from("a")), (2, 2, String::from("b"))];
+        assert!(substitute_mentions("abcdef", &overlapping).is_err());
+        assert!(substitute_mentions("abc", &[(2, 2, String::from("a"))]).is_err());
+        assert!(substitute_mentions("abc", &[(1, 0, String::from("a"))]).is_err());
+    }
+
+    #[test]
+    fn styles_are_parsed() {
+        assert_eq!(
+            parse("**bold** *it* ~~del~~ ||spoiler|| `code`"),
+            (
+                String::from("bold it del spoiler code"),
+                vec![
+                    range(0, 4, Style::Bold),
+                    range(5, 2, Style::Italic),
+                    range(8, 3, Style::Strikethrough),
+                    range(12, 7, Style::Spoiler),
+                    range(20, 4, Style::Monospace),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn style_offsets_are_utf16() {
+        assert_eq!(
+            parse("\u{1F600} **b\u{1F600}**"),
+            (
+                String::from("\u{1F600} b\u{1F600}"),
+                vec![range(3, 3, Style::Bold)]
+            )
+        );
+    }
+
+    #[test]
+    fn delimiters_have_to_touch_text() {
+        assert_eq!(parse("2 * 3 * 4"), (String::from("2 * 3 * 4"), vec![]));
+        assert_eq!(parse("a ** b **"), (String::from("a ** b **"), vec![]));
+        assert_eq!(
+            parse("*a * b*"),
+            (String::from("a * b"), vec![range(0, 5, Style::Italic)])
+        );
+    }
+
+    #[test]
+    fn code_is_taken_literally() {
+        assert_eq!(
+            parse("` *a* `"),
+            (String::from(" *a* "), vec![range(0, 5, Style::Monospace)])
+        );
+        assert_eq!(
+            parse("```\nlet _x_ = 1;\n```"),
+            (
+                String::from("let _x_ = 1;\n"),
+                vec![range(0, 13, Style::Monospace)]
+            )
+        );
+    }
+
+    #[test]
+    fn unpaired_and_escaped_delimiters_are_kept() {
+        assert_eq!(parse("a * b"), (String::from("a * b"), vec![]));
+        assert_eq!(parse("\\*a\\*"), (String::from("*a*"), vec![]));
+        assert_eq!(
+            parse("snake_case_name"),
+            (String::from("snake_case_name"), vec![])
+        );
+    }
+
+    #[test]
+    fn refused_closing_keeps_opening_delimiter() {
+        assert_eq!(
+            parse("_init_module"),
+            (String::from("_init_module"), vec![])
+        );
+        assert_eq!(parse("_a_b"), (String::from("_a_b"), vec![]));
+        assert_eq!(parse("*a *"), (String::from("*a *"), vec![]));
+        assert_eq!(
+            parse("_a **b**_c"),
+            (String::from("_a b_c"), vec![range(3, 1, Style::Bold)])
+        );
+        assert_eq!(
+            parse("**a _b** c_d"),
+            (String::from("a _b c_d"), vec![range(0, 4, Style::Bold)])
+        );
+    }
+
+    #[test]
+    fn mentions_follow_rewritten_body() {
+        let mentions = [(2, 4, String::from("uuid"))];
+        let (body, mut mention_ranges) = substitute_mentions("**@Bob**", &mentions).unwrap();
+        let (body, _) = parse_markdown(&body);
+        relocate_mentions(&body, &mut mention_ranges);
+        assert_eq!(body, "\u{FFFC}");
+        assert_eq!(mention_ranges[0].start(), 0);
+    }
+}
@@ -0,0 +1,122 @@
+use aes::cipher::{NewCipher, StreamCipher};
+use aes::Aes256Ctr;
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use hmac::{Hmac, Mac, NewMac};
+use libsignal_protocol::{IdentityKey, IdentityKeyPair, KeyPair, PublicKey};
+use prost::Message;
+use rand::{CryptoRng, Rng};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+use crate::error::{Error, Result};
+use crate::proto::signal_service::DeviceName;
+
+const SYNTHETIC_IV_SIZE: usize = 16;
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+fn synthetic_iv(master_secret: &[u8], plaintext: &[u8]) -> [u8; SYNTHETIC_IV_SIZE] {
+    let auth_key = hmac_sha256(master_secret, b"auth");
+    let mut iv = [0u8; SYNTHETIC_IV_SIZE];
+    iv.copy_from_slice(&hmac_sha256(&auth_key, plaintext)[..SYNTHETIC_IV_SIZE]);
+    iv
+}
+
+fn apply_cipher(master_secret: &[u8], synthetic_iv: &[u8], data: &mut [u8]) {
+    let cipher_key = hmac_sha256(&hmac_sha256(master_secret, b"cipher"), synthetic_iv);
+    Aes256Ctr::new(&cipher_key.into(), &[0u8; 16].into()).apply_keystream(data);
+}
+
+/// Encrypts device name to our identity key the same way as Signal-Desktop does,
+/// so the server never learns the name.
+pub(crate) fn encrypt_device_name<R: Rng + CryptoRng>(
+    name: &str,
+    identity_key: &IdentityKey,
+    csprng: &mut R,
+) -> Result<String> {
+    let ephemeral_key_pair = KeyPair::generate(csprng);
+    let master_secret = ephemeral_key_pair
+        .private_key
+        .calculate_agreement(identity_key.public_key())?;
+
+    let synthetic_iv = synthetic_iv(&master_secret, name.as_bytes());
+    let mut ciphertext = name.as_bytes().to_vec();
+    apply_cipher(&master_secret, &synthetic_iv, &mut ciphertext);
+
+    let device_name = DeviceName {
+        ephemeral_public: Some(ephemeral_key_pair.public_key.serialize().into_vec()),
+        synthetic_iv: Some(synthetic_iv.to_vec()),
+        ciphertext: Some(ciphertext),
+    };
+    Ok(STANDARD.encode(device_name.encode_to_vec()))
+}
+
+/// Decrypts device name encrypted to our identity key the same way as Signal-Desktop does.
+pub(crate) fn decrypt_device_name(
+    encrypted: &str,
+    identity_key_pair: &IdentityKeyPair,
+) -> Result<String> {
+    let device_name = DeviceName::decode(&*STANDARD.decode(encrypted)?)?;
+    let ephemeral_public = PublicKey::deserialize(device_name.ephemeral_public())?;
+    let master_secret = identity_key_pair
+        .private_key()
+        .calculate_agreement(&ephemeral_public)?;
+
+    let mut plaintext = device_name.ciphertext().to_vec();
+    apply_cipher(&master_secret, device_name.synthetic_iv(), &mut plaintext);
+
+    let expected_iv = synthetic_iv(&master_secret, &plaintext);
+    if !bool::from(expected_iv[..].ct_eq(device_name.synthetic_iv())) {
+        return Err(Error::InvalidDeviceName);
+    }
+
+    String::from_utf8(plaintext).map_err(|_| Error::InvalidDeviceName)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn device_name_round_trips() {
+        let identity_key_pair = IdentityKeyPair::generate(&mut OsRng);
+        let encrypted =
+            encrypt_device_name("Laptop 💻", identity_key_pair.identity_key(), &mut OsRng).unwrap();
+        assert_eq!(
+            decrypt_device_name(&encrypted, &identity_key_pair).unwrap(),
+            "Laptop 💻"
+        );
+    }
+
+    #[test]
+    fn device_name_of_other_identity_is_rejected() {
+        let identity_key_pair = IdentityKeyPair::generate(&mut OsRng);
+        let other_key_pair = IdentityKeyPair::generate(&mut OsRng);
+        let encrypted =
+            encrypt_device_name("Laptop", identity_key_pair.identity_key(), &mut OsRng).unwrap();
+        assert!(matches!(
+            decrypt_device_name(&encrypted, &other_key_pair),
+            Err(Error::InvalidDeviceName)
+        ));
+    }
+
+    #[test]
+    fn tampered_device_name_is_rejected() {
+        let identity_key_pair = IdentityKeyPair::generate(&mut OsRng);
+        let encrypted =
+            encrypt_device_name("Laptop", identity_key_pair.identity_key(), &mut OsRng).unwrap();
+        let mut device_name = DeviceName::decode(&*STANDARD.decode(encrypted).unwrap()).unwrap();
+        device_name.ciphertext.as_mut().unwrap()[0] ^= 1;
+        let tampered = STANDARD.encode(device_name.encode_to_vec());
+        assert!(matches!(
+            decrypt_device_name(&tampered, &identity_key_pair),
+            Err(Error::InvalidDeviceName)
+        ));
+    }
+}
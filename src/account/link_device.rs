@@ -0,0 +1,94 @@
+use aes::Aes256;
+use base64::engine::{general_purpose::STANDARD_NO_PAD, Engine as _};
+use block_modes::block_padding::Pkcs7;
+use block_modes::{BlockMode, Cbc};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac, NewMac};
+use libsignal_protocol::{KeyPair, PublicKey};
+use prost::Message;
+use rand::{CryptoRng, Rng};
+use sha2::Sha256;
+use url::Url;
+
+use crate::error::{Error, Result};
+use crate::proto::signal_service::{ProvisionEnvelope, ProvisionMessage};
+
+const PROVISIONING_INFO: &[u8] = b"TextSecure Provisioning Message";
+const CIPHER_VERSION: u8 = 1;
+
+/// Content of `sgnl://linkdevice?uuid=...&pub_key=...` URL shown by the device being linked.
+pub(crate) struct ProvisioningUrl {
+    pub(crate) uuid: String,
+    pub(crate) public_key: PublicKey,
+}
+
+impl ProvisioningUrl {
+    pub(crate) fn parse(url: &str) -> Result<Self> {
+        let invalid = |reason: &str| Error::InvalidProvisioningUrl(reason.to_string());
+
+        let url = Url::parse(url).map_err(|err| invalid(&err.to_string()))?;
+        if url.scheme() != "sgnl" || url.host_str() != Some("linkdevice") {
+            return Err(invalid("expected sgnl://linkdevice URL"));
+        }
+
+        let mut uuid = None;
+        let mut public_key = None;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "uuid" => uuid = Some(value.to_string()),
+                "pub_key" => public_key = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        let uuid = uuid.ok_or_else(|| invalid("missing uuid"))?;
+        let public_key = public_key.ok_or_else(|| invalid("missing pub_key"))?;
+        let public_key = STANDARD_NO_PAD
+            .decode(public_key.trim_end_matches('='))
+            .map_err(|_| invalid("pub_key is not valid base64"))?;
+
+        Ok(Self {
+            uuid,
+            public_key: PublicKey::deserialize(&public_key)?,
+        })
+    }
+}
+
+/// Encrypts the provision message to the key of the device being linked.
+/// This is the inverse of what `signal_provisioning_api::ProvisioningSocket` does on receiving side.
+pub(crate) fn encrypt_provision_message<R: Rng + CryptoRng>(
+    message: &ProvisionMessage,
+    their_public_key: &PublicKey,
+    csprng: &mut R,
+) -> Result<ProvisionEnvelope> {
+    let our_key_pair = KeyPair::generate(csprng);
+    let shared_secret = our_key_pair
+        .private_key
+        .calculate_agreement(their_public_key)?;
+
+    let mut derived = [0u8; 64];
+    Hkdf::<Sha256>::new(None, &shared_secret)
+        .expand(PROVISIONING_INFO, &mut derived)
+        .expect("Output length is valid");
+    let (cipher_key, mac_key) = derived.split_at(32);
+
+    let mut iv = [0u8; 16];
+    csprng.fill_bytes(&mut iv);
+    let ciphertext = Cbc::<Aes256, Pkcs7>::new_from_slices(cipher_key, &iv)
+        .expect("Key and IV have valid size")
+        .encrypt_vec(&message.encode_to_vec());
+
+    let mut body = Vec::with_capacity(1 + iv.len() + ciphertext.len() + 32);
+    body.push(CIPHER_VERSION);
+    body.extend_from_slice(&iv);
+    body.extend_from_slice(&ciphertext);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(mac_key).expect("HMAC can take key of any size");
+    mac.update(&body);
+    body.extend_from_slice(&mac.finalize().into_bytes());
+
+    Ok(ProvisionEnvelope {
+        public_key: Some(our_key_pair.public_key.serialize().into_vec()),
+        body: Some(body),
+    })
+}
@@ -0,0 +1,106 @@
+use url::Url;
+
+/// Open Graph metadata of the web page
+#[derive(Debug, Default)]
+pub(super) struct PageMetadata {
+    pub(super) title: Option<String>,
+    pub(super) description: Option<String>,
+    pub(super) image: Option<String>,
+}
+
+/// Finds the first HTTPS URL in the body. Official clients show previews only for HTTPS
+/// links contained in the body as is, so the URL is returned as written in the body.
+pub(super) fn find_url(body: &str) -> Option<&str> {
+    body.split_whitespace()
+        .map(|word| {
+            word.trim_start_matches(|c| matches!(c, '(' | '<' | '"' | '\''))
+                .trim_end_matches(|c| {
+                    matches!(
+                        c,
+                        '.' | ',' | ';' | ':' | '!' | '?' | ')' | '>' | '"' | '\''
+                    )
+                })
+        })
+        .filter(|word| word.starts_with("https://"))
+        .find(|word| Url::parse(word).map_or(false, |url| url.host_str().is_some()))
+}
+
+/// Extracts title, description and image from Open Graph `<meta>` tags of the page,
+/// falling back to `<title>` and the description `<meta>` tag.
+pub(super) fn parse_page(html: &str) -> PageMetadata {
+    let mut metadata = PageMetadata::default();
+    let mut fallback_description = None;
+    let lowercase = html.to_ascii_lowercase();
+
+    let mut position = 0;
+    while let Some(start) = lowercase[position..].find("<meta") {
+        let start = position + start;
+        let end = match lowercase[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let tag = &html[start..end];
+        position = end;
+
+        let name = attribute(tag, "property").or_else(|| attribute(tag, "name"));
+        let content = match attribute(tag, "content") {
+            Some(content) if !content.trim().is_empty() => content.trim().to_string(),
+            _ => continue,
+        };
+        match name.map(|name| name.to_ascii_lowercase()).as_deref() {
+            Some("og:title") => metadata.title = metadata.title.or(Some(content)),
+            Some("og:description") => metadata.description = metadata.description.or(Some(content)),
+            Some("og:image") | Some("og:image:url") => {
+                metadata.image = metadata.image.or(Some(content))
+            }
+            Some("description") => fallback_description = fallback_description.or(Some(content)),
+            _ => {}
+        }
+    }
+
+    if metadata.title.is_none() {
+        metadata.title = lowercase.find("<title").and_then(|start| {
+            let start = start + lowercase[start..].find('>')? + 1;
+            let end = start + lowercase[start..].find("</title")?;
+            Some(decode_entities(html[start..end].trim())).filter(|title| !title.is_empty())
+        });
+    }
+    metadata.description = metadata.description.or(fallback_description);
+    metadata
+}
+
+/// Value of the attribute of HTML tag, with entities decoded.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lowercase = tag.to_ascii_lowercase();
+    let mut position = 0;
+    while let Some(found) = lowercase[position..].find(name) {
+        let start = position + found;
+        position = start + name.len();
+        // Skip matches which are only part of another attribute name
+        if !lowercase[..start].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let rest = tag[position..].trim_start();
+        let rest = match rest.strip_prefix('=') {
+            Some(rest) => rest.trim_start(),
+            None => continue,
+        };
+        let value = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => rest[1..].split(quote).next()?,
+            _ => rest.split(|c: char| c.is_whitespace() || c == '/').next()?,
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
@@ -1,10 +1,10 @@
-use std::time::SystemTime;
-
-use libsignal_protocol::{CiphertextMessage, DeviceId};
+use libsignal_protocol::{CiphertextMessage, DeviceId, ProtocolAddress};
 use serde::{Deserialize, Serialize};
 
+use crate::proto::signal_service::{Content, Envelope};
 use crate::utils::serde::{
-    deserialize_device_id_vec, serialize_ciphertext_message, serialize_device_id,
+    deserialize_device_id_vec, deserialize_opt_byte_vec, serialize_byte_vec,
+    serialize_ciphertext_message, serialize_device_id, serialize_opt_byte_vec,
 };
 
 #[derive(Serialize)]
@@ -42,13 +42,11 @@ pub(crate) struct MessagesWrapper {
 }
 
 impl MessagesWrapper {
-    pub(crate) fn new(messages: Vec<SendMetadata>) -> Self {
+    /// The `timestamp` has to match the timestamp of the sent `DataMessage`.
+    pub(crate) fn new(messages: Vec<SendMetadata>, timestamp: u64) -> Self {
         Self {
             messages,
-            timestamp: SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_millis() as u64,
+            timestamp,
             online: false,
         }
     }
@@ -68,9 +66,185 @@ pub(crate) struct MessageResponse409 {
     pub(crate) extra_devices: Vec<DeviceId>,
 }
 
+/// Devices whose sessions are out of date, e.g. after the device was re-registered
+#[derive(Debug, Deserialize)]
+pub(crate) struct MessageResponse410 {
+    #[serde(
+        rename = "staleDevices",
+        deserialize_with = "deserialize_device_id_vec"
+    )]
+    pub(crate) stale_devices: Vec<DeviceId>,
+}
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct MessageResponse200 {
     #[serde(rename = "needsSync")]
-    #[allow(dead_code)]
     pub(crate) needs_sync: bool,
 }
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeviceInfo {
+    pub(crate) id: u32,
+    pub(crate) name: Option<String>,
+    #[serde(rename = "lastSeen")]
+    pub(crate) last_seen: u64,
+    pub(crate) created: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DevicesResponse {
+    pub(crate) devices: Vec<DeviceInfo>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DeviceNameRequest {
+    #[serde(rename = "deviceName")]
+    pub(crate) device_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ProvisioningCodeResponse {
+    #[serde(rename = "verificationCode")]
+    pub(crate) verification_code: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ProvisioningMessageRequest {
+    pub(crate) body: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct IncomingMessageEntity {
+    pub(crate) guid: String,
+    #[serde(rename = "type")]
+    msg_type: i32,
+    timestamp: u64,
+    source: Option<String>,
+    #[serde(rename = "sourceUuid")]
+    source_uuid: Option<String>,
+    #[serde(rename = "sourceDevice", default)]
+    source_device: u32,
+    #[serde(default, deserialize_with = "deserialize_opt_byte_vec")]
+    content: Option<Vec<u8>>,
+    #[serde(rename = "serverTimestamp", default)]
+    server_timestamp: u64,
+}
+
+impl From<IncomingMessageEntity> for Envelope {
+    fn from(entity: IncomingMessageEntity) -> Self {
+        Self {
+            r#type: Some(entity.msg_type),
+            source: entity.source,
+            source_uuid: entity.source_uuid,
+            source_device: Some(entity.source_device),
+            timestamp: Some(entity.timestamp),
+            content: entity.content,
+            server_guid: Some(entity.guid),
+            server_timestamp: Some(entity.server_timestamp),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct IncomingMessageList {
+    pub(crate) messages: Vec<IncomingMessageEntity>,
+    pub(crate) more: bool,
+}
+
+/// Decrypted content of an incoming envelope
+#[derive(Debug)]
+pub(crate) struct ReceivedMessage {
+    /// Id of the inbox entry, which is removed once the message is acknowledged
+    pub(crate) id: u64,
+    pub(crate) sender: ProtocolAddress,
+    /// Name of the sender as known from contacts
+    pub(crate) sender_name: String,
+    pub(crate) timestamp: u64,
+    pub(crate) content: Content,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ProfileResponse {
+    #[serde(default, deserialize_with = "deserialize_opt_byte_vec")]
+    pub(crate) name: Option<Vec<u8>>,
+    #[serde(default, deserialize_with = "deserialize_opt_byte_vec")]
+    pub(crate) about: Option<Vec<u8>>,
+    #[serde(
+        rename = "aboutEmoji",
+        default,
+        deserialize_with = "deserialize_opt_byte_vec"
+    )]
+    pub(crate) about_emoji: Option<Vec<u8>>,
+    /// Path of the encrypted avatar on the CDN
+    pub(crate) avatar: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ProfileWriteRequest {
+    pub(crate) version: String,
+    #[serde(serialize_with = "serialize_opt_byte_vec")]
+    pub(crate) name: Option<Vec<u8>>,
+    #[serde(serialize_with = "serialize_opt_byte_vec")]
+    pub(crate) about: Option<Vec<u8>>,
+    #[serde(rename = "aboutEmoji", serialize_with = "serialize_opt_byte_vec")]
+    pub(crate) about_emoji: Option<Vec<u8>>,
+    #[serde(rename = "paymentAddress")]
+    pub(crate) payment_address: Option<String>,
+    /// Whether new avatar is going to be uploaded
+    pub(crate) avatar: bool,
+    /// Whether the avatar of previous profile version should be kept
+    #[serde(rename = "sameAvatar")]
+    pub(crate) same_avatar: bool,
+    #[serde(serialize_with = "serialize_byte_vec")]
+    pub(crate) commitment: Vec<u8>,
+    #[serde(rename = "badgeIds")]
+    pub(crate) badge_ids: Vec<String>,
+}
+
+/// Pre-signed form for uploading the avatar to the CDN
+#[derive(Debug, Deserialize)]
+pub(crate) struct AvatarUploadAttributes {
+    pub(crate) key: String,
+    pub(crate) credential: String,
+    pub(crate) acl: String,
+    pub(crate) algorithm: String,
+    pub(crate) date: String,
+    pub(crate) policy: String,
+    pub(crate) signature: String,
+}
+
+/// Pre-signed form for uploading the attachment to the CDN
+#[derive(Debug, Deserialize)]
+pub(crate) struct AttachmentUploadAttributes {
+    pub(crate) key: String,
+    pub(crate) credential: String,
+    pub(crate) acl: String,
+    pub(crate) algorithm: String,
+    pub(crate) date: String,
+    pub(crate) policy: String,
+    pub(crate) signature: String,
+    #[serde(rename = "attachmentId")]
+    pub(crate) attachment_id: u64,
+}
+
+/// Pre-signed forms for uploading the manifest and stickers of a new pack to the CDN
+#[derive(Debug, Deserialize)]
+pub(crate) struct StickerPackUploadAttributes {
+    #[serde(rename = "packId")]
+    pub(crate) pack_id: String,
+    pub(crate) manifest: StickerUploadAttributes,
+    pub(crate) stickers: Vec<StickerUploadAttributes>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct StickerUploadAttributes {
+    pub(crate) id: u32,
+    pub(crate) key: String,
+    pub(crate) credential: String,
+    pub(crate) acl: String,
+    pub(crate) algorithm: String,
+    pub(crate) date: String,
+    pub(crate) policy: String,
+    pub(crate) signature: String,
+}
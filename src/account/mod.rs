@@ -1,5 +1,19 @@
 mod account_manager;
+mod attachments;
+pub(crate) mod attributes;
+mod body_ranges;
+pub(crate) mod device_name;
+mod link_device;
+mod link_preview;
 mod messages;
+mod outbox;
+mod padding;
 mod pre_keys;
+mod profile;
+mod stickers;
+pub(crate) mod sync;
+mod thumbnail;
+mod vcard;
 
 pub(crate) use account_manager::AccountManager;
+pub(crate) use messages::{DeviceInfo, ReceivedMessage};
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Delay before the first retry, doubled with each failed attempt
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+/// Failed delivery attempts after which the message is dropped
+const MAX_DELIVERY_ATTEMPTS: u32 = 15;
+/// Retries of the message after the device list of the recipient kept changing
+const MAX_DEVICE_CHANGE_RETRIES: u32 = 3;
+/// Age after which the message is dropped when its delivery fails again
+const MAX_QUEUED_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Delay before the next delivery attempt of a message which failed `attempts` times
+/// before this failure, or `None` when the failure is permanent. Network errors,
+/// server errors and rate limiting are retried, device lists changing under our hands
+/// only a few times; rate limiting honours `Retry-After`.
+pub(crate) fn retry_delay(err: &Error, attempts: u32) -> Option<Duration> {
+    let backoff = BASE_RETRY_DELAY
+        .checked_mul(2u32.saturating_pow(attempts))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY));
+    match err {
+        Error::RateLimited {
+            retry_after: Some(retry_after),
+        } => Some(*retry_after),
+        Error::RateLimited { retry_after: None } => Some(backoff),
+        Error::HttpError(status, _) if status.is_server_error() => Some(backoff),
+        Error::HyperError(_)
+        | Error::IoError(_)
+        | Error::SocketError(_)
+        | Error::ConnectionError(_) => Some(backoff),
+        Error::TooManySendAttempts(_) if attempts < MAX_DEVICE_CHANGE_RETRIES => Some(backoff),
+        _ => None,
+    }
+}
+
+/// Whether delivery of the message sent at `timestamp`, which failed `attempts` times
+/// before this failure, is given up instead of retried. Times are in milliseconds since epoch.
+pub(crate) fn gives_up(attempts: u32, timestamp: u64, now: u64) -> bool {
+    attempts + 1 >= MAX_DELIVERY_ATTEMPTS
+        || now.saturating_sub(timestamp) >= MAX_QUEUED_AGE.as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn retry_delay_backs_off_exponentially() {
+        let err = Error::ConnectionError(String::from("closed"));
+        assert_eq!(retry_delay(&err, 0), Some(Duration::from_secs(5)));
+        assert_eq!(retry_delay(&err, 1), Some(Duration::from_secs(10)));
+        assert_eq!(retry_delay(&err, 3), Some(Duration::from_secs(40)));
+        assert_eq!(retry_delay(&err, 20), Some(MAX_RETRY_DELAY));
+        assert_eq!(retry_delay(&err, u32::MAX), Some(MAX_RETRY_DELAY));
+    }
+
+    #[test]
+    fn retry_delay_honours_retry_after() {
+        let err = Error::RateLimited {
+            retry_after: Some(Duration::from_secs(120)),
+        };
+        assert_eq!(retry_delay(&err, 0), Some(Duration::from_secs(120)));
+        let err = Error::RateLimited { retry_after: None };
+        assert_eq!(retry_delay(&err, 1), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn retry_delay_depends_on_error() {
+        let server_error = Error::HttpError(StatusCode::SERVICE_UNAVAILABLE, String::new());
+        assert!(retry_delay(&server_error, 0).is_some());
+        let client_error = Error::HttpError(StatusCode::BAD_REQUEST, String::new());
+        assert_eq!(retry_delay(&client_error, 0), None);
+        let unregistered = Error::UnregisteredRecipient(String::from("alice"));
+        assert_eq!(retry_delay(&unregistered, 0), None);
+    }
+
+    #[test]
+    fn device_list_changes_are_retried_few_times() {
+        let err = Error::TooManySendAttempts(String::from("alice"));
+        for attempts in 0..MAX_DEVICE_CHANGE_RETRIES {
+            assert!(retry_delay(&err, attempts).is_some());
+        }
+        assert_eq!(retry_delay(&err, MAX_DEVICE_CHANGE_RETRIES), None);
+    }
+
+    #[test]
+    fn delivery_gives_up_after_attempts_or_age() {
+        let timestamp = 1_600_000_000_000;
+        assert!(!gives_up(0, timestamp, timestamp));
+        assert!(!gives_up(MAX_DELIVERY_ATTEMPTS - 2, timestamp, timestamp));
+        assert!(gives_up(MAX_DELIVERY_ATTEMPTS - 1, timestamp, timestamp));
+
+        let max_age = MAX_QUEUED_AGE.as_millis() as u64;
+        assert!(!gives_up(0, timestamp, timestamp + max_age - 1));
+        assert!(gives_up(0, timestamp, timestamp + max_age));
+        // Clock going backwards doesn't drop the message
+        assert!(!gives_up(0, timestamp, timestamp - 1000));
+    }
+}
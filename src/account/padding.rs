@@ -0,0 +1,18 @@
+const PADDING_BLOCK: usize = 160;
+
+/// Pads serialized `Content` with `0x80` followed by zero bytes, so the length of the message
+/// leaks only its rough size.
+pub(super) fn add_padding(mut data: Vec<u8>) -> Vec<u8> {
+    let padded_len = (data.len() + 1 + PADDING_BLOCK - 1) / PADDING_BLOCK * PADDING_BLOCK;
+    data.push(0x80);
+    data.resize(padded_len, 0);
+    data
+}
+
+/// Removes padding appended to the serialized `Content`, i.e. `0x80` followed by zero bytes.
+pub(super) fn strip_padding(data: &[u8]) -> &[u8] {
+    match data.iter().rposition(|&byte| byte != 0) {
+        Some(index) if data[index] == 0x80 => &data[..index],
+        _ => data,
+    }
+}
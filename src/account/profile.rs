@@ -0,0 +1,82 @@
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rand::{CryptoRng, Rng};
+use uuid::Uuid;
+use zkgroup::profiles::ProfileKey;
+
+use crate::error::{Error, Result};
+
+const NONCE_LEN: usize = 12;
+
+/// Hex encoded version of the profile, as expected by the versioned profile endpoint.
+pub(crate) fn profile_key_version(profile_key: &[u8], uuid: &Uuid) -> Result<String> {
+    let profile_key: [u8; 32] = profile_key.try_into().map_err(|_| Error::InvalidProfile)?;
+    let version = ProfileKey::create(profile_key).get_profile_key_version(*uuid.as_bytes());
+    // Version serializes to its hex encoding
+    let version = bincode::serialize(&version).map_err(|_| Error::InvalidProfile)?;
+    String::from_utf8(version).map_err(|_| Error::InvalidProfile)
+}
+
+/// Sizes to which profile fields are padded, so their length is not revealed.
+pub(crate) const NAME_PADDING: &[usize] = &[53, 257];
+pub(crate) const ABOUT_PADDING: &[usize] = &[128, 254, 512];
+pub(crate) const EMOJI_PADDING: &[usize] = &[32];
+
+/// Serialized zkgroup commitment to the profile key, uploaded along with the profile.
+pub(crate) fn profile_key_commitment(profile_key: &[u8; 32], uuid: &Uuid) -> Result<Vec<u8>> {
+    let commitment = ProfileKey::create(*profile_key).get_commitment(*uuid.as_bytes());
+    bincode::serialize(&commitment).map_err(|_| Error::InvalidProfile)
+}
+
+/// Encrypts profile field or avatar: random nonce followed by AES-256-GCM ciphertext.
+pub(crate) fn encrypt_profile_data<R: Rng + CryptoRng>(
+    profile_key: &[u8; 32],
+    plaintext: &[u8],
+    csprng: &mut R,
+) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    csprng.fill_bytes(&mut nonce);
+    let ciphertext = Aes256Gcm::new(Key::from_slice(profile_key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| Error::InvalidProfile)?;
+    Ok([&nonce[..], &ciphertext].concat())
+}
+
+/// Encrypts profile text field padded by zero bytes to the smallest fitting size of `padding`.
+pub(crate) fn encrypt_profile_string<R: Rng + CryptoRng>(
+    profile_key: &[u8; 32],
+    value: &str,
+    padding: &[usize],
+    csprng: &mut R,
+) -> Result<Vec<u8>> {
+    let size = padding
+        .iter()
+        .copied()
+        .find(|&size| size >= value.len())
+        .ok_or(Error::InvalidProfile)?;
+    let mut plaintext = value.as_bytes().to_vec();
+    plaintext.resize(size, 0);
+    encrypt_profile_data(profile_key, &plaintext, csprng)
+}
+
+/// Decrypts profile field or avatar: nonce followed by AES-256-GCM ciphertext.
+pub(crate) fn decrypt_profile_data(profile_key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    if profile_key.len() != 32 || data.len() < NONCE_LEN {
+        return Err(Error::InvalidProfile);
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    Aes256Gcm::new(Key::from_slice(profile_key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::InvalidProfile)
+}
+
+/// Decrypts profile text field, which is padded by zero bytes to hide its length.
+pub(crate) fn decrypt_profile_string(profile_key: &[u8], data: &[u8]) -> Result<String> {
+    let mut plaintext = decrypt_profile_data(profile_key, data)?;
+    let len = plaintext
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |index| index + 1);
+    plaintext.truncate(len);
+    String::from_utf8(plaintext).map_err(|_| Error::InvalidProfile)
+}
@@ -0,0 +1,60 @@
+use hkdf::Hkdf;
+use rand::{CryptoRng, Rng};
+use sha2::Sha256;
+
+use crate::error::{Error, Result};
+
+use super::attachments::{decrypt_blob, encrypt_blob};
+
+const STICKER_PACK_INFO: &[u8] = b"Sticker Pack";
+
+/// Maximum number of stickers the server accepts in a single pack
+pub(crate) const MAX_PACK_SIZE: usize = 200;
+
+/// Derives the AES and HMAC keys of manifest and stickers from the pack key.
+fn derive_sticker_keys(pack_key: &[u8]) -> [u8; 64] {
+    let mut keys = [0u8; 64];
+    Hkdf::<Sha256>::new(None, pack_key)
+        .expand(STICKER_PACK_INFO, &mut keys)
+        .expect("Output has valid length");
+    keys
+}
+
+/// Encrypts the manifest or a sticker of the pack for the upload to the CDN.
+pub(crate) fn encrypt_sticker_data<R: Rng + CryptoRng>(
+    pack_key: &[u8],
+    plaintext: &[u8],
+    csprng: &mut R,
+) -> Vec<u8> {
+    encrypt_blob(&derive_sticker_keys(pack_key), plaintext, csprng)
+}
+
+/// Decrypts the manifest or a sticker of the pack downloaded from the CDN.
+pub(crate) fn decrypt_sticker_data(pack_key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    decrypt_blob(&derive_sticker_keys(pack_key), data)
+}
+
+/// Lowercase hex, which is how pack ids and keys appear in CDN paths and pack links.
+pub(crate) fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let invalid = || Error::StickerError(format!("Invalid hex string {}", hex));
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+/// Link which installs the pack in Signal apps.
+pub(crate) fn sticker_pack_url(pack_id: &[u8], pack_key: &[u8]) -> String {
+    format!(
+        "https://signal.art/addstickers/#pack_id={}&pack_key={}",
+        encode_hex(pack_id),
+        encode_hex(pack_key)
+    )
+}
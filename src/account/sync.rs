@@ -0,0 +1,23 @@
+use crate::error::Result;
+use crate::proto::signal_service::{ContactDetails, GroupDetails};
+use crate::utils::read_delimited;
+
+/// Parses length-delimited `ContactDetails` as sent in the contacts sync blob.
+pub(crate) fn read_contact_details(blob: &[u8]) -> Result<Vec<ContactDetails>> {
+    read_delimited(blob, |details: &ContactDetails| {
+        details
+            .avatar
+            .as_ref()
+            .map_or(0, |avatar| avatar.length() as usize)
+    })
+}
+
+/// Parses length-delimited `GroupDetails` as sent in the groups sync blob.
+pub(crate) fn read_group_details(blob: &[u8]) -> Result<Vec<GroupDetails>> {
+    read_delimited(blob, |details: &GroupDetails| {
+        details
+            .avatar
+            .as_ref()
+            .map_or(0, |avatar| avatar.length() as usize)
+    })
+}
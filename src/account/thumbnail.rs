@@ -0,0 +1,22 @@
+use image::{DynamicImage, ImageOutputFormat};
+
+use crate::error::{Error, Result};
+
+/// Longer side of the thumbnail in pixels
+const THUMBNAIL_SIZE: u32 = 150;
+const THUMBNAIL_QUALITY: u8 = 75;
+pub(super) const THUMBNAIL_CONTENT_TYPE: &str = "image/jpeg";
+
+/// Scales the image down to fit the thumbnail size, keeping its aspect ratio,
+/// and encodes it as JPEG.
+pub(super) fn create_thumbnail(data: &[u8]) -> Result<Vec<u8>> {
+    let image = image::load_from_memory(data).map_err(|err| Error::ImageError(err.to_string()))?;
+    // JPEG has no alpha channel
+    let thumbnail =
+        DynamicImage::ImageRgb8(image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgb8());
+    let mut output = Vec::new();
+    thumbnail
+        .write_to(&mut output, ImageOutputFormat::Jpeg(THUMBNAIL_QUALITY))
+        .map_err(|err| Error::ImageError(err.to_string()))?;
+    Ok(output)
+}
@@ -0,0 +1,631 @@
+use std::borrow::Cow;
+
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+
+use crate::error::{Error, Result};
+use crate::proto::signal_service::data_message::contact::{
+    email, phone, postal_address, Email, Name, Phone, PostalAddress,
+};
+use crate::proto::signal_service::data_message::Contact;
+
+/// Octets after which lines of written vCards are folded
+const MAX_LINE_LEN: usize = 75;
+
+/// Values of the vCard 2.1 `ENCODING` parameter
+const ENCODINGS: &[&str] = &["7bit", "8bit", "quoted-printable", "base64"];
+
+/// Contact read from a vCard. The photo is kept aside with its content type,
+/// since it has to be uploaded as an attachment before the contact is sent.
+pub(crate) struct VCard {
+    pub(crate) contact: Contact,
+    pub(crate) photo: Option<(Vec<u8>, String)>,
+}
+
+/// Single content line of a vCard split into its name, lowercase parameters and value.
+/// Quoted-printable values of vCard 2.1 are decoded, other values are kept raw.
+struct Property<'a> {
+    name: String,
+    params: Vec<(String, String)>,
+    value: Cow<'a, str>,
+}
+
+impl<'a> Property<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let (head, value) = line.split_once(':')?;
+        let mut parts = head.split(';');
+        let name = parts.next()?;
+        // Grouped properties like `item1.TEL` are treated as ungrouped
+        let name = name.rsplit('.').next().unwrap_or(name).to_uppercase();
+        let params = parts
+            .map(|param| match param.split_once('=') {
+                Some((key, value)) => (key.to_lowercase(), value.trim_matches('"').to_lowercase()),
+                // vCard 2.1 allows encodings and types without the `ENCODING=` or `TYPE=` prefix
+                None => match param.to_lowercase() {
+                    encoding if ENCODINGS.contains(&encoding.as_str()) => {
+                        (String::from("encoding"), encoding)
+                    }
+                    kind => (String::from("type"), kind),
+                },
+            })
+            .collect();
+        let mut property = Self {
+            name,
+            params,
+            value: Cow::Borrowed(value),
+        };
+        if property.param("encoding") == Some("quoted-printable") {
+            let decoded = decode_quoted_printable(value);
+            property.value = Cow::Owned(decode_charset(decoded, property.param("charset")));
+        }
+        Some(property)
+    }
+
+    /// Values of all `TYPE` parameters, which may be repeated or comma separated.
+    fn types(&self) -> impl Iterator<Item = &str> {
+        self.params
+            .iter()
+            .filter(|(key, _)| key == "type")
+            .flat_map(|(_, value)| value.split(','))
+    }
+
+    fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Type given by the first type recognized by `known`. Unrecognized types
+    /// other than the generic ones become the label of a custom type.
+    fn kind<T>(&self, known: impl Fn(&str) -> Option<T>) -> (Option<T>, Option<String>) {
+        const GENERIC: &[&str] = &["pref", "voice", "internet", "x400", "msg", "text"];
+        if let Some(kind) = self.types().find_map(&known) {
+            return (Some(kind), None);
+        }
+        let label = self
+            .types()
+            .find(|kind| !GENERIC.contains(kind))
+            .map(str::to_string);
+        (None, label)
+    }
+
+    fn text(&self) -> Option<String> {
+        Some(unescape(&self.value)).filter(|value| !value.is_empty())
+    }
+
+    fn components(&self) -> Vec<Option<String>> {
+        split_unescaped(&self.value)
+            .into_iter()
+            .map(|component| Some(unescape(component)).filter(|value| !value.is_empty()))
+            .collect()
+    }
+}
+
+/// Reads all contacts of a `.vcf` file. Versions 2.1, 3.0 and 4.0 are understood
+/// as far as the fields of Signal contact cards go.
+pub(crate) fn parse_vcards(text: &str) -> Result<Vec<VCard>> {
+    let mut cards = Vec::new();
+    let mut current: Option<VCard> = None;
+
+    for line in unfold(text) {
+        let property = match Property::parse(&line) {
+            Some(property) => property,
+            None => continue,
+        };
+        match (property.name.as_str(), &mut current) {
+            ("BEGIN", None) if property.value.eq_ignore_ascii_case("vcard") => {
+                current = Some(VCard {
+                    contact: Contact::default(),
+                    photo: None,
+                });
+            }
+            ("END", Some(_)) if property.value.eq_ignore_ascii_case("vcard") => {
+                cards.extend(current.take());
+            }
+            (_, Some(card)) => apply_property(card, &property)?,
+            (_, None) => {}
+        }
+    }
+
+    if current.is_some() {
+        return Err(Error::InvalidVCard(String::from("Missing END:VCARD")));
+    }
+    if cards.is_empty() {
+        return Err(Error::InvalidVCard(String::from("No vCard found")));
+    }
+    Ok(cards)
+}
+
+fn apply_property(card: &mut VCard, property: &Property) -> Result<()> {
+    let contact = &mut card.contact;
+    match property.name.as_str() {
+        "N" => {
+            let mut components = property.components().into_iter();
+            let name = contact.name.get_or_insert_with(Name::default);
+            name.family_name = components.next().flatten();
+            name.given_name = components.next().flatten();
+            name.middle_name = components.next().flatten();
+            name.prefix = components.next().flatten();
+            name.suffix = components.next().flatten();
+        }
+        "FN" => {
+            contact.name.get_or_insert_with(Name::default).display_name = property.text();
+        }
+        "ORG" => {
+            contact.organization = property.components().into_iter().next().flatten();
+        }
+        "TEL" => {
+            let value = match property.text() {
+                // vCard 4.0 gives phone numbers as `tel:` URIs
+                Some(value) => value.trim_start_matches("tel:").to_string(),
+                None => return Ok(()),
+            };
+            let (kind, label) = property.kind(|kind| match kind {
+                "cell" | "mobile" | "iphone" => Some(phone::Type::Mobile),
+                "home" => Some(phone::Type::Home),
+                "work" => Some(phone::Type::Work),
+                _ => None,
+            });
+            let kind = match (kind, &label) {
+                (Some(kind), _) => kind,
+                (None, Some(_)) => phone::Type::Custom,
+                (None, None) => phone::Type::Mobile,
+            };
+            contact.number.push(Phone {
+                value: Some(value),
+                r#type: Some(kind as i32),
+                label,
+            });
+        }
+        "EMAIL" => {
+            let value = match property.text() {
+                Some(value) => value,
+                None => return Ok(()),
+            };
+            let (kind, label) = property.kind(|kind| match kind {
+                "home" => Some(email::Type::Home),
+                "work" => Some(email::Type::Work),
+                "cell" | "mobile" => Some(email::Type::Mobile),
+                _ => None,
+            });
+            let kind = match (kind, &label) {
+                (Some(kind), _) => kind,
+                (None, Some(_)) => email::Type::Custom,
+                (None, None) => email::Type::Home,
+            };
+            contact.email.push(Email {
+                value: Some(value),
+                r#type: Some(kind as i32),
+                label,
+            });
+        }
+        "ADR" => {
+            let (kind, label) = property.kind(|kind| match kind {
+                "home" => Some(postal_address::Type::Home),
+                "work" => Some(postal_address::Type::Work),
+                _ => None,
+            });
+            let kind = match (kind, &label) {
+                (Some(kind), _) => kind,
+                (None, Some(_)) => postal_address::Type::Custom,
+                (None, None) => postal_address::Type::Home,
+            };
+            let mut components = property.components().into_iter();
+            let mut next = || components.next().flatten();
+            // Extended address carries the neighborhood
+            contact.address.push(PostalAddress {
+                r#type: Some(kind as i32),
+                label,
+                pobox: next(),
+                neighborhood: next(),
+                street: next(),
+                city: next(),
+                region: next(),
+                postcode: next(),
+                country: next(),
+            });
+        }
+        "PHOTO" => card.photo = parse_photo(property)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Reads inline photo, either base64 encoded (2.1 and 3.0) or given by data URI (4.0).
+/// Photos linked by URL are ignored.
+fn parse_photo(property: &Property) -> Result<Option<(Vec<u8>, String)>> {
+    let value: String = property.value.split_whitespace().collect();
+    if let Some(uri) = value.strip_prefix("data:") {
+        let (media_type, data) = uri
+            .split_once(',')
+            .ok_or_else(|| Error::InvalidVCard(String::from("Invalid photo data URI")))?;
+        let content_type = media_type.trim_end_matches(";base64");
+        return Ok(Some((STANDARD.decode(data)?, content_type.to_string())));
+    }
+    match property.param("encoding") {
+        Some("b") | Some("base64") => {
+            let content_type = match property.types().next() {
+                Some(kind) if kind.contains('/') => kind.to_string(),
+                Some(kind) => format!("image/{}", kind),
+                None => String::from("image/jpeg"),
+            };
+            Ok(Some((STANDARD.decode(value)?, content_type)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Writes the contact as vCard 3.0. The photo is expected to be already downloaded.
+pub(crate) fn write_vcard(contact: &Contact, photo: Option<(&[u8], &str)>) -> String {
+    let mut lines = vec![String::from("BEGIN:VCARD"), String::from("VERSION:3.0")];
+    let name = contact.name.clone().unwrap_or_default();
+
+    let components = [
+        &name.family_name,
+        &name.given_name,
+        &name.middle_name,
+        &name.prefix,
+        &name.suffix,
+    ];
+    lines.push(format!("N:{}", join_components(&components)));
+    // FN is mandatory in vCard 3.0
+    let full_name = name
+        .display_name
+        .clone()
+        .or_else(|| {
+            let parts = [
+                &name.prefix,
+                &name.given_name,
+                &name.middle_name,
+                &name.family_name,
+            ];
+            let parts = parts
+                .iter()
+                .filter_map(|part| part.as_deref())
+                .collect::<Vec<_>>();
+            Some(parts.join(" ")).filter(|name| !name.is_empty())
+        })
+        .or_else(|| contact.organization.clone())
+        .or_else(|| contact.number.first().and_then(|phone| phone.value.clone()))
+        .unwrap_or_default();
+    lines.push(format!("FN:{}", escape(&full_name)));
+    if let Some(organization) = &contact.organization {
+        lines.push(format!("ORG:{}", escape(organization)));
+    }
+
+    for phone in &contact.number {
+        let kind = match phone.r#type() {
+            phone::Type::Home => "HOME",
+            phone::Type::Mobile => "CELL",
+            phone::Type::Work => "WORK",
+            phone::Type::Custom => "",
+        };
+        lines.push(format!(
+            "TEL{}:{}",
+            type_param(kind, &phone.label),
+            escape(phone.value())
+        ));
+    }
+    for email in &contact.email {
+        let kind = match email.r#type() {
+            email::Type::Home => "HOME",
+            email::Type::Mobile => "CELL",
+            email::Type::Work => "WORK",
+            email::Type::Custom => "",
+        };
+        lines.push(format!(
+            "EMAIL{}:{}",
+            type_param(kind, &email.label),
+            escape(email.value())
+        ));
+    }
+    for address in &contact.address {
+        let kind = match address.r#type() {
+            postal_address::Type::Home => "HOME",
+            postal_address::Type::Work => "WORK",
+            postal_address::Type::Custom => "",
+        };
+        let components = [
+            &address.pobox,
+            &address.neighborhood,
+            &address.street,
+            &address.city,
+            &address.region,
+            &address.postcode,
+            &address.country,
+        ];
+        lines.push(format!(
+            "ADR{}:{}",
+            type_param(kind, &address.label),
+            join_components(&components)
+        ));
+    }
+    if let Some((data, content_type)) = photo {
+        let kind = content_type.strip_prefix("image/").unwrap_or("jpeg");
+        lines.push(format!(
+            "PHOTO;ENCODING=b;TYPE={}:{}",
+            kind.to_uppercase(),
+            STANDARD.encode(data)
+        ));
+    }
+    lines.push(String::from("END:VCARD"));
+
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+/// `;TYPE=` parameter of the standard type, or of the label for custom types.
+fn type_param(kind: &str, label: &Option<String>) -> String {
+    let kind = match label.as_deref() {
+        Some(label) if kind.is_empty() => label
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == '-')
+            .collect(),
+        _ => kind.to_string(),
+    };
+    if kind.is_empty() {
+        String::new()
+    } else {
+        format!(";TYPE={}", kind)
+    }
+}
+
+fn join_components(components: &[&Option<String>]) -> String {
+    components
+        .iter()
+        .map(|component| component.as_deref().map(escape).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Joins lines continued by a leading space or tab, or by a soft line break
+/// of a quoted-printable value, and drops empty ones.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some(last) = lines.last_mut().filter(|last| has_soft_break(last)) {
+            last.pop();
+            last.push_str(line);
+            continue;
+        }
+        match (line.strip_prefix(&[' ', '\t'][..]), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ if line.trim().is_empty() => {}
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Whether the line is a quoted-printable value ending with `=`, which continues
+/// on the next line. Literal `=` is always encoded in such values.
+fn has_soft_break(line: &str) -> bool {
+    match line.split_once(':') {
+        Some((head, _)) => {
+            line.ends_with('=') && head.to_ascii_lowercase().contains("quoted-printable")
+        }
+        None => false,
+    }
+}
+
+/// Decodes `=XX` escapes of a quoted-printable value, whose soft line breaks
+/// were already removed by unfolding.
+fn decode_quoted_printable(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i + 1..i + 3) {
+            Some(hex) if bytes[i] == b'=' && hex.iter().all(u8::is_ascii_hexdigit) => {
+                std::str::from_utf8(hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Reads the decoded value in the given charset. Charsets other than UTF-8
+/// and Latin-1 are read as UTF-8, replacing invalid sequences.
+fn decode_charset(bytes: Vec<u8>, charset: Option<&str>) -> String {
+    match charset {
+        Some("iso-8859-1") | Some("latin1") => bytes.into_iter().map(char::from).collect(),
+        _ => String::from_utf8(bytes)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned()),
+    }
+}
+
+/// Folds the line into CRLF terminated lines of at most 75 octets.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / MAX_LINE_LEN * 3 + 2);
+    let mut line_len = 0;
+    for c in line.chars() {
+        if line_len + c.len_utf8() > MAX_LINE_LEN {
+            folded.push_str("\r\n ");
+            line_len = 1;
+        }
+        folded.push(c);
+        line_len += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// Splits structured value on semicolons which are not escaped.
+fn split_unescaped(value: &str) -> Vec<&str> {
+    let mut components = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ';' => {
+                components.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    components.push(&value[start..]);
+    components
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_one(text: &str) -> VCard {
+        let mut cards = parse_vcards(text).unwrap();
+        assert_eq!(cards.len(), 1);
+        cards.remove(0)
+    }
+
+    #[test]
+    fn written_vcard_is_read_back() {
+        let contact = Contact {
+            name: Some(Name {
+                given_name: Some(String::from("Jan")),
+                family_name: Some(String::from("Nováček")),
+                prefix: Some(String::from("Ing.")),
+                suffix: None,
+                middle_name: None,
+                display_name: Some(String::from("Jan; \"Honza\", Nováček")),
+            }),
+            number: vec![
+                Phone {
+                    value: Some(String::from("+420123456789")),
+                    r#type: Some(phone::Type::Mobile as i32),
+                    label: None,
+                },
+                Phone {
+                    value: Some(String::from("+420987654321")),
+                    r#type: Some(phone::Type::Custom as i32),
+                    label: Some(String::from("cottage")),
+                },
+            ],
+            email: vec![Email {
+                value: Some(String::from("jan@example.com")),
+                r#type: Some(email::Type::Work as i32),
+                label: None,
+            }],
+            address: vec![PostalAddress {
+                r#type: Some(postal_address::Type::Home as i32),
+                label: None,
+                street: Some(String::from("Dlouhá 1\nbyt 2")),
+                pobox: None,
+                neighborhood: Some(String::from("Staré Město")),
+                city: Some(String::from("Praha")),
+                region: None,
+                postcode: Some(String::from("110 00")),
+                country: Some(String::from("Czechia")),
+            }],
+            avatar: None,
+            organization: Some(String::from("Example, s.r.o.")),
+        };
+        let photo = vec![0xff; 200];
+
+        let text = write_vcard(&contact, Some((&photo, "image/png")));
+        assert!(text.lines().all(|line| line.len() <= MAX_LINE_LEN + 1));
+        let card = parse_one(&text);
+        assert_eq!(card.contact, contact);
+        assert_eq!(card.photo, Some((photo, String::from("image/png"))));
+    }
+
+    #[test]
+    fn quoted_printable_values_are_decoded() {
+        let text = "BEGIN:VCARD\r\n\
+            VERSION:2.1\r\n\
+            N;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:Nov=C3=A1=C4=8Dek;Jan;;;\r\n\
+            ADR;HOME;CHARSET=UTF-8;QUOTED-PRINTABLE:;;Dlouh=C3=A1 1=0D=0A=\r\n\
+            byt 2;Praha;;110 00;\r\n\
+            TEL;CELL:+420123456789\r\n\
+            END:VCARD\r\n";
+        let contact = parse_one(text).contact;
+        let name = contact.name.unwrap();
+        assert_eq!(name.family_name.as_deref(), Some("Nováček"));
+        assert_eq!(name.given_name.as_deref(), Some("Jan"));
+        assert_eq!(contact.address.len(), 1);
+        assert_eq!(contact.address[0].r#type(), postal_address::Type::Home);
+        assert_eq!(contact.address[0].label, None);
+        assert_eq!(
+            contact.address[0].street.as_deref(),
+            Some("Dlouhá 1\r\nbyt 2")
+        );
+        assert_eq!(contact.address[0].city.as_deref(), Some("Praha"));
+        assert_eq!(contact.number[0].r#type(), phone::Type::Mobile);
+    }
+
+    #[test]
+    fn latin1_charset_is_decoded() {
+        let text = "BEGIN:VCARD\n\
+            VERSION:2.1\n\
+            FN;CHARSET=ISO-8859-1;ENCODING=QUOTED-PRINTABLE:Ren=E9e\n\
+            END:VCARD\n";
+        let name = parse_one(text).contact.name.unwrap();
+        assert_eq!(name.display_name.as_deref(), Some("Renée"));
+    }
+
+    #[test]
+    fn invalid_escapes_are_kept() {
+        assert_eq!(decode_quoted_printable("a=3Db=4x=+1="), b"a=b=4x=+1=");
+    }
+
+    #[test]
+    fn folded_lines_are_joined() {
+        let text = "BEGIN:VCARD\nVERSION:3.0\nFN:Jan\n  Nov\n\tacek\nEND:VCARD\n";
+        let name = parse_one(text).contact.name.unwrap();
+        assert_eq!(name.display_name.as_deref(), Some("Jan Novacek"));
+    }
+
+    #[test]
+    fn missing_end_is_rejected() {
+        assert!(parse_vcards("BEGIN:VCARD\nFN:Jan\n").is_err());
+        assert!(parse_vcards("FN:Jan\n").is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+use rand::rngs::OsRng;
+
+use crate::account::AccountManager;
+use crate::common::ApiConfig;
+use crate::error::Result;
+use crate::store::AccountRegistry;
+
+pub fn list_accounts(data_dir: PathBuf) -> Result<()> {
+    let registry = AccountRegistry::load(data_dir)?;
+    for (entry, state_store) in registry.open_all()? {
+        let status = if state_store.is_registered()? {
+            "registered"
+        } else {
+            "not registered"
+        };
+        println!(
+            "{}\t{}\tdevice {}\t{}",
+            entry.uuid,
+            entry.number.as_deref().unwrap_or("-"),
+            entry.device_id,
+            status
+        );
+    }
+    Ok(())
+}
+
+pub fn remove_account(data_dir: PathBuf, account: &str) -> Result<()> {
+    let mut registry = AccountRegistry::load(data_dir)?;
+    let entry = registry.remove(account)?;
+    eprintln!("Removed local data of account {}.", entry.uuid);
+    Ok(())
+}
+
+pub async fn update_attributes(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    account_manager.update_attributes().await?;
+    eprintln!("Account attributes updated.");
+    Ok(())
+}
+
+/// Requests contacts, groups, blocked list and configuration from the primary device.
+/// The data arrive as sync messages, which are processed by `receive`.
+pub async fn request_sync(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    account_manager.request_sync().await?;
+    eprintln!("Requested sync from the primary device.");
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use std::time::SystemTime;
+
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use hmac::Hmac;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::error::{Error, Result};
+use crate::store::TreeDump;
+use crate::utils::serde::{deserialize_byte_vec, serialize_byte_vec};
+
+const MAGIC: &[u8] = b"SDCBACKUP";
+const VERSION: u8 = 1;
+const KDF_ROUNDS: u32 = 200_000;
+/// Round count of the header isn't authenticated until the key is derived,
+/// so crafted archive must not be able to make the derivation run for ages
+const MAX_KDF_ROUNDS: u32 = 10 * KDF_ROUNDS;
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const HEADER_SIZE: usize = MAGIC.len() + 1 + 4 + SALT_SIZE + NONCE_SIZE;
+
+// Archive layout:
+//   magic | version (u8) | kdf rounds (u32 LE) | salt | nonce | AES-256-GCM ciphertext
+// The whole header is authenticated as associated data, so the GCM tag acts as integrity
+// check of both the header and the serialized payload.
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct BackupPayload {
+    created: u64,
+    trees: Vec<BackupTree>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupTree {
+    #[serde(
+        serialize_with = "serialize_byte_vec",
+        deserialize_with = "deserialize_byte_vec"
+    )]
+    name: Vec<u8>,
+    entries: Vec<BackupEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupEntry {
+    #[serde(
+        serialize_with = "serialize_byte_vec",
+        deserialize_with = "deserialize_byte_vec"
+    )]
+    key: Vec<u8>,
+    #[serde(
+        serialize_with = "serialize_byte_vec",
+        deserialize_with = "deserialize_byte_vec"
+    )]
+    value: Vec<u8>,
+}
+
+impl BackupPayload {
+    pub(super) fn new(trees: Vec<TreeDump>) -> Self {
+        Self {
+            created: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_millis() as u64,
+            trees: trees
+                .into_iter()
+                .map(|(name, entries)| BackupTree {
+                    name,
+                    entries: entries
+                        .into_iter()
+                        .map(|(key, value)| BackupEntry { key, value })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    pub(super) fn tree_count(&self) -> usize {
+        self.trees.len()
+    }
+
+    pub(super) fn into_trees(self) -> Vec<TreeDump> {
+        self.trees
+            .into_iter()
+            .map(|tree| {
+                let entries = tree
+                    .entries
+                    .into_iter()
+                    .map(|entry| (entry.key, entry.value))
+                    .collect();
+                (tree.name, entries)
+            })
+            .collect()
+    }
+}
+
+fn cipher(passphrase: &str, salt: &[u8], rounds: u32) -> Aes256Gcm {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, rounds, &mut key);
+    Aes256Gcm::new(Key::from_slice(&key))
+}
+
+pub(super) fn seal(payload: &BackupPayload, passphrase: &str) -> Result<Vec<u8>> {
+    let plaintext = serde_json::to_vec(payload)?;
+
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut archive = Vec::with_capacity(HEADER_SIZE + plaintext.len() + 16);
+    archive.extend_from_slice(MAGIC);
+    archive.push(VERSION);
+    archive.extend_from_slice(&KDF_ROUNDS.to_le_bytes());
+    archive.extend_from_slice(&salt);
+    archive.extend_from_slice(&nonce);
+
+    let ciphertext = cipher(passphrase, &salt, KDF_ROUNDS)
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &plaintext,
+                aad: &archive,
+            },
+        )
+        .map_err(|_| Error::BackupError(String::from("Failed to encrypt backup")))?;
+    archive.extend(ciphertext);
+
+    Ok(archive)
+}
+
+pub(super) fn open(archive: &[u8], passphrase: &str) -> Result<BackupPayload> {
+    if archive.len() < HEADER_SIZE || !archive.starts_with(MAGIC) {
+        return Err(Error::BackupError(String::from("Not a backup archive")));
+    }
+    let (header, ciphertext) = archive.split_at(HEADER_SIZE);
+    let (version, rest) = header[MAGIC.len()..].split_at(1);
+    if version[0] != VERSION {
+        return Err(Error::BackupError(format!(
+            "Unsupported backup version {}",
+            version[0]
+        )));
+    }
+    let (rounds, rest) = rest.split_at(4);
+    let (salt, nonce) = rest.split_at(SALT_SIZE);
+    let rounds = u32::from_le_bytes(rounds.try_into().expect("Slice has 4 bytes"));
+    if rounds == 0 || rounds > MAX_KDF_ROUNDS {
+        return Err(Error::BackupError(format!(
+            "Unsupported number of key derivation rounds {}",
+            rounds
+        )));
+    }
+
+    let plaintext = cipher(passphrase, salt, rounds)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| {
+            Error::BackupError(String::from("Wrong passphrase or the backup is corrupted"))
+        })?;
+
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> BackupPayload {
+        BackupPayload::new(vec![
+            (
+                b"contacts".to_vec(),
+                vec![(b"key".to_vec(), b"value".to_vec())],
+            ),
+            (b"settings".to_vec(), vec![]),
+        ])
+    }
+
+    #[test]
+    fn sealed_archive_opens_with_passphrase() {
+        let archive = seal(&payload(), "correct horse").unwrap();
+        assert!(archive.starts_with(MAGIC));
+
+        let opened = open(&archive, "correct horse").unwrap();
+        assert_eq!(opened.tree_count(), 2);
+        assert_eq!(opened.into_trees(), payload().into_trees());
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let archive = seal(&payload(), "correct horse").unwrap();
+        assert!(matches!(
+            open(&archive, "battery staple"),
+            Err(Error::BackupError(_))
+        ));
+    }
+
+    #[test]
+    fn tampered_archive_is_rejected() {
+        let archive = seal(&payload(), "correct horse").unwrap();
+
+        let mut tampered = archive.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open(&tampered, "correct horse").is_err());
+
+        // Header is authenticated as well
+        let mut tampered = archive.clone();
+        tampered[HEADER_SIZE - 1] ^= 1;
+        assert!(open(&tampered, "correct horse").is_err());
+
+        let mut tampered = archive;
+        tampered[MAGIC.len() + 1..MAGIC.len() + 5]
+            .copy_from_slice(&(MAX_KDF_ROUNDS + 1).to_le_bytes());
+        assert!(open(&tampered, "correct horse").is_err());
+
+        assert!(open(b"SDCBACKUP", "correct horse").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::store::{AccountRegistry, SledStateStore};
+
+mod archive;
+
+const PASSPHRASE_ENV: &str = "SIGNAL_BACKUP_PASSPHRASE";
+
+fn read_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        if passphrase.is_empty() {
+            return Err(Error::BackupError(format!(
+                "Empty passphrase in {}",
+                PASSPHRASE_ENV
+            )));
+        }
+        return Ok(passphrase);
+    }
+
+    let passphrase = rpassword::prompt_password("Backup passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(Error::BackupError(String::from("Empty passphrase")));
+    }
+    if confirm && passphrase != rpassword::prompt_password("Repeat passphrase: ")? {
+        return Err(Error::BackupError(String::from("Passphrases do not match")));
+    }
+    Ok(passphrase)
+}
+
+pub fn backup(data_dir: PathBuf, account: Option<&str>, file: &Path) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    if !state_store.is_registered()? {
+        return Err(Error::Uninitialized);
+    }
+
+    let payload = archive::BackupPayload::new(state_store.export_trees()?);
+    let passphrase = read_passphrase(true)?;
+    let archive = archive::seal(&payload, &passphrase)?;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(file)?.write_all(&archive)?;
+    eprintln!(
+        "Stored {} trees in backup {}.",
+        payload.tree_count(),
+        file.display()
+    );
+
+    Ok(())
+}
+
+pub fn restore(data_dir: PathBuf, file: &Path, force: bool) -> Result<()> {
+    let archive = fs::read(file)?;
+    let passphrase = read_passphrase(false)?;
+    let payload = archive::open(&archive, &passphrase)?;
+
+    let trees = payload.into_trees();
+    let (address, number) = SledStateStore::account_from_dump(&trees)?;
+
+    let mut registry = AccountRegistry::load(data_dir)?;
+    if registry.find(Some(address.name())).is_ok() && !force {
+        return Err(Error::AlreadyRegistered);
+    }
+    let account_dir = registry.add(&address, number.as_deref())?;
+
+    let tree_count = trees.len();
+    SledStateStore::new(account_dir)?.import_trees(trees)?;
+    eprintln!(
+        "Restored {} trees of account {} from backup.",
+        tree_count,
+        address.name()
+    );
+
+    Ok(())
+}
@@ -1,14 +1,27 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
+use base64::engine::{general_purpose::STANDARD, Engine as _};
 use hyper::http::uri::{Authority, PathAndQuery};
+use hyper::StatusCode;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use tokio_rustls::rustls::{ClientConfig, RootCertStore};
 
 use crate::error::{Error, Result};
 
+use super::WebConfig;
+
+#[derive(Clone)]
 pub struct ApiConfig {
     pub user_agent: String,
     pub authority: Authority,
     pub cert_bytes: Box<[u8]>,
+    /// Public key of the server signing sender certificates of sealed sender messages
+    pub trust_root: Box<[u8]>,
+    /// CDNs serving attachments, indexed by `cdnNumber` of the attachment pointer
+    pub cdn_authorities: HashMap<u32, Authority>,
+    /// Client fetching web pages for link previews
+    pub web: WebConfig,
 }
 
 impl ApiConfig {
@@ -35,6 +48,16 @@ impl Default for ApiConfig {
             user_agent: "Signal-Desktop/6.10.1 Linux".to_string(),
             authority: Authority::from_static("textsecure-service.whispersystems.org:443"),
             cert_bytes: Vec::from(cert_bytes).into_boxed_slice(),
+            trust_root: STANDARD
+                .decode("BXu6QIKVz5MA8gstzfOgRQGqyLqOwNKHL6INkv3IHWMF")
+                .expect("Valid base64.")
+                .into_boxed_slice(),
+            cdn_authorities: HashMap::from([
+                (0, Authority::from_static("cdn.signal.org:443")),
+                (2, Authority::from_static("cdn2.signal.org:443")),
+                (3, Authority::from_static("cdn3.signal.org:443")),
+            ]),
+            web: WebConfig::default(),
         }
     }
 }
@@ -44,6 +67,25 @@ pub enum ApiPath<'a> {
     Device {
         provisioning_code: &'a str,
     },
+    Devices,
+    RemoveDevice {
+        device_id: u32,
+    },
+    Account,
+    ProvisioningCode,
+    Provisioning {
+        destination: &'a str,
+    },
+    RequestVerificationCode {
+        transport: &'a str,
+        number: &'a str,
+        captcha: Option<&'a str>,
+    },
+    VerifyAccount {
+        verification_code: &'a str,
+    },
+    DeviceName,
+    AccountAttributes,
     PreKeys,
     SendMessage {
         recipient: &'a str,
@@ -52,15 +94,72 @@ pub enum ApiPath<'a> {
         recipient: &'a str,
         device_id: &'a str,
     },
+    Messages,
+    AcknowledgeMessage {
+        guid: &'a str,
+    },
+    Attachment {
+        identifier: &'a str,
+    },
+    Profile {
+        uuid: &'a str,
+        version: &'a str,
+    },
+    ProfileAvatar {
+        path: &'a str,
+    },
+    SetProfile,
+    ProfileAvatarUpload,
+    AttachmentUploadForm,
+    AttachmentUpload,
+    StickerManifest {
+        pack_id: &'a str,
+    },
+    Sticker {
+        pack_id: &'a str,
+        sticker_id: u32,
+    },
+    StickerPackUploadForm {
+        count: usize,
+    },
+    StickerUpload,
 }
 
 impl<'a> ApiPath<'a> {
-    pub fn get_path(self) -> PathAndQuery {
+    pub fn get_path(&self) -> PathAndQuery {
         match self {
             Self::ProvisioningSocket => PathAndQuery::from_static("/v1/websocket/provisioning/"),
             Self::Device { provisioning_code } => {
                 PathAndQuery::from_str(&format!("/v1/devices/{}", provisioning_code)).unwrap()
             }
+            Self::Devices => PathAndQuery::from_static("/v1/devices/"),
+            Self::RemoveDevice { device_id } => {
+                PathAndQuery::from_str(&format!("/v1/devices/{}", device_id)).unwrap()
+            }
+            Self::Account => PathAndQuery::from_static("/v1/accounts/me"),
+            Self::ProvisioningCode => PathAndQuery::from_static("/v1/devices/provisioning/code"),
+            Self::Provisioning { destination } => PathAndQuery::from_str(&format!(
+                "/v1/provisioning/{}",
+                utf8_percent_encode(destination, NON_ALPHANUMERIC)
+            ))
+            .unwrap(),
+            Self::RequestVerificationCode {
+                transport,
+                number,
+                captcha,
+            } => {
+                let mut path = format!("/v1/accounts/{}/code/{}?client=android", transport, number);
+                if let Some(captcha) = captcha {
+                    path.push_str("&captcha=");
+                    path.extend(utf8_percent_encode(captcha, NON_ALPHANUMERIC));
+                }
+                PathAndQuery::from_str(&path).unwrap()
+            }
+            Self::VerifyAccount { verification_code } => {
+                PathAndQuery::from_str(&format!("/v1/accounts/code/{}", verification_code)).unwrap()
+            }
+            Self::DeviceName => PathAndQuery::from_static("/v1/accounts/name"),
+            Self::AccountAttributes => PathAndQuery::from_static("/v1/accounts/attributes/"),
             Self::PreKeys => PathAndQuery::from_static("/v2/keys/"),
             Self::SendMessage { recipient } => {
                 PathAndQuery::from_str(&format!("/v1/messages/{}", recipient)).unwrap()
@@ -69,6 +168,92 @@ impl<'a> ApiPath<'a> {
                 recipient,
                 device_id,
             } => PathAndQuery::from_str(&format!("/v2/keys/{}/{}", recipient, device_id)).unwrap(),
+            Self::Messages => PathAndQuery::from_static("/v1/messages/"),
+            Self::AcknowledgeMessage { guid } => {
+                PathAndQuery::from_str(&format!("/v1/messages/uuid/{}", guid)).unwrap()
+            }
+            Self::Profile { uuid, version } => {
+                PathAndQuery::from_str(&format!("/v1/profile/{}/{}", uuid, version)).unwrap()
+            }
+            Self::ProfileAvatar { path } => PathAndQuery::from_str(&format!("/{}", path)).unwrap(),
+            Self::SetProfile => PathAndQuery::from_static("/v1/profile/"),
+            Self::ProfileAvatarUpload => PathAndQuery::from_static("/"),
+            Self::AttachmentUploadForm => PathAndQuery::from_static("/v2/attachments/form/upload"),
+            Self::AttachmentUpload => PathAndQuery::from_static("/attachments/"),
+            Self::StickerManifest { pack_id } => {
+                PathAndQuery::from_str(&format!("/stickers/{}/manifest.proto", pack_id)).unwrap()
+            }
+            Self::Sticker {
+                pack_id,
+                sticker_id,
+            } => PathAndQuery::from_str(&format!("/stickers/{}/full/{}", pack_id, sticker_id))
+                .unwrap(),
+            Self::StickerPackUploadForm { count } => {
+                PathAndQuery::from_str(&format!("/v1/sticker/pack/form/{}", count)).unwrap()
+            }
+            Self::StickerUpload => PathAndQuery::from_static("/"),
+            Self::Attachment { identifier } => PathAndQuery::from_str(&format!(
+                "/attachments/{}",
+                utf8_percent_encode(identifier, NON_ALPHANUMERIC)
+            ))
+            .unwrap(),
+        }
+    }
+
+    /// Error meant by the failure status at this endpoint. Rejected credentials
+    /// or verification code are reported by registration, linking and the endpoints
+    /// checking the credentials of a registered device, and linking enforces
+    /// the device limit. Elsewhere the status is kept with the response body.
+    pub(crate) fn status_error(&self, status: StatusCode) -> Option<Error> {
+        match (self, status) {
+            (
+                Self::VerifyAccount { .. }
+                | Self::Device { .. }
+                | Self::AccountAttributes
+                | Self::Messages,
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN,
+            ) => Some(Error::AuthFailed),
+            (Self::Device { .. } | Self::ProvisioningCode, StatusCode::LENGTH_REQUIRED) => {
+                Some(Error::DeviceLimitExceeded)
+            }
+            _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_are_mapped_only_where_they_have_meaning() {
+        let link = ApiPath::Device {
+            provisioning_code: "code",
+        };
+        assert!(matches!(
+            link.status_error(StatusCode::FORBIDDEN),
+            Some(Error::AuthFailed)
+        ));
+        assert!(matches!(
+            link.status_error(StatusCode::LENGTH_REQUIRED),
+            Some(Error::DeviceLimitExceeded)
+        ));
+        assert!(matches!(
+            ApiPath::Messages.status_error(StatusCode::UNAUTHORIZED),
+            Some(Error::AuthFailed)
+        ));
+
+        let profile = ApiPath::Profile {
+            uuid: "uuid",
+            version: "version",
+        };
+        assert!(profile.status_error(StatusCode::UNAUTHORIZED).is_none());
+        assert!(profile.status_error(StatusCode::FORBIDDEN).is_none());
+        assert!(ApiPath::SendMessage { recipient: "uuid" }
+            .status_error(StatusCode::LENGTH_REQUIRED)
+            .is_none());
+        assert!(ApiPath::Messages
+            .status_error(StatusCode::INTERNAL_SERVER_ERROR)
+            .is_none());
+    }
+}
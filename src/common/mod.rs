@@ -1,3 +1,5 @@
 mod api_config;
+mod web_config;
 
 pub use api_config::{ApiConfig, ApiPath};
+pub use web_config::WebConfig;
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use tokio_rustls::rustls::{Certificate, ClientConfig, RootCertStore};
+
+use crate::error::{Error, Result};
+
+/// Configuration of the client fetching arbitrary web pages, e.g. for link previews.
+/// Unlike the Signal servers, the pages are verified against the system root certificates
+/// and only hosts on public addresses are contacted.
+#[derive(Clone)]
+pub struct WebConfig {
+    pub user_agent: String,
+    /// Limit of the whole request including redirects
+    pub timeout: Duration,
+    /// Responses larger than the limit are rejected
+    pub max_size: usize,
+    pub max_redirects: usize,
+}
+
+impl WebConfig {
+    pub(crate) fn rustls_config(&self) -> Result<ClientConfig> {
+        let certs = rustls_native_certs::load_native_certs().map_err(|err| {
+            Error::ConfigError(format!("Failed to load system certificates: {}", err))
+        })?;
+        let certs: Vec<Vec<u8>> = certs.into_iter().map(|Certificate(cert)| cert).collect();
+
+        let mut root_store = RootCertStore::empty();
+        root_store.add_parsable_certificates(&certs);
+
+        Ok(ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store)
+            .with_no_client_auth())
+    }
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            timeout: Duration::from_secs(10),
+            max_size: 2 * 1024 * 1024,
+            max_redirects: 5,
+        }
+    }
+}
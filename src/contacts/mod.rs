@@ -0,0 +1,175 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use libsignal_protocol::IdentityKey;
+use rand::rngs::OsRng;
+use uuid::Uuid;
+
+use crate::account::sync::read_contact_details;
+use crate::account::AccountManager;
+use crate::common::ApiConfig;
+use crate::error::{Error, Result};
+use crate::store::{AccountRegistry, Contact};
+
+pub fn list_contacts(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    for contact in state_store.contacts()? {
+        println!(
+            "{}\t{}\t{}",
+            contact.uuid.as_deref().unwrap_or("-"),
+            contact.number.as_deref().unwrap_or("-"),
+            contact.name.as_deref().unwrap_or("-"),
+        );
+    }
+    Ok(())
+}
+
+pub fn add_contact(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    uuid: Option<&str>,
+    number: Option<&str>,
+    name: Option<&str>,
+) -> Result<()> {
+    let uuid = uuid.map(Uuid::parse_str).transpose()?;
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    let contact = state_store.save_contact(&Contact {
+        uuid: uuid.map(|uuid| uuid.to_string()),
+        number: number.map(str::to_string),
+        name: name.map(str::to_string),
+        ..Default::default()
+    })?;
+    eprintln!("Contact {} saved.", contact.display_name());
+    Ok(())
+}
+
+/// Changes name or phone number of the contact given by UUID, phone number or name.
+pub fn edit_contact(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    contact: &str,
+    name: Option<&str>,
+    number: Option<&str>,
+) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    let mut existing = state_store
+        .find_contact(contact)?
+        .ok_or_else(|| Error::UnknownContact(contact.to_string()))?;
+
+    // Contact known only by the phone number is stored under it
+    state_store.remove_contact(&existing)?;
+    if let Some(name) = name {
+        existing.name = Some(name.to_string());
+    }
+    if let Some(number) = number {
+        existing.number = Some(number.to_string());
+    }
+    let contact = state_store.save_contact(&existing)?;
+    eprintln!("Contact {} saved.", contact.display_name());
+    Ok(())
+}
+
+pub fn remove_contact(data_dir: PathBuf, account: Option<&str>, contact: &str) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    let existing = state_store
+        .find_contact(contact)?
+        .ok_or_else(|| Error::UnknownContact(contact.to_string()))?;
+    state_store.remove_contact(&existing)?;
+    eprintln!("Contact {} removed.", existing.display_name());
+    Ok(())
+}
+
+/// Imports contacts from decrypted contacts sync blob.
+pub fn import_contacts(data_dir: PathBuf, account: Option<&str>, file: &Path) -> Result<()> {
+    let details = read_contact_details(&fs::read(file)?)?;
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    let mut imported = 0;
+    for details in details {
+        if details.uuid.is_none() && details.number.is_none() {
+            continue;
+        }
+        state_store.save_contact(&details.into())?;
+        imported += 1;
+    }
+    eprintln!("Imported {} contacts.", imported);
+    Ok(())
+}
+
+pub fn list_groups(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    for group in state_store.groups()? {
+        println!(
+            "{}\t{}\t{} members{}",
+            STANDARD.encode(&group.id),
+            group.name.as_deref().unwrap_or("-"),
+            group.members.len(),
+            if group.active { "" } else { "\t(left)" }
+        );
+    }
+    Ok(())
+}
+
+/// Blocks or unblocks the contact, or the group given by base64 encoded id when `group` is set.
+pub async fn set_blocked(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    target: &str,
+    group: bool,
+    blocked: bool,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    if group {
+        account_manager
+            .set_group_blocked(&STANDARD.decode(target)?, blocked)
+            .await?;
+    } else {
+        account_manager.set_contact_blocked(target, blocked).await?;
+    }
+    eprintln!(
+        "{} {}.",
+        target,
+        if blocked { "blocked" } else { "unblocked" }
+    );
+    Ok(())
+}
+
+/// Trusts the changed identity key of the contact and starts new sessions with it.
+/// When `identity_key` is given as base64, only that key is trusted.
+pub async fn trust_identity(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    contact: &str,
+    identity_key: Option<&str>,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    let expected = identity_key
+        .map(|key| Ok::<_, Error>(IdentityKey::try_from(&*STANDARD.decode(key)?)?))
+        .transpose()?;
+    let identity_key = account_manager
+        .trust_identity(contact, expected.as_ref())
+        .await?;
+    eprintln!("Identity key of {} is trusted.", contact);
+    println!("{}", STANDARD.encode(identity_key.serialize()));
+    Ok(())
+}
+
+pub fn list_blocked(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    let blocked = state_store.blocked_list()?;
+    for uuid in &blocked.uuids {
+        println!("uuid\t{}", uuid);
+    }
+    for number in &blocked.numbers {
+        println!("number\t{}", number);
+    }
+    for group_id in blocked.group_ids() {
+        println!("group\t{}", STANDARD.encode(group_id));
+    }
+    Ok(())
+}
@@ -1 +1,76 @@
+use std::path::PathBuf;
+use std::time::Duration;
 
+use rand::rngs::OsRng;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use zbus::{Connection, ConnectionBuilder};
+
+use crate::account::AccountManager;
+use crate::common::{ApiConfig, WebConfig};
+use crate::error::Result;
+
+use service::SignalService;
+
+mod service;
+
+pub(crate) const BUS_NAME: &str = "io.github.tm_drtina.SignalDbusClient";
+pub(crate) const INTERFACE_NAME: &str = "io.github.tm_drtina.SignalDbusClient";
+pub(crate) const OBJECT_PATH: &str = "/io/github/tm_drtina/SignalDbusClient";
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the outbox is checked for messages due for retry
+const OUTBOX_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Broadcasts provisioning URL on the session bus, so it can be rendered by other application.
+pub(crate) async fn emit_linking_url(url: &str) -> Result<()> {
+    let connection = Connection::session().await?;
+    connection
+        .emit_signal(
+            None::<&str>,
+            OBJECT_PATH,
+            INTERFACE_NAME,
+            "LinkingUrl",
+            &(url,),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Serves the account on the session bus until interrupted by Ctrl-C.
+pub async fn serve(data_dir: PathBuf, account: Option<&str>, web_config: &WebConfig) -> Result<()> {
+    let api_config = ApiConfig {
+        web: web_config.clone(),
+        ..ApiConfig::default()
+    };
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    let (sender, mut requests) = mpsc::channel(16);
+    let _connection = ConnectionBuilder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, SignalService::new(sender))?
+        .build()
+        .await?;
+    info!(bus_name = BUS_NAME, "serving on the session bus");
+
+    let mut purge = tokio::time::interval(PURGE_INTERVAL);
+    let mut outbox = tokio::time::interval(OUTBOX_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = outbox.tick() => {
+                if let Err(err) = account_manager.flush_outbox(false).await {
+                    warn!(error = %err, "failed to deliver queued messages");
+                }
+            }
+            _ = purge.tick() => {
+                if let Err(err) = account_manager.purge_expired_messages() {
+                    warn!(error = %err, "failed to purge expired messages");
+                }
+            }
+            request = requests.recv() => match request {
+                Some(request) => request.handle(&account_manager).await,
+                None => return Ok(()),
+            },
+            result = tokio::signal::ctrl_c() => return Ok(result?),
+        }
+    }
+}
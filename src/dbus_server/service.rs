@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+
+use libsignal_protocol::IdentityKey;
+use rand::{CryptoRng, Rng};
+use tokio::sync::{mpsc, oneshot};
+use zbus::names::ErrorName;
+use zbus::zvariant::{Array, Fd, OwnedValue};
+use zbus::{dbus_interface, DBusError, Message, MessageBuilder, MessageHeader};
+
+use crate::account::AccountManager;
+use crate::error::{Error, ErrorKind, Result};
+use crate::send::{Attachment, Quote, SendOptions, SendOutcome};
+use crate::store::Profile;
+
+type Reply<T> = oneshot::Sender<Result<T>>;
+type ServiceResult<T> = std::result::Result<T, ServiceError>;
+
+/// Error returned by methods of the interface. It is named by the kind of the failure,
+/// see [`ErrorKind::dbus_name`], and carries the human readable message.
+#[derive(Debug)]
+pub(super) struct ServiceError {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl ServiceError {
+    fn invalid_args(message: String) -> Self {
+        Self {
+            kind: ErrorKind::InvalidInput,
+            message,
+        }
+    }
+}
+
+impl From<Error> for ServiceError {
+    fn from(err: Error) -> Self {
+        Self {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl DBusError for ServiceError {
+    fn create_reply(&self, call: &MessageHeader<'_>) -> zbus::Result<Message> {
+        MessageBuilder::error(call, self.name())?.build(&(self.message.as_str(),))
+    }
+
+    fn name(&self) -> ErrorName<'_> {
+        ErrorName::from_static_str_unchecked(self.kind.dbus_name())
+    }
+
+    fn description(&self) -> Option<&str> {
+        Some(&self.message)
+    }
+}
+
+/// Calls of D-Bus methods forwarded to the task owning the `AccountManager`.
+/// Futures of the signal stores are not `Send`, so they can't be awaited in the interface itself.
+pub(super) enum Request {
+    SendMessage {
+        recipient: String,
+        message: String,
+        options: SendOptions,
+        reply: Reply<SendOutcome>,
+    },
+    SetContactBlocked {
+        recipient: String,
+        blocked: bool,
+        reply: Reply<()>,
+    },
+    SetGroupBlocked {
+        group_id: Vec<u8>,
+        blocked: bool,
+        reply: Reply<()>,
+    },
+    GetProfile {
+        contact: String,
+        reply: Reply<Profile>,
+    },
+    SetExpireTimer {
+        recipient: String,
+        seconds: u32,
+        reply: Reply<SendOutcome>,
+    },
+    SendSticker {
+        recipient: String,
+        pack_id: String,
+        sticker_id: u32,
+        reply: Reply<SendOutcome>,
+    },
+    TrustIdentity {
+        recipient: String,
+        identity_key: Option<IdentityKey>,
+        reply: Reply<IdentityKey>,
+    },
+}
+
+impl Request {
+    pub(super) async fn handle<R: Rng + CryptoRng + Clone>(
+        self,
+        account_manager: &AccountManager<R>,
+    ) {
+        // Caller could have given up waiting, so there may be nobody to reply to
+        let _ = match self {
+            Self::SendMessage {
+                recipient,
+                message,
+                options,
+                reply,
+            } => reply.send(
+                account_manager
+                    .send_message(&recipient, &message, &options)
+                    .await,
+            ),
+            Self::SetContactBlocked {
+                recipient,
+                blocked,
+                reply,
+            } => reply.send(
+                account_manager
+                    .set_contact_blocked(&recipient, blocked)
+                    .await,
+            ),
+            Self::SetGroupBlocked {
+                group_id,
+                blocked,
+                reply,
+            } => reply.send(account_manager.set_group_blocked(&group_id, blocked).await),
+            Self::GetProfile { contact, reply } => {
+                reply.send(account_manager.fetch_profile(&contact).await)
+            }
+            Self::SetExpireTimer {
+                recipient,
+                seconds,
+                reply,
+            } => reply.send(account_manager.set_expire_timer(&recipient, seconds).await),
+            Self::SendSticker {
+                recipient,
+                pack_id,
+                sticker_id,
+                reply,
+            } => reply.send(
+                account_manager
+                    .send_sticker(&recipient, &pack_id, sticker_id, false)
+                    .await,
+            ),
+            Self::TrustIdentity {
+                recipient,
+                identity_key,
+                reply,
+            } => reply.send(
+                account_manager
+                    .trust_identity(&recipient, identity_key.as_ref())
+                    .await,
+            ),
+        };
+    }
+}
+
+pub(super) struct SignalService {
+    requests: mpsc::Sender<Request>,
+}
+
+impl SignalService {
+    pub(super) fn new(requests: mpsc::Sender<Request>) -> Self {
+        Self { requests }
+    }
+
+    async fn call<T, F>(&self, request: F) -> ServiceResult<T>
+    where
+        F: FnOnce(Reply<T>) -> Request,
+    {
+        let (reply, response) = oneshot::channel();
+        let result = match self.requests.send(request(reply)).await {
+            Ok(()) => response.await.unwrap_or(Err(Error::DaemonStopped)),
+            Err(_) => Err(Error::DaemonStopped),
+        };
+        Ok(result?)
+    }
+}
+
+fn send_options(options: &HashMap<String, OwnedValue>) -> ServiceResult<SendOptions> {
+    let flag = |name: &str| {
+        options
+            .get(name)
+            .map(bool::try_from)
+            .transpose()
+            .map(Option::unwrap_or_default)
+            .map_err(|_| ServiceError::invalid_args(format!("Option {} has to be boolean", name)))
+    };
+
+    let string = |name: &str| {
+        options
+            .get(name)
+            .map(|value| String::try_from(value.clone()))
+            .transpose()
+            .map_err(|_| ServiceError::invalid_args(format!("Option {} has to be string", name)))
+    };
+
+    let quote_timestamp = options
+        .get("quoteTimestamp")
+        .map(u64::try_from)
+        .transpose()
+        .map_err(|_| {
+            ServiceError::invalid_args(String::from("Option quoteTimestamp has to be u64"))
+        })?;
+    let quote = match (string("quoteAuthor")?, quote_timestamp) {
+        (Some(author), Some(timestamp)) => Some(Quote { author, timestamp }),
+        (None, None) => None,
+        _ => {
+            return Err(ServiceError::invalid_args(String::from(
+                "Options quoteAuthor and quoteTimestamp have to be set together",
+            )))
+        }
+    };
+
+    let mentions = match options.get("mentions") {
+        Some(value) => Array::try_from(value.clone())
+            .and_then(Vec::<String>::try_from)
+            .map_err(|_| {
+                ServiceError::invalid_args(String::from("Option mentions has to be string array"))
+            })?
+            .iter()
+            .map(|mention| mention.parse().map_err(ServiceError::invalid_args))
+            .collect::<ServiceResult<_>>()?,
+        None => Vec::new(),
+    };
+
+    let attachments = match options.get("attachments") {
+        Some(value) => Array::try_from(value.clone())
+            .and_then(Vec::<(String, Fd)>::try_from)
+            .map_err(|_| {
+                ServiceError::invalid_args(String::from(
+                    "Option attachments has to be array of file name and descriptor pairs",
+                ))
+            })?
+            .into_iter()
+            .map(|(file_name, fd)| {
+                Ok(Attachment {
+                    file_name: Some(file_name).filter(|name| !name.is_empty()),
+                    data: read_fd(fd)?,
+                })
+            })
+            .collect::<ServiceResult<_>>()?,
+        None => Vec::new(),
+    };
+    let contacts = match options.get("contacts") {
+        Some(value) => Array::try_from(value.clone())
+            .and_then(Vec::<String>::try_from)
+            .map_err(|_| {
+                ServiceError::invalid_args(String::from("Option contacts has to be string array"))
+            })?,
+        None => Vec::new(),
+    };
+
+    Ok(SendOptions {
+        allow_blocked: flag("allowBlocked")?,
+        quote,
+        mentions,
+        markdown: flag("markdown")?,
+        link_preview: flag("linkPreview")?,
+        contacts,
+        attachments,
+        view_once: flag("viewOnce")?,
+    })
+}
+
+/// Reply of methods sending a message. Id of sent messages is zero.
+fn queued_reply(outcome: SendOutcome) -> (bool, u64) {
+    match outcome {
+        SendOutcome::Sent => (false, 0),
+        SendOutcome::Queued { id } => (true, id),
+    }
+}
+
+/// Reads the regular file passed by the caller. Files are passed as descriptors,
+/// so the daemon reads only files the caller can read itself.
+fn read_fd(fd: Fd) -> ServiceResult<Vec<u8>> {
+    // SAFETY: descriptors passed with the message stay open until the method call returns
+    let fd = unsafe { BorrowedFd::borrow_raw(fd.as_raw_fd()) };
+    let mut file = File::from(fd.try_clone_to_owned().map_err(Error::from)?);
+    if !file.metadata().map_err(Error::from)?.is_file() {
+        return Err(ServiceError::invalid_args(String::from(
+            "Attachments have to be regular files",
+        )));
+    }
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).map_err(Error::from)?;
+    Ok(data)
+}
+
+#[dbus_interface(name = "io.github.tm_drtina.SignalDbusClient")]
+impl SignalService {
+    /// Sends text message to the recipient given by phone number, UUID or contact name.
+    /// Supported options: `allowBlocked` (b), `quoteAuthor` (s) with `quoteTimestamp` (t)
+    /// replying to the message, `mentions` (as) in `start:length:recipient` format
+    /// `markdown` (b) styling the text, `linkPreview` (b) attaching preview of the first link
+    /// `contacts` (as) sharing contacts given as vCard text, `attachments` (a(sh)) attaching
+    /// files given by name and open descriptor, and `viewOnce` (b) for a single attachment.
+    /// Returns whether the message was only queued for retry and its id in the queue.
+    async fn send_message(
+        &self,
+        recipient: String,
+        message: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> ServiceResult<(bool, u64)> {
+        let options = send_options(&options)?;
+        self.call(|reply| Request::SendMessage {
+            recipient,
+            message,
+            options,
+            reply,
+        })
+        .await
+        .map(queued_reply)
+    }
+
+    /// Sends sticker of an installed pack given by its hex encoded id.
+    /// Returns whether the message was only queued for retry and its id in the queue.
+    async fn send_sticker(
+        &self,
+        recipient: String,
+        pack_id: String,
+        sticker_id: u32,
+    ) -> ServiceResult<(bool, u64)> {
+        self.call(|reply| Request::SendSticker {
+            recipient,
+            pack_id,
+            sticker_id,
+            reply,
+        })
+        .await
+        .map(queued_reply)
+    }
+
+    /// Fetches the profile of the contact. Returned fields are `name`, `givenName`,
+    /// `familyName`, `about` and `aboutEmoji`; unset fields are omitted.
+    async fn get_profile(&self, contact: String) -> ServiceResult<HashMap<String, String>> {
+        let profile = self
+            .call(|reply| Request::GetProfile { contact, reply })
+            .await?;
+        let fields = [
+            ("name", profile.display_name()),
+            ("givenName", profile.given_name),
+            ("familyName", profile.family_name),
+            ("about", profile.about),
+            ("aboutEmoji", profile.about_emoji),
+        ];
+        Ok(fields
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.to_string(), value?)))
+            .collect())
+    }
+
+    /// Sets disappearing messages timer of the conversation in seconds, zero turns it off.
+    /// Returns whether the update was only queued for retry and its id in the queue.
+    async fn set_expire_timer(
+        &self,
+        recipient: String,
+        seconds: u32,
+    ) -> ServiceResult<(bool, u64)> {
+        self.call(|reply| Request::SetExpireTimer {
+            recipient,
+            seconds,
+            reply,
+        })
+        .await
+        .map(queued_reply)
+    }
+
+    /// Trusts the changed identity key of the recipient, so messages can be exchanged again.
+    /// When `identity_key` isn't empty, only that serialized key is trusted.
+    /// Returns the trusted key.
+    async fn trust_identity(
+        &self,
+        recipient: String,
+        identity_key: Vec<u8>,
+    ) -> ServiceResult<Vec<u8>> {
+        let identity_key =
+            match identity_key.as_slice() {
+                [] => None,
+                bytes => Some(IdentityKey::try_from(bytes).map_err(|_| {
+                    ServiceError::invalid_args(String::from("Invalid identity key"))
+                })?),
+            };
+        let trusted = self
+            .call(|reply| Request::TrustIdentity {
+                recipient,
+                identity_key,
+                reply,
+            })
+            .await?;
+        Ok(trusted.serialize().into_vec())
+    }
+
+    async fn block(&self, recipient: String) -> ServiceResult<()> {
+        self.call(|reply| Request::SetContactBlocked {
+            recipient,
+            blocked: true,
+            reply,
+        })
+        .await
+    }
+
+    async fn unblock(&self, recipient: String) -> ServiceResult<()> {
+        self.call(|reply| Request::SetContactBlocked {
+            recipient,
+            blocked: false,
+            reply,
+        })
+        .await
+    }
+
+    async fn block_group(&self, group_id: Vec<u8>) -> ServiceResult<()> {
+        self.call(|reply| Request::SetGroupBlocked {
+            group_id,
+            blocked: true,
+            reply,
+        })
+        .await
+    }
+
+    async fn unblock_group(&self, group_id: Vec<u8>) -> ServiceResult<()> {
+        self.call(|reply| Request::SetGroupBlocked {
+            group_id,
+            blocked: false,
+            reply,
+        })
+        .await
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use rand::rngs::OsRng;
+
+use crate::account::AccountManager;
+use crate::common::ApiConfig;
+use crate::error::Result;
+use crate::store::AccountRegistry;
+
+// Days since epoch to civil date, see http://howardhinnant.github.io/date_algorithms.html
+fn format_date(timestamp_millis: u64) -> String {
+    let days = (timestamp_millis / 86_400_000) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub async fn list_devices(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+    let own_device_id = account_manager.device_id()?;
+
+    for device in account_manager.list_devices().await? {
+        println!(
+            "{}\t{}\tcreated {}\tlast seen {}{}",
+            device.id,
+            device.name.as_deref().unwrap_or("-"),
+            format_date(device.created),
+            format_date(device.last_seen),
+            if device.id == own_device_id {
+                "\t(this device)"
+            } else {
+                ""
+            }
+        );
+    }
+    Ok(())
+}
+
+pub async fn remove_device(data_dir: PathBuf, account: Option<&str>, device_id: u32) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    account_manager.remove_device(device_id).await?;
+    eprintln!("Device {} removed.", device_id);
+    Ok(())
+}
+
+pub async fn link_device(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    provisioning_url: &str,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let mut account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    account_manager.link_device(provisioning_url).await?;
+    eprintln!("Provisioning message sent to the new device.");
+    Ok(())
+}
+
+pub async fn rename_device(data_dir: PathBuf, account: Option<&str>, name: &str) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let mut account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    account_manager.rename_device(name).await?;
+    eprintln!("Device renamed.");
+    Ok(())
+}
+
+pub async fn unregister(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let mut registry = AccountRegistry::load(data_dir)?;
+    let uuid = registry.find(account)?.uuid.clone();
+
+    let account_manager =
+        AccountManager::with_store(registry.open(Some(&uuid))?, OsRng, &api_config)?;
+    account_manager.unregister().await?;
+    eprintln!("Device unregistered.");
+    // Store has to be closed before its data are removed
+    drop(account_manager);
+
+    registry.remove(&uuid)?;
+    eprintln!("Removed local data of account {}.", uuid);
+    Ok(())
+}
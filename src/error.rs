@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use hyper::StatusCode;
 use tokio_tungstenite::tungstenite;
 
@@ -7,6 +9,7 @@ pub enum Error {
     SignalCryptoError(signal_provisioning_api::SignalCryptoError),
     SocketError(tungstenite::Error),
     HttpParserError(tungstenite::http::Error),
+    /// Server answered with a status that has no dedicated variant, carries the response body
     HttpError(StatusCode, String),
     DeprecatedHttpError(String),
     IoError(std::io::Error),
@@ -14,12 +17,189 @@ pub enum Error {
     HyperError(hyper::Error),
     SledError(sled::Error),
     UuidParsingError(uuid::Error),
+    DbusError(zbus::Error),
+    Base64Error(base64::DecodeError),
+    ProtobufError(prost::DecodeError),
 
     ProvisioningFailed,
+    ProvisioningTimeout,
+    ProvisioningCancelled,
     ConfigError(String),
     EmptyResponse,
     ConnectionError(String),
     Uninitialized,
+    AlreadyRegistered,
+    UnknownAccount(String),
+    AccountSelectionRequired,
+    BackupError(String),
+    InvalidDeviceName,
+    CaptchaRequired,
+    InvalidProvisioningUrl(String),
+    NotPrimaryDevice,
+    ImageError(String),
+    UnknownContact(String),
+    AmbiguousContact(String),
+    InvalidContact(String),
+    InvalidEnvelope(String),
+    AttachmentError(String),
+    BlockedRecipient(String),
+    DaemonStopped,
+    MissingProfileKey(String),
+    InvalidProfile,
+    InvalidMention(String),
+    LinkPreviewError(String),
+    StickerError(String),
+    InvalidVCard(String),
+    InvalidViewOnce(String),
+    /// Server refused the request for exceeding its rate limit, with the delay it asked for
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    UnknownQueuedMessage(u64),
+    UnknownInboxMessage(u64),
+    /// Identity key of the address changed from the one we trust, carries the new key
+    UntrustedIdentity {
+        address: libsignal_protocol::ProtocolAddress,
+        identity_key: libsignal_protocol::IdentityKey,
+    },
+    /// Identity key published by the recipient isn't the one the user expected
+    IdentityKeyMismatch(String),
+    /// Device list of the recipient kept changing between send attempts
+    TooManySendAttempts(String),
+    /// Recipient has no Signal account
+    UnregisteredRecipient(String),
+    /// Account already has the maximum number of linked devices
+    DeviceLimitExceeded,
+    /// Server rejected our credentials or the verification code
+    AuthFailed,
+}
+
+/// Broad category of an [`Error`]. Unlike the variants of the error, the categories,
+/// their exit codes and D-Bus error names are stable, so scripts can rely on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Bug, misconfiguration or failure of the local environment
+    Internal = 1,
+    /// Arguments or input files are invalid, shares the exit code with usage errors of the CLI
+    InvalidInput = 2,
+    /// Account is missing, ambiguous or can't perform the operation
+    Account = 3,
+    AuthFailed = 4,
+    /// Server can't be reached, retrying later may help
+    Network = 5,
+    RateLimited = 6,
+    /// Server rejected the request or failed to process it
+    Server = 7,
+    UnregisteredRecipient = 8,
+    UntrustedIdentity = 9,
+    DeviceLimitExceeded = 10,
+    BlockedRecipient = 11,
+    /// Contact, message or other local record doesn't exist
+    NotFound = 12,
+    /// Local database or files can't be read or written
+    Storage = 13,
+    /// Data received from the server or other devices is malformed or can't be decrypted
+    Protocol = 14,
+    /// Linking this device failed or was interrupted
+    Provisioning = 15,
+    CaptchaRequired = 16,
+}
+
+impl ErrorKind {
+    /// Exit code of the CLI when a command fails with the error of this kind.
+    pub fn exit_code(self) -> i32 {
+        self as i32
+    }
+
+    /// Name of the D-Bus error returned by the service for errors of this kind.
+    pub fn dbus_name(self) -> &'static str {
+        match self {
+            Self::Internal => "io.github.tm_drtina.SignalDbusClient.Error.Internal",
+            Self::InvalidInput => "io.github.tm_drtina.SignalDbusClient.Error.InvalidInput",
+            Self::Account => "io.github.tm_drtina.SignalDbusClient.Error.Account",
+            Self::AuthFailed => "io.github.tm_drtina.SignalDbusClient.Error.AuthFailed",
+            Self::Network => "io.github.tm_drtina.SignalDbusClient.Error.Network",
+            Self::RateLimited => "io.github.tm_drtina.SignalDbusClient.Error.RateLimited",
+            Self::Server => "io.github.tm_drtina.SignalDbusClient.Error.Server",
+            Self::UnregisteredRecipient => {
+                "io.github.tm_drtina.SignalDbusClient.Error.UnregisteredRecipient"
+            }
+            Self::UntrustedIdentity => {
+                "io.github.tm_drtina.SignalDbusClient.Error.UntrustedIdentity"
+            }
+            Self::DeviceLimitExceeded => {
+                "io.github.tm_drtina.SignalDbusClient.Error.DeviceLimitExceeded"
+            }
+            Self::BlockedRecipient => "io.github.tm_drtina.SignalDbusClient.Error.BlockedRecipient",
+            Self::NotFound => "io.github.tm_drtina.SignalDbusClient.Error.NotFound",
+            Self::Storage => "io.github.tm_drtina.SignalDbusClient.Error.Storage",
+            Self::Protocol => "io.github.tm_drtina.SignalDbusClient.Error.Protocol",
+            Self::Provisioning => "io.github.tm_drtina.SignalDbusClient.Error.Provisioning",
+            Self::CaptchaRequired => "io.github.tm_drtina.SignalDbusClient.Error.CaptchaRequired",
+        }
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::SignalProtocolError(
+                libsignal_protocol::SignalProtocolError::UntrustedIdentity(_),
+            )
+            | Self::UntrustedIdentity { .. }
+            | Self::IdentityKeyMismatch(_) => ErrorKind::UntrustedIdentity,
+            Self::SignalProtocolError(_)
+            | Self::SignalCryptoError(_)
+            | Self::SerdeError(_)
+            | Self::Base64Error(_)
+            | Self::ProtobufError(_)
+            | Self::EmptyResponse
+            | Self::InvalidEnvelope(_)
+            | Self::InvalidDeviceName
+            | Self::InvalidProfile
+            | Self::AttachmentError(_) => ErrorKind::Protocol,
+            Self::SocketError(_)
+            | Self::HyperError(_)
+            | Self::ConnectionError(_)
+            | Self::LinkPreviewError(_) => ErrorKind::Network,
+            Self::HttpError(status, _) if *status == StatusCode::NOT_FOUND => ErrorKind::NotFound,
+            Self::HttpError(..) | Self::DeprecatedHttpError(_) | Self::TooManySendAttempts(_) => {
+                ErrorKind::Server
+            }
+            Self::IoError(_) | Self::SledError(_) | Self::BackupError(_) => ErrorKind::Storage,
+            Self::HttpParserError(_)
+            | Self::DbusError(_)
+            | Self::ConfigError(_)
+            | Self::ImageError(_)
+            | Self::DaemonStopped => ErrorKind::Internal,
+            Self::UuidParsingError(_)
+            | Self::InvalidProvisioningUrl(_)
+            | Self::AmbiguousContact(_)
+            | Self::InvalidContact(_)
+            | Self::InvalidMention(_)
+            | Self::StickerError(_)
+            | Self::InvalidVCard(_)
+            | Self::InvalidViewOnce(_) => ErrorKind::InvalidInput,
+            Self::ProvisioningFailed | Self::ProvisioningTimeout | Self::ProvisioningCancelled => {
+                ErrorKind::Provisioning
+            }
+            Self::Uninitialized
+            | Self::AlreadyRegistered
+            | Self::UnknownAccount(_)
+            | Self::AccountSelectionRequired
+            | Self::NotPrimaryDevice => ErrorKind::Account,
+            Self::UnknownContact(_)
+            | Self::MissingProfileKey(_)
+            | Self::UnknownQueuedMessage(_)
+            | Self::UnknownInboxMessage(_) => ErrorKind::NotFound,
+            Self::CaptchaRequired => ErrorKind::CaptchaRequired,
+            Self::BlockedRecipient(_) => ErrorKind::BlockedRecipient,
+            Self::RateLimited { .. } => ErrorKind::RateLimited,
+            Self::UnregisteredRecipient(_) => ErrorKind::UnregisteredRecipient,
+            Self::DeviceLimitExceeded => ErrorKind::DeviceLimitExceeded,
+            Self::AuthFailed => ErrorKind::AuthFailed,
+        }
+    }
 }
 
 impl From<signal_provisioning_api::Error> for Error {
@@ -76,11 +256,213 @@ impl From<sled::Error> for Error {
     }
 }
 
+impl From<uuid::Error> for Error {
+    fn from(err: uuid::Error) -> Self {
+        Self::UuidParsingError(err)
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(err: base64::DecodeError) -> Self {
+        Self::Base64Error(err)
+    }
+}
+
+impl From<prost::DecodeError> for Error {
+    fn from(err: prost::DecodeError) -> Self {
+        Self::ProtobufError(err)
+    }
+}
+
+impl From<zbus::Error> for Error {
+    fn from(err: zbus::Error) -> Self {
+        Self::DbusError(err)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&format!("{:?}", self))
+        match self {
+            Self::SignalProtocolError(err) => write!(f, "Signal protocol error: {}", err),
+            Self::SignalCryptoError(err) => write!(f, "Signal crypto error: {:?}", err),
+            Self::SocketError(err) => write!(f, "WebSocket error: {}", err),
+            Self::HttpParserError(err) => write!(f, "Invalid HTTP request: {}", err),
+            Self::HttpError(status, body) if body.is_empty() => {
+                write!(f, "Server responded with {}", status)
+            }
+            Self::HttpError(status, body) => {
+                write!(f, "Server responded with {}: {}", status, body)
+            }
+            Self::DeprecatedHttpError(_) => {
+                f.write_str("Server no longer supports this version of the client")
+            }
+            Self::IoError(err) => write!(f, "I/O error: {}", err),
+            Self::SerdeError(err) => write!(f, "Invalid JSON: {}", err),
+            Self::HyperError(err) => write!(f, "HTTP error: {}", err),
+            Self::SledError(err) => write!(f, "Database error: {}", err),
+            Self::UuidParsingError(err) => write!(f, "Invalid UUID: {}", err),
+            Self::DbusError(err) => write!(f, "D-Bus error: {}", err),
+            Self::Base64Error(err) => write!(f, "Invalid base64: {}", err),
+            Self::ProtobufError(err) => write!(f, "Invalid protobuf message: {}", err),
+            Self::ProvisioningFailed => f.write_str("Linking the device failed"),
+            Self::ProvisioningTimeout => f.write_str("Device was not linked in time"),
+            Self::ProvisioningCancelled => f.write_str("Linking the device was cancelled"),
+            Self::ConfigError(reason) => write!(f, "Configuration error: {}", reason),
+            Self::EmptyResponse => f.write_str("Server sent an empty response"),
+            Self::ConnectionError(reason) => write!(f, "Connection error: {}", reason),
+            Self::Uninitialized => f.write_str("No account is registered"),
+            Self::AlreadyRegistered => f.write_str("Account is already registered"),
+            Self::UnknownAccount(account) => write!(f, "Unknown account {}", account),
+            Self::AccountSelectionRequired => {
+                f.write_str("Multiple accounts are registered, select one with --account")
+            }
+            Self::BackupError(reason) => write!(f, "Backup error: {}", reason),
+            Self::InvalidDeviceName => f.write_str("Device name can't be decrypted"),
+            Self::CaptchaRequired => f.write_str("Server requires solving a captcha"),
+            Self::InvalidProvisioningUrl(url) => write!(f, "Invalid provisioning URL {}", url),
+            Self::NotPrimaryDevice => f.write_str("Only the primary device can do this"),
+            Self::ImageError(reason) => write!(f, "Image error: {}", reason),
+            Self::UnknownContact(contact) => write!(f, "Unknown contact {}", contact),
+            Self::AmbiguousContact(contact) => {
+                write!(f, "{} matches multiple contacts", contact)
+            }
+            Self::InvalidContact(reason) => write!(f, "Invalid contact: {}", reason),
+            Self::InvalidEnvelope(reason) => write!(f, "Invalid envelope: {}", reason),
+            Self::AttachmentError(reason) => write!(f, "Attachment error: {}", reason),
+            Self::BlockedRecipient(recipient) => write!(f, "{} is blocked", recipient),
+            Self::DaemonStopped => f.write_str("Daemon is stopped"),
+            Self::MissingProfileKey(contact) => write!(f, "Profile key of {} is unknown", contact),
+            Self::InvalidProfile => f.write_str("Profile can't be encrypted or decrypted"),
+            Self::InvalidMention(reason) => write!(f, "Invalid mention: {}", reason),
+            Self::LinkPreviewError(reason) => write!(f, "Link preview failed: {}", reason),
+            Self::StickerError(reason) => write!(f, "Sticker error: {}", reason),
+            Self::InvalidVCard(reason) => write!(f, "Invalid vCard: {}", reason),
+            Self::InvalidViewOnce(reason) => write!(f, "Invalid view-once message: {}", reason),
+            Self::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "Rate limited by the server, retry after {} seconds",
+                retry_after.as_secs()
+            ),
+            Self::RateLimited { retry_after: None } => f.write_str("Rate limited by the server"),
+            Self::UnknownQueuedMessage(id) => write!(f, "No queued message with id {}", id),
+            Self::UnknownInboxMessage(id) => write!(f, "No received message with id {}", id),
+            Self::UntrustedIdentity { address, .. } => {
+                write!(f, "Identity key of {} changed and is not trusted", address)
+            }
+            Self::IdentityKeyMismatch(recipient) => {
+                write!(f, "Identity key of {} is not the expected one", recipient)
+            }
+            Self::TooManySendAttempts(recipient) => write!(
+                f,
+                "Devices of {} kept changing while sending the message",
+                recipient
+            ),
+            Self::UnregisteredRecipient(recipient) => {
+                write!(f, "{} is not registered with Signal", recipient)
+            }
+            Self::DeviceLimitExceeded => f.write_str("Account has too many linked devices"),
+            Self::AuthFailed => f.write_str("Authorization failed"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::SignalProtocolError(err) => Some(err),
+            Self::SocketError(err) => Some(err),
+            Self::HttpParserError(err) => Some(err),
+            Self::IoError(err) => Some(err),
+            Self::SerdeError(err) => Some(err),
+            Self::HyperError(err) => Some(err),
+            Self::SledError(err) => Some(err),
+            Self::UuidParsingError(err) => Some(err),
+            Self::DbusError(err) => Some(err),
+            Self::Base64Error(err) => Some(err),
+            Self::ProtobufError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exit codes and D-Bus names are documented, so scripts rely on them.
+    #[test]
+    fn error_kinds_are_stable() {
+        let kinds = [
+            (ErrorKind::Internal, 1, "Internal"),
+            (ErrorKind::InvalidInput, 2, "InvalidInput"),
+            (ErrorKind::Account, 3, "Account"),
+            (ErrorKind::AuthFailed, 4, "AuthFailed"),
+            (ErrorKind::Network, 5, "Network"),
+            (ErrorKind::RateLimited, 6, "RateLimited"),
+            (ErrorKind::Server, 7, "Server"),
+            (ErrorKind::UnregisteredRecipient, 8, "UnregisteredRecipient"),
+            (ErrorKind::UntrustedIdentity, 9, "UntrustedIdentity"),
+            (ErrorKind::DeviceLimitExceeded, 10, "DeviceLimitExceeded"),
+            (ErrorKind::BlockedRecipient, 11, "BlockedRecipient"),
+            (ErrorKind::NotFound, 12, "NotFound"),
+            (ErrorKind::Storage, 13, "Storage"),
+            (ErrorKind::Protocol, 14, "Protocol"),
+            (ErrorKind::Provisioning, 15, "Provisioning"),
+            (ErrorKind::CaptchaRequired, 16, "CaptchaRequired"),
+        ];
+        for (kind, code, name) in kinds {
+            assert_eq!(kind.exit_code(), code);
+            assert_eq!(
+                kind.dbus_name(),
+                format!("io.github.tm_drtina.SignalDbusClient.Error.{}", name)
+            );
+        }
+    }
+
+    #[test]
+    fn errors_map_to_kinds() {
+        let errors = [
+            (
+                Error::HttpError(StatusCode::UNAUTHORIZED, String::new()),
+                ErrorKind::Server,
+            ),
+            (
+                Error::HttpError(StatusCode::NOT_FOUND, String::new()),
+                ErrorKind::NotFound,
+            ),
+            (
+                Error::HttpError(StatusCode::INTERNAL_SERVER_ERROR, String::new()),
+                ErrorKind::Server,
+            ),
+            (Error::AuthFailed, ErrorKind::AuthFailed),
+            (Error::DeviceLimitExceeded, ErrorKind::DeviceLimitExceeded),
+            (
+                Error::IdentityKeyMismatch(String::from("alice")),
+                ErrorKind::UntrustedIdentity,
+            ),
+            (
+                Error::RateLimited { retry_after: None },
+                ErrorKind::RateLimited,
+            ),
+            (
+                Error::TooManySendAttempts(String::from("alice")),
+                ErrorKind::Server,
+            ),
+            (
+                Error::UnregisteredRecipient(String::from("alice")),
+                ErrorKind::UnregisteredRecipient,
+            ),
+            (Error::UnknownQueuedMessage(1), ErrorKind::NotFound),
+            (Error::CaptchaRequired, ErrorKind::CaptchaRequired),
+            (Error::ProvisioningTimeout, ErrorKind::Provisioning),
+            (Error::InvalidDeviceName, ErrorKind::Protocol),
+        ];
+        for (err, kind) in errors {
+            assert_eq!(err.kind(), kind, "{:?}", err);
+        }
     }
 }
-impl std::error::Error for Error {}
@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+
+use rand::rngs::OsRng;
+
+use crate::account::AccountManager;
+use crate::common::ApiConfig;
+use crate::error::{Error, Result};
+use crate::receive::print_messages;
+use crate::store::AccountRegistry;
+
+/// Lists received messages which were not handed over yet, mostly those which failed
+/// to be decrypted or processed.
+pub fn list_inbox(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    for entry in state_store.inbox()? {
+        let state = if entry.processed {
+            "processed"
+        } else if entry.message.is_some() {
+            "decrypted"
+        } else {
+            "encrypted"
+        };
+        let (sender, timestamp) = match &entry.message {
+            Some(message) => (message.sender.as_str(), message.timestamp.to_string()),
+            None => ("-", String::from("-")),
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            entry.id,
+            entry.guid,
+            state,
+            sender,
+            timestamp,
+            entry.attempts,
+            entry.last_error.as_deref().unwrap_or("-"),
+        );
+    }
+    Ok(())
+}
+
+/// Processes all messages of the inbox again, including those which were given up,
+/// and prints them like `receive` does.
+pub async fn retry_inbox(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    contacts_dir: Option<&Path>,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    let messages = account_manager.process_inbox(true).await?;
+    print_messages(&account_manager, messages, contacts_dir).await
+}
+
+/// Removes the message from the inbox without processing it.
+pub fn drop_inbox_message(data_dir: PathBuf, account: Option<&str>, id: u64) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    if !state_store.remove_inbox_entry(id)? {
+        return Err(Error::UnknownInboxMessage(id));
+    }
+    eprintln!("Received message {} dropped.", id);
+    Ok(())
+}
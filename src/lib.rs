@@ -1,11 +1,45 @@
 mod account;
+mod accounts;
+mod backup;
 mod common;
+mod contacts;
 mod dbus_server;
+mod devices;
 pub mod error;
+mod inbox;
+mod logging;
+mod profiles;
+mod proto;
+mod queue;
+mod receive;
 mod register;
 mod send;
+mod stickers;
 mod store;
 mod utils;
+mod view_once;
 
-pub use register::register;
-pub use send::send_message;
+pub use accounts::{list_accounts, remove_account, request_sync, update_attributes};
+pub use backup::{backup, restore};
+pub use common::WebConfig;
+pub use contacts::{
+    add_contact, edit_contact, import_contacts, list_blocked, list_contacts, list_groups,
+    remove_contact, set_blocked, trust_identity,
+};
+pub use dbus_server::serve;
+pub use devices::{link_device, list_devices, remove_device, rename_device, unregister};
+pub use inbox::{drop_inbox_message, list_inbox, retry_inbox};
+pub use logging::{init_logging, LogFormat, LoggingOptions};
+pub use profiles::{set_profile, show_profile};
+pub use queue::{drop_queued, flush_queue, list_queue};
+pub use receive::receive;
+pub use register::{register, register_primary, LinkingOptions};
+pub use send::{
+    send_message, set_expire_timer, Attachment, Mention, Quote, SendOptions, SendOutcome,
+};
+pub use stickers::{
+    install_sticker_pack, list_sticker_packs, remove_sticker_pack, send_sticker,
+    upload_sticker_pack,
+};
+pub use utils::QrCodeFormat;
+pub use view_once::{list_view_once, open_view_once};
@@ -0,0 +1,54 @@
+use tracing_subscriber::EnvFilter;
+
+mod redact;
+
+pub(crate) use redact::Secret;
+use redact::{set_redaction, RedactingWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, for log aggregators
+    Json,
+}
+
+/// Controls what is logged to stderr and how.
+#[derive(Debug, Clone)]
+pub struct LoggingOptions {
+    /// Raises the level of our logs from `info` to `debug` and `trace`, and of dependencies
+    /// from `warn`. Ignored when `RUST_LOG` is set.
+    pub verbosity: u8,
+    pub format: LogFormat,
+    /// Masks phone numbers, UUIDs, authorization headers and message bodies
+    pub redact: bool,
+}
+
+impl Default for LoggingOptions {
+    fn default() -> Self {
+        Self {
+            verbosity: 0,
+            format: LogFormat::Text,
+            redact: true,
+        }
+    }
+}
+
+/// Installs the global subscriber. Has to be called at most once.
+pub fn init_logging(options: &LoggingOptions) {
+    set_redaction(options.redact);
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(match options.verbosity {
+            0 => "warn,signal_dbus_client=info",
+            1 => "warn,signal_dbus_client=debug",
+            2 => "info,signal_dbus_client=trace",
+            _ => "trace",
+        })
+    });
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(RedactingWriter(std::io::stderr));
+    match options.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Whether logs are redacted, set once when the logging is initialized.
+static REDACT: AtomicBool = AtomicBool::new(true);
+
+const MASK: &str = "[redacted]";
+
+pub(super) fn set_redaction(enabled: bool) {
+    REDACT.store(enabled, Ordering::Relaxed);
+}
+
+fn is_redacted() -> bool {
+    REDACT.load(Ordering::Relaxed)
+}
+
+/// Value which is logged only when redaction is turned off, e.g. message bodies.
+pub(crate) struct Secret<T>(pub(crate) T);
+
+impl<T: fmt::Display> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_redacted() {
+            f.write_str(MASK)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_redacted() {
+            f.write_str(MASK)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+/// Writer of formatted log lines which masks phone numbers, UUIDs and credentials
+/// of authorization headers, wherever they appear in the line.
+pub(super) struct RedactingWriter<M>(pub(super) M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingWriter<M> {
+    type Writer = Redacting<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Redacting(self.0.make_writer())
+    }
+}
+
+pub(super) struct Redacting<W>(W);
+
+impl<W: io::Write> io::Write for Redacting<W> {
+    // Formatting layers write whole events at once, so no value is split between writes
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if is_redacted() {
+            self.0
+                .write_all(redact(&String::from_utf8_lossy(buf)).as_bytes())?;
+        } else {
+            self.0.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Masks phone numbers and UUIDs, keeping their last characters so log lines of
+/// the same recipient can be still correlated, and tokens of `Basic`/`Bearer` credentials.
+pub(super) fn redact(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut redacted = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let previous = i.checked_sub(1).map(|previous| bytes[previous]);
+        let boundary = !matches!(previous, Some(byte) if byte.is_ascii_alphanumeric());
+        // Digits after a dot are fractions, e.g. of seconds in timestamps of log lines
+        let fraction = previous == Some(b'.');
+        let (start, end) = if !boundary {
+            (i, i)
+        } else if let Some(len) = uuid_len(&bytes[i..]) {
+            (i, i + len - 4)
+        } else if let Some((prefix, len)) = phone_len(&bytes[i..], !fraction) {
+            (i + prefix, i + len - 2)
+        } else if let Some((prefix, len)) = credentials_len(&bytes[i..]) {
+            (i + prefix, i + len)
+        } else {
+            (i, i)
+        };
+        if start == end {
+            i += 1;
+            continue;
+        }
+        redacted.push_str(&text[copied..start]);
+        redacted.push_str(MASK);
+        copied = end;
+        i = end;
+    }
+    redacted.push_str(&text[copied..]);
+    redacted
+}
+
+/// Length of the UUID at the start of the text.
+fn uuid_len(text: &[u8]) -> Option<usize> {
+    const GROUPS: [usize; 5] = [8, 4, 4, 4, 12];
+    let mut len = 0;
+    for (index, group) in GROUPS.iter().enumerate() {
+        if index > 0 {
+            if text.get(len) != Some(&b'-') {
+                return None;
+            }
+            len += 1;
+        }
+        let digits = text.get(len..len + group)?;
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        len += group;
+    }
+    match text.get(len) {
+        Some(next) if next.is_ascii_alphanumeric() => None,
+        _ => Some(len),
+    }
+}
+
+/// Length of the E164 number at the start of the text, with the length of its `+` prefix,
+/// which may be percent encoded in URLs. Numbers without the prefix are matched only
+/// when `bare` is set and they don't look like timestamps or decimal numbers.
+fn phone_len(text: &[u8], bare: bool) -> Option<(usize, usize)> {
+    let prefix = if text.starts_with(b"+") {
+        1
+    } else if text.len() >= 3 && text[..3].eq_ignore_ascii_case(b"%2b") {
+        3
+    } else if bare {
+        0
+    } else {
+        return None;
+    };
+    let digits = text[prefix..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    if !(7..=15).contains(&digits) {
+        return None;
+    }
+    if prefix == 0 {
+        if matches!(text.get(digits), Some(byte) if byte.is_ascii_alphanumeric() || *byte == b'.') {
+            return None;
+        }
+        // Timestamps in seconds or milliseconds since epoch until the year 2033
+        if text[0] == b'1' && (digits == 10 || digits == 13) {
+            return None;
+        }
+    }
+    Some((prefix, prefix + digits))
+}
+
+/// Length of the credentials at the start of the text, with the length of their scheme.
+fn credentials_len(text: &[u8]) -> Option<(usize, usize)> {
+    let scheme = [&b"basic "[..], &b"bearer "[..]]
+        .into_iter()
+        .find(|scheme| {
+            text.len() > scheme.len() && text[..scheme.len()].eq_ignore_ascii_case(scheme)
+        })?
+        .len();
+    let token = text[scheme..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_alphanumeric() || b"+/=._-".contains(byte))
+        .count();
+    (token > 0).then_some((scheme, scheme + token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phone_numbers_keep_last_digits() {
+        assert_eq!(redact("to +420123456789."), "to +[redacted]89.");
+        assert_eq!(
+            redact("/v1/accounts/sms/code/%2B420123456789?client=android"),
+            "/v1/accounts/sms/code/%2B[redacted]89?client=android"
+        );
+        assert_eq!(redact("number=420123456789"), "number=[redacted]89");
+        assert_eq!(redact("\"12025550123\""), "\"[redacted]23\"");
+    }
+
+    #[test]
+    fn other_numbers_are_kept() {
+        let lines = [
+            "timestamp=1697040000000 seconds=1697040000",
+            "2023-10-11T16:00:00.123456789Z id=42 size=123456",
+            "version 1.2.3456789 and 12345678901234567890",
+            "+123456 and 1234567abc",
+        ];
+        for line in lines {
+            assert_eq!(redact(line), line);
+        }
+    }
+
+    #[test]
+    fn uuids_keep_last_characters() {
+        assert_eq!(
+            redact("uuid=0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0 device=2"),
+            "uuid=[redacted]e1f0 device=2"
+        );
+        assert_eq!(
+            redact("/v1/profile/0F1E2D3C-4B5A-6978-8796-A5B4C3D2E1F0/"),
+            "/v1/profile/[redacted]E1F0/"
+        );
+        let not_uuid =
+            "x0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0 0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0a";
+        assert_eq!(redact(not_uuid), not_uuid);
+    }
+
+    #[test]
+    fn credentials_are_masked() {
+        assert_eq!(
+            redact("authorization: Basic dXNlcjpwYXNz=="),
+            "authorization: Basic [redacted]"
+        );
+        assert_eq!(
+            redact("\"authorization\": \"bearer a.b-c_d/e+f\""),
+            "\"authorization\": \"bearer [redacted]\""
+        );
+        assert_eq!(redact("Basic "), "Basic ");
+    }
+
+    #[test]
+    fn non_ascii_text_is_kept_intact() {
+        assert_eq!(
+            redact("Zpráva pro +420123456789 – odesláno ✓"),
+            "Zpráva pro +[redacted]89 – odesláno ✓"
+        );
+        assert_eq!(
+            redact("čísla:420123456789ž 0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0€"),
+            "čísla:[redacted]89ž [redacted]e1f0€"
+        );
+    }
+}
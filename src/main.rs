@@ -1,9 +1,21 @@
-use std::fs::DirBuilder;
+use std::fs::{self, DirBuilder};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use signal_dbus_client::error::Result;
-use signal_dbus_client::{register, send_message};
+use signal_dbus_client::{
+    add_contact, backup, drop_inbox_message, drop_queued, edit_contact, flush_queue,
+    import_contacts, init_logging, install_sticker_pack, link_device, list_accounts, list_blocked,
+    list_contacts, list_devices, list_groups, list_inbox, list_queue, list_sticker_packs,
+    list_view_once, open_view_once, receive, register, register_primary, remove_account,
+    remove_contact, remove_device, remove_sticker_pack, rename_device, request_sync, restore,
+    retry_inbox, send_message, send_sticker, serve, set_blocked, set_expire_timer, set_profile,
+    show_profile, trust_identity, unregister, update_attributes, upload_sticker_pack, Attachment,
+    LinkingOptions, LogFormat, LoggingOptions, Mention, QrCodeFormat, Quote, SendOptions,
+    WebConfig,
+};
+use tracing::error;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -23,6 +35,57 @@ struct Cli {
         help = "Sets a custom data directory"
     )]
     data_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        short,
+        value_name = "UUID|E164",
+        help = "Selects the account to use when multiple accounts are registered"
+    )]
+    account: Option<String>,
+
+    #[arg(
+        long,
+        short,
+        action = ArgAction::Count,
+        help = "Logs more details, repeat for even more. RUST_LOG takes precedence"
+    )]
+    verbose: u8,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = LogOutput::Text,
+        help = "Format of the logs written to stderr"
+    )]
+    log_format: LogOutput,
+
+    #[arg(
+        long,
+        help = "Logs phone numbers, UUIDs, authorization headers and message bodies unmasked"
+    )]
+    no_redact: bool,
+
+    #[arg(
+        long,
+        value_name = "AGENT",
+        help = "User agent sent when fetching web pages for link previews"
+    )]
+    web_user_agent: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Time limit of fetching a web page for link preview, including redirects"
+    )]
+    web_timeout: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Largest web page or image fetched for link preview"
+    )]
+    web_max_size: Option<usize>,
 }
 
 #[derive(Subcommand)]
@@ -31,19 +94,449 @@ enum Commands {
     Register {
         #[arg(help = "Sets the name of newly registered sub-device")]
         name: String,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = QrFormat::Text,
+            help = "Format of the provisioning QR code"
+        )]
+        qr_format: QrFormat,
+        #[arg(long, value_name = "PATH", help = "Writes the QR code into the file")]
+        qr_output: Option<PathBuf>,
+        #[arg(
+            long,
+            conflicts_with_all = ["qr_format", "qr_output"],
+            help = "Prints only the sgnl:// provisioning URL instead of the QR code"
+        )]
+        url_only: bool,
+        #[arg(long, help = "Emits the provisioning URL as a D-Bus signal")]
+        dbus_signal: bool,
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            help = "Gives up when the device is not linked within the time limit"
+        )]
+        timeout: Option<u64>,
+    },
+    #[command(about = "Register new account with this client as the primary device")]
+    RegisterPrimary {
+        #[arg(help = "Telephone number of the account in E164 format")]
+        number: String,
+        #[arg(
+            long,
+            help = "Requests the verification code by voice call instead of SMS"
+        )]
+        voice: bool,
+        #[arg(
+            long,
+            help = "Captcha token from https://signalcaptchas.org/registration/generate.html"
+        )]
+        captcha: Option<String>,
+        #[arg(
+            long,
+            value_name = "CODE",
+            help = "Already received verification code. Skips requesting a new one"
+        )]
+        verification_code: Option<String>,
     },
     #[command(about = "Sends message to specified recipient")]
     Send {
-        #[arg(help = "Recipient of the message. Either E164 telephone format or UUID")]
+        #[arg(
+            help = "Recipient of the message. Either E164 telephone format, UUID or contact name"
+        )]
         recipient: String,
         message: String,
+        #[arg(long, help = "Sends the message even if the recipient is blocked")]
+        allow_blocked: bool,
+        #[arg(
+            long,
+            requires = "quote_timestamp",
+            help = "Author of the message to reply to. Either E164 telephone format, UUID or contact name"
+        )]
+        quote_author: Option<String>,
+        #[arg(
+            long,
+            requires = "quote_author",
+            help = "Sent timestamp of the message to reply to"
+        )]
+        quote_timestamp: Option<u64>,
+        #[arg(
+            long,
+            value_name = "START:LENGTH:RECIPIENT",
+            help = "Mentions the contact in place of LENGTH characters of the message from START"
+        )]
+        mention: Vec<Mention>,
+        #[arg(
+            long,
+            help = "Formats the message by Markdown: **bold**, *italic*, ~~strikethrough~~, ||spoiler|| and `monospace`"
+        )]
+        markdown: bool,
+        #[arg(long, help = "Attaches preview of the first HTTPS link in the message")]
+        link_preview: bool,
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Shares contacts of the vCard file. The message may then be empty"
+        )]
+        contact: Vec<PathBuf>,
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Attaches the file. The message may then be empty"
+        )]
+        attachment: Vec<PathBuf>,
+        #[arg(
+            long,
+            requires = "attachment",
+            help = "Lets the recipient view the single image or video attachment only once. Requires empty message"
+        )]
+        view_once: bool,
     },
+    #[command(about = "Sends sticker of an installed pack to specified recipient")]
+    SendSticker {
+        #[arg(
+            help = "Recipient of the message. Either E164 telephone format, UUID or contact name"
+        )]
+        recipient: String,
+        #[arg(help = "Hex encoded id of the sticker pack")]
+        pack_id: String,
+        #[arg(help = "Id of the sticker within the pack")]
+        sticker_id: u32,
+        #[arg(long, help = "Sends the sticker even if the recipient is blocked")]
+        allow_blocked: bool,
+    },
+    #[command(about = "Sets disappearing messages timer of the conversation")]
+    ExpireTimer {
+        #[arg(
+            help = "Recipient of the message. Either E164 telephone format, UUID or contact name"
+        )]
+        recipient: String,
+        #[arg(help = "Time after which messages disappear in seconds. Zero turns it off")]
+        seconds: u32,
+    },
+    #[command(about = "Receives queued messages and prints them to stdout")]
+    Receive {
+        #[arg(
+            long,
+            value_name = "DIRECTORY",
+            help = "Writes contacts shared in the messages into the directory as vCards"
+        )]
+        contacts_dir: Option<PathBuf>,
+    },
+    #[command(about = "Manages received messages which failed to be processed")]
+    Inbox {
+        #[clap(subcommand)]
+        command: InboxCommands,
+    },
+    #[command(about = "Manages contacts")]
+    Contacts {
+        #[clap(subcommand)]
+        command: ContactsCommands,
+    },
+    #[command(about = "Fetches and shows the profile of the contact")]
+    Profile {
+        #[arg(
+            help = "Contact whose profile to show. Either E164 telephone format, UUID or contact name"
+        )]
+        contact: String,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Writes the decrypted avatar into the file"
+        )]
+        avatar_output: Option<PathBuf>,
+    },
+    #[command(about = "Encrypts and uploads the profile of the account")]
+    SetProfile {
+        #[arg(long, help = "Given name shown to recipients")]
+        given_name: Option<String>,
+        #[arg(long, help = "Family name shown to recipients. Empty value removes it")]
+        family_name: Option<String>,
+        #[arg(long, help = "Short text about the account")]
+        about: Option<String>,
+        #[arg(long, value_name = "FILE", help = "Image used as the avatar")]
+        avatar: Option<PathBuf>,
+    },
+    #[command(about = "Manages messages queued for delivery")]
+    Queue {
+        #[clap(subcommand)]
+        command: QueueCommands,
+    },
+    #[command(about = "Manages received view-once messages")]
+    ViewOnce {
+        #[clap(subcommand)]
+        command: ViewOnceCommands,
+    },
+    #[command(about = "Manages sticker packs")]
+    Stickers {
+        #[clap(subcommand)]
+        command: StickersCommands,
+    },
+    #[command(about = "Manages groups")]
+    Groups {
+        #[clap(subcommand)]
+        command: GroupsCommands,
+    },
+    #[command(
+        about = "Requests contacts, groups, blocked list and configuration from the primary device"
+    )]
+    Sync,
+    #[command(about = "Blocks the contact or group")]
+    Block {
+        #[arg(help = "Contact to block. Either E164 telephone format, UUID or contact name")]
+        target: String,
+        #[arg(long, help = "Treats the target as base64 encoded group id")]
+        group: bool,
+    },
+    #[command(about = "Unblocks the contact or group")]
+    Unblock {
+        #[arg(help = "Contact to unblock. Either E164 telephone format, UUID or contact name")]
+        target: String,
+        #[arg(long, help = "Treats the target as base64 encoded group id")]
+        group: bool,
+    },
+    #[command(about = "Lists blocked contacts and groups")]
+    Blocked,
+    #[command(
+        about = "Trusts the changed identity key of the contact, so messages can be exchanged again"
+    )]
+    Trust {
+        #[arg(help = "Contact to trust. Either E164 telephone format, UUID or contact name")]
+        contact: String,
+        #[arg(
+            long,
+            value_name = "BASE64",
+            help = "Trusts the key only if it is this one, e.g. after comparing it in person"
+        )]
+        identity_key: Option<String>,
+    },
+    #[command(about = "Runs D-Bus service on the session bus")]
+    Daemon,
+    #[command(about = "Backs up account data into passphrase-encrypted archive")]
+    Backup {
+        #[arg(help = "Path of the archive to create")]
+        file: PathBuf,
+    },
+    #[command(about = "Restores account data from passphrase-encrypted archive")]
+    Restore {
+        #[arg(help = "Path of the archive to restore from")]
+        file: PathBuf,
+        #[arg(long, help = "Overwrite already registered account")]
+        force: bool,
+    },
+    #[command(about = "Manages devices linked to the account")]
+    Devices {
+        #[clap(subcommand)]
+        command: DevicesCommands,
+    },
+    #[command(about = "Changes the name of this device")]
+    RenameDevice {
+        #[arg(help = "New name of this device")]
+        name: String,
+    },
+    #[command(about = "Updates attributes and advertised capabilities of this device")]
+    UpdateAttributes,
+    #[command(about = "Removes this device from the account and wipes its local data")]
+    Unregister,
+    #[command(about = "Manages locally registered accounts")]
+    Accounts {
+        #[clap(subcommand)]
+        command: AccountsCommands,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum QrFormat {
+    Text,
+    Png,
+    Svg,
+}
+
+impl From<QrFormat> for QrCodeFormat {
+    fn from(format: QrFormat) -> Self {
+        match format {
+            QrFormat::Text => Self::Text,
+            QrFormat::Png => Self::Png,
+            QrFormat::Svg => Self::Svg,
+        }
+    }
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum LogOutput {
+    Text,
+    Json,
+}
+
+impl From<LogOutput> for LogFormat {
+    fn from(format: LogOutput) -> Self {
+        match format {
+            LogOutput::Text => Self::Text,
+            LogOutput::Json => Self::Json,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum DevicesCommands {
+    #[command(about = "Lists devices linked to the account")]
+    List,
+    #[command(about = "Links new device to the account. Requires this to be the primary device")]
+    Link {
+        #[arg(help = "The sgnl://linkdevice URL encoded in the QR code of the new device")]
+        url: String,
+    },
+    #[command(about = "Unlinks the device from the account")]
+    Remove {
+        #[arg(help = "Id of the device to unlink")]
+        device_id: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum ContactsCommands {
+    #[command(about = "Lists known contacts")]
+    List,
+    #[command(about = "Adds new contact or updates the existing one")]
+    Add {
+        #[arg(long, help = "UUID of the contact")]
+        uuid: Option<String>,
+        #[arg(long, help = "Telephone number of the contact in E164 format")]
+        number: Option<String>,
+        #[arg(long, help = "Name of the contact")]
+        name: Option<String>,
+    },
+    #[command(about = "Changes name or telephone number of the contact")]
+    Edit {
+        #[arg(help = "Contact to edit. Either E164 telephone format, UUID or contact name")]
+        contact: String,
+        #[arg(long, help = "New name of the contact")]
+        name: Option<String>,
+        #[arg(long, help = "New telephone number of the contact in E164 format")]
+        number: Option<String>,
+    },
+    #[command(about = "Removes the contact")]
+    Remove {
+        #[arg(help = "Contact to remove. Either E164 telephone format, UUID or contact name")]
+        contact: String,
+    },
+    #[command(about = "Imports contacts from decrypted contacts sync blob")]
+    Import {
+        #[arg(help = "Path of the blob with length-delimited ContactDetails")]
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum StickersCommands {
+    #[command(about = "Lists installed sticker packs and their stickers")]
+    List,
+    #[command(about = "Installs the sticker pack and syncs it to other devices")]
+    Install {
+        #[arg(help = "Hex encoded id of the sticker pack")]
+        pack_id: String,
+        #[arg(help = "Hex encoded key of the sticker pack")]
+        pack_key: String,
+    },
+    #[command(about = "Removes the sticker pack and syncs the removal to other devices")]
+    Remove {
+        #[arg(help = "Hex encoded id of the sticker pack")]
+        pack_id: String,
+    },
+    #[command(about = "Uploads WebP images of the directory as a new sticker pack")]
+    Upload {
+        #[arg(help = "Directory with the stickers. The first one by file name is the cover")]
+        dir: PathBuf,
+        #[arg(long, help = "Title of the sticker pack")]
+        title: String,
+        #[arg(long, help = "Author of the sticker pack")]
+        author: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum QueueCommands {
+    #[command(about = "Lists queued messages with their delivery attempts and last error")]
+    List,
+    #[command(about = "Attempts delivery of all queued messages now")]
+    Flush,
+    #[command(about = "Removes the message from the queue without delivering it")]
+    Drop {
+        #[arg(help = "Id of the queued message")]
+        id: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum InboxCommands {
+    #[command(about = "Lists received messages with their processing attempts and last error")]
+    List,
+    #[command(about = "Processes all received messages again and prints them to stdout")]
+    Retry {
+        #[arg(
+            long,
+            value_name = "DIRECTORY",
+            help = "Writes contacts shared in the messages into the directory as vCards"
+        )]
+        contacts_dir: Option<PathBuf>,
+    },
+    #[command(about = "Removes the message from the inbox without processing it")]
+    Drop {
+        #[arg(help = "Id of the received message")]
+        id: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum ViewOnceCommands {
+    #[command(about = "Lists view-once messages which were not opened yet")]
+    List,
+    #[command(about = "Saves the view-once media and deletes it here and on other devices")]
+    Open {
+        #[arg(help = "Sender of the message. Either E164 telephone format, UUID or contact name")]
+        sender: String,
+        #[arg(help = "Sent timestamp of the message")]
+        timestamp: u64,
+        #[arg(long, value_name = "PATH", help = "File to write the media into")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum GroupsCommands {
+    #[command(about = "Lists groups synced from the primary device")]
+    List,
+}
+
+#[derive(Subcommand)]
+enum AccountsCommands {
+    #[command(about = "Lists locally registered accounts")]
+    List,
+    #[command(about = "Removes local data of the account")]
+    Remove {
+        #[arg(help = "Account to remove. Either E164 telephone format or UUID")]
+        account: String,
+    },
+}
+
+/// Failed commands exit with the code of the error kind, see `ErrorKind::exit_code`.
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
+async fn main() {
     let cli = Cli::parse();
+    init_logging(&LoggingOptions {
+        verbosity: cli.verbose,
+        format: cli.log_format.into(),
+        redact: !cli.no_redact,
+    });
+
+    if let Err(err) = run(cli).await {
+        // Logged, so the message is redacted and formatted like the other logs
+        error!("{}", err);
+        std::process::exit(err.kind().exit_code());
+    }
+}
 
+async fn run(cli: Cli) -> Result<()> {
     let data_dir = if let Some(path) = cli.data_dir {
         test_writeable_directory(&path)?;
         path
@@ -51,9 +544,221 @@ async fn main() -> Result<()> {
         get_default_data_dir()?
     };
 
+    let account = cli.account.as_deref();
+    let defaults = WebConfig::default();
+    let web_config = WebConfig {
+        user_agent: cli.web_user_agent.unwrap_or(defaults.user_agent),
+        timeout: cli
+            .web_timeout
+            .map_or(defaults.timeout, Duration::from_secs),
+        max_size: cli.web_max_size.unwrap_or(defaults.max_size),
+        ..defaults
+    };
+
     match cli.command {
-        Commands::Register { name } => register(data_dir, &name).await,
-        Commands::Send { recipient, message } => send_message(data_dir, &recipient, &message).await,
+        Commands::Register {
+            name,
+            qr_format,
+            qr_output,
+            url_only,
+            dbus_signal,
+            timeout,
+        } => {
+            let options = LinkingOptions {
+                qr_format: qr_format.into(),
+                qr_output,
+                url_only,
+                dbus_signal,
+                timeout: timeout.map(Duration::from_secs),
+            };
+            register(data_dir, &name, &options).await
+        }
+        Commands::RegisterPrimary {
+            number,
+            voice,
+            captcha,
+            verification_code,
+        } => {
+            register_primary(
+                data_dir,
+                &number,
+                voice,
+                captcha.as_deref(),
+                verification_code.as_deref(),
+            )
+            .await
+        }
+        Commands::Send {
+            recipient,
+            message,
+            allow_blocked,
+            quote_author,
+            quote_timestamp,
+            mention,
+            markdown,
+            link_preview,
+            contact,
+            attachment,
+            view_once,
+        } => {
+            let quote = quote_author
+                .zip(quote_timestamp)
+                .map(|(author, timestamp)| Quote { author, timestamp });
+            let options = SendOptions {
+                allow_blocked,
+                quote,
+                mentions: mention,
+                markdown,
+                link_preview,
+                contacts: contact
+                    .iter()
+                    .map(fs::read_to_string)
+                    .collect::<std::io::Result<_>>()?,
+                attachments: attachment
+                    .iter()
+                    .map(|path| Attachment::read(path))
+                    .collect::<Result<_>>()?,
+                view_once,
+            };
+            send_message(
+                data_dir,
+                account,
+                &recipient,
+                &message,
+                &options,
+                &web_config,
+            )
+            .await
+        }
+        Commands::SendSticker {
+            recipient,
+            pack_id,
+            sticker_id,
+            allow_blocked,
+        } => {
+            send_sticker(
+                data_dir,
+                account,
+                &recipient,
+                &pack_id,
+                sticker_id,
+                allow_blocked,
+            )
+            .await
+        }
+        Commands::ExpireTimer { recipient, seconds } => {
+            set_expire_timer(data_dir, account, &recipient, seconds).await
+        }
+        Commands::Receive { contacts_dir } => {
+            receive(data_dir, account, contacts_dir.as_deref()).await
+        }
+        Commands::Contacts { command } => match command {
+            ContactsCommands::List => list_contacts(data_dir, account),
+            ContactsCommands::Add { uuid, number, name } => add_contact(
+                data_dir,
+                account,
+                uuid.as_deref(),
+                number.as_deref(),
+                name.as_deref(),
+            ),
+            ContactsCommands::Edit {
+                contact,
+                name,
+                number,
+            } => edit_contact(
+                data_dir,
+                account,
+                &contact,
+                name.as_deref(),
+                number.as_deref(),
+            ),
+            ContactsCommands::Remove { contact } => remove_contact(data_dir, account, &contact),
+            ContactsCommands::Import { file } => import_contacts(data_dir, account, &file),
+        },
+        Commands::Profile {
+            contact,
+            avatar_output,
+        } => show_profile(data_dir, account, &contact, avatar_output.as_deref()).await,
+        Commands::SetProfile {
+            given_name,
+            family_name,
+            about,
+            avatar,
+        } => {
+            set_profile(
+                data_dir,
+                account,
+                given_name.as_deref(),
+                family_name.as_deref(),
+                about.as_deref(),
+                avatar.as_deref(),
+            )
+            .await
+        }
+        Commands::Inbox { command } => match command {
+            InboxCommands::List => list_inbox(data_dir, account),
+            InboxCommands::Retry { contacts_dir } => {
+                retry_inbox(data_dir, account, contacts_dir.as_deref()).await
+            }
+            InboxCommands::Drop { id } => drop_inbox_message(data_dir, account, id),
+        },
+        Commands::Queue { command } => match command {
+            QueueCommands::List => list_queue(data_dir, account),
+            QueueCommands::Flush => flush_queue(data_dir, account).await,
+            QueueCommands::Drop { id } => drop_queued(data_dir, account, id),
+        },
+        Commands::ViewOnce { command } => match command {
+            ViewOnceCommands::List => list_view_once(data_dir, account),
+            ViewOnceCommands::Open {
+                sender,
+                timestamp,
+                output,
+            } => open_view_once(data_dir, account, &sender, timestamp, &output).await,
+        },
+        Commands::Stickers { command } => match command {
+            StickersCommands::List => list_sticker_packs(data_dir, account),
+            StickersCommands::Install { pack_id, pack_key } => {
+                install_sticker_pack(data_dir, account, &pack_id, &pack_key).await
+            }
+            StickersCommands::Remove { pack_id } => {
+                remove_sticker_pack(data_dir, account, &pack_id).await
+            }
+            StickersCommands::Upload { dir, title, author } => {
+                upload_sticker_pack(data_dir, account, &dir, &title, &author).await
+            }
+        },
+        Commands::Groups { command } => match command {
+            GroupsCommands::List => list_groups(data_dir, account),
+        },
+        Commands::Sync => request_sync(data_dir, account).await,
+        Commands::Block { target, group } => {
+            set_blocked(data_dir, account, &target, group, true).await
+        }
+        Commands::Unblock { target, group } => {
+            set_blocked(data_dir, account, &target, group, false).await
+        }
+        Commands::Blocked => list_blocked(data_dir, account),
+        Commands::Trust {
+            contact,
+            identity_key,
+        } => trust_identity(data_dir, account, &contact, identity_key.as_deref()).await,
+        Commands::Daemon => serve(data_dir, account, &web_config).await,
+        Commands::Backup { file } => backup(data_dir, account, &file),
+        Commands::Restore { file, force } => restore(data_dir, &file, force),
+        Commands::Devices { command } => match command {
+            DevicesCommands::List => list_devices(data_dir, account).await,
+            DevicesCommands::Link { url } => link_device(data_dir, account, &url).await,
+            DevicesCommands::Remove { device_id } => {
+                remove_device(data_dir, account, device_id).await
+            }
+        },
+        Commands::RenameDevice { name } => rename_device(data_dir, account, &name).await,
+        Commands::UpdateAttributes => update_attributes(data_dir, account).await,
+        Commands::Unregister => unregister(data_dir, account).await,
+        Commands::Accounts { command } => match command {
+            AccountsCommands::List => list_accounts(data_dir),
+            AccountsCommands::Remove { account } => remove_account(data_dir, &account),
+        },
     }
 }
 
@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rand::rngs::OsRng;
+
+use crate::account::AccountManager;
+use crate::common::ApiConfig;
+use crate::error::Result;
+
+/// Fetches and prints the profile of the contact given by UUID, phone number or name.
+/// The avatar is written into `avatar_output`, when given.
+pub async fn show_profile(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    contact: &str,
+    avatar_output: Option<&Path>,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+    let profile = account_manager.fetch_profile(contact).await?;
+
+    println!("name\t{}", profile.display_name().as_deref().unwrap_or("-"));
+    println!("about\t{}", profile.about.as_deref().unwrap_or("-"));
+    println!("emoji\t{}", profile.about_emoji.as_deref().unwrap_or("-"));
+    match (&profile.avatar, avatar_output) {
+        (Some(avatar), Some(path)) => {
+            fs::write(path, avatar)?;
+            eprintln!("Avatar written to {}.", path.display());
+        }
+        (Some(_), None) => eprintln!("Profile has an avatar. Use --avatar-output to save it."),
+        (None, _) => {}
+    }
+    Ok(())
+}
+
+/// Encrypts and uploads our profile, so that recipients of our messages can see who we are.
+pub async fn set_profile(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    given_name: Option<&str>,
+    family_name: Option<&str>,
+    about: Option<&str>,
+    avatar: Option<&Path>,
+) -> Result<()> {
+    let avatar = avatar.map(fs::read).transpose()?;
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    account_manager
+        .set_profile(given_name, family_name, about, avatar.as_deref())
+        .await?;
+    eprintln!("Profile updated.");
+    Ok(())
+}
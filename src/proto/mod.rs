@@ -0,0 +1 @@
+pub(crate) mod signal_service;
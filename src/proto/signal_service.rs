@@ -3,4 +3,4 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
-include!(concat!(env!("OUT_DIR"), "/signal.proto.signal_service.rs"));
+include!(concat!(env!("OUT_DIR"), "/signalservice.rs"));
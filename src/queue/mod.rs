@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use rand::rngs::OsRng;
+
+use crate::account::AccountManager;
+use crate::common::ApiConfig;
+use crate::error::{Error, Result};
+use crate::store::AccountRegistry;
+
+/// Lists messages waiting in the outbox for delivery.
+pub fn list_queue(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    for entry in state_store.outbox(None)? {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            entry.id,
+            entry.recipient,
+            entry.timestamp,
+            entry.attempts,
+            entry.next_attempt,
+            entry.last_error.as_deref().unwrap_or("-"),
+        );
+    }
+    Ok(())
+}
+
+/// Attempts delivery of all queued messages regardless of their retry schedule.
+pub async fn flush_queue(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    let pending = account_manager.flush_outbox(true).await?;
+    eprintln!("{} messages remain queued.", pending);
+    Ok(())
+}
+
+/// Removes the message from the outbox without delivering it.
+pub fn drop_queued(data_dir: PathBuf, account: Option<&str>, id: u64) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    let entry = state_store
+        .outbox(None)?
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .ok_or(Error::UnknownQueuedMessage(id))?;
+    state_store.remove_queued_message(&entry)?;
+    eprintln!("Queued message {} dropped.", id);
+    Ok(())
+}
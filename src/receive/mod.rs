@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rand::rngs::OsRng;
+use rand::{CryptoRng, Rng};
+use tracing::warn;
+
+use crate::account::{AccountManager, ReceivedMessage};
+use crate::common::ApiConfig;
+use crate::error::Result;
+use crate::proto::signal_service::data_message;
+
+/// Receives messages queued on the server and prints text messages to stdout.
+/// Contacts shared with us are written as vCards into `contacts_dir`, when given.
+pub async fn receive(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    contacts_dir: Option<&Path>,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    let messages = account_manager.receive_messages().await?;
+    print_messages(&account_manager, messages, contacts_dir).await
+}
+
+/// Prints the messages and removes them from the inbox once printed.
+pub(crate) async fn print_messages<R: Rng + CryptoRng + Clone>(
+    account_manager: &AccountManager<R>,
+    messages: Vec<ReceivedMessage>,
+    contacts_dir: Option<&Path>,
+) -> Result<()> {
+    for message in messages {
+        print_message(account_manager, &message, contacts_dir).await?;
+        account_manager.acknowledge_received(&message)?;
+    }
+    Ok(())
+}
+
+async fn print_message<R: Rng + CryptoRng + Clone>(
+    account_manager: &AccountManager<R>,
+    message: &ReceivedMessage,
+    contacts_dir: Option<&Path>,
+) -> Result<()> {
+    let data_message = match &message.content.data_message {
+        Some(data_message) => data_message,
+        None => return Ok(()),
+    };
+    if let Some(body) = &data_message.body {
+        println!(
+            "{}\t{}\t{}\t{}",
+            message.timestamp,
+            message.sender.name(),
+            message.sender_name,
+            body
+        );
+    }
+    if data_message.is_view_once() && !data_message.attachments.is_empty() {
+        println!(
+            "{}\t{}\t{}\t[view-once]",
+            message.timestamp,
+            message.sender.name(),
+            message.sender_name,
+        );
+    }
+    for (index, contact) in data_message.contact.iter().enumerate() {
+        let name = contact
+            .name
+            .as_ref()
+            .and_then(|name| name.display_name.as_deref().or(name.given_name.as_deref()))
+            .unwrap_or("-");
+        println!(
+            "{}\t{}\t{}\t[contact {}]",
+            message.timestamp,
+            message.sender.name(),
+            message.sender_name,
+            name
+        );
+        match contacts_dir {
+            Some(dir) => {
+                let path = dir.join(format!("{}-{}.vcf", message.timestamp, index));
+                // A contact which can't be saved must not hold back the rest of the message
+                match write_contact(account_manager, contact, &path).await {
+                    Ok(()) => eprintln!("Contact {} written to {}.", name, path.display()),
+                    Err(err) => warn!(
+                        "Failed to write shared contact {} to {}: {}",
+                        name,
+                        path.display(),
+                        err
+                    ),
+                }
+            }
+            None => eprintln!("Use --contacts-dir to save shared contact {}.", name),
+        }
+    }
+    Ok(())
+}
+
+async fn write_contact<R: Rng + CryptoRng + Clone>(
+    account_manager: &AccountManager<R>,
+    contact: &data_message::Contact,
+    path: &Path,
+) -> Result<()> {
+    let vcard = account_manager.shared_contact_vcard(contact).await?;
+    fs::write(path, vcard)?;
+    Ok(())
+}
@@ -4,5 +4,7 @@ pub struct Credentials {
     pub aci_identity_key_pair: IdentityKeyPair,
     pub registration_id: u32,
     pub address: ProtocolAddress,
+    pub number: String,
     pub api_pass: String,
+    pub profile_key: Option<[u8; 32]>,
 }
@@ -1,37 +1,140 @@
+use std::io::{BufRead, Write};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use rand::rngs::OsRng;
+use tracing::{info, instrument, warn};
 
 use crate::account::AccountManager;
 use crate::common::ApiConfig;
-use crate::error::Result;
-use crate::store::SledStateStore;
+use crate::error::{Error, Result};
+use crate::store::{AccountRegistry, SledStateStore};
+use crate::utils::QrCodeFormat;
+
+use credentials::Credentials;
 
 mod credentials;
+mod primary;
 mod provision;
 mod register_device;
 
-pub async fn register(data_dir: PathBuf, name: &str) -> Result<()> {
-    let api_config = ApiConfig::default();
-    let csprng = &mut OsRng;
+/// Controls how the provisioning URL is presented while linking this device.
+#[derive(Debug, Clone)]
+pub struct LinkingOptions {
+    pub qr_format: QrCodeFormat,
+    /// Writes the QR code into the file instead of the terminal
+    pub qr_output: Option<PathBuf>,
+    /// Prints only the `sgnl://` URL to stdout, e.g. to render it elsewhere
+    pub url_only: bool,
+    /// Emits the URL as `LinkingUrl` signal on the D-Bus session bus
+    pub dbus_signal: bool,
+    /// Gives up linking when the provision message doesn't arrive in time
+    pub timeout: Option<Duration>,
+}
 
-    let provision_message = provision::get_provision_message(&api_config).await?;
-    eprintln!("Received provision message.");
-    let creds = register_device::register_device(&api_config, &provision_message, name).await?;
-    eprintln!("Device registered successfuly.");
+impl Default for LinkingOptions {
+    fn default() -> Self {
+        Self {
+            qr_format: QrCodeFormat::Text,
+            qr_output: None,
+            url_only: false,
+            dbus_signal: false,
+            timeout: None,
+        }
+    }
+}
 
-    let state_store = SledStateStore::new(&data_dir)?;
+#[instrument(skip_all)]
+async fn store_account(
+    data_dir: PathBuf,
+    creds: Credentials,
+    device_name: Option<&str>,
+    api_config: &ApiConfig,
+) -> Result<AccountManager<OsRng>> {
+    let mut registry = AccountRegistry::load(data_dir)?;
+    let account_dir = registry.add(&creds.address, Some(creds.number.as_str()))?;
+    let state_store = SledStateStore::new(account_dir)?;
+    // Registering already known account makes old sessions and keys obsolete
+    state_store.wipe()?;
     state_store.register_new_account(
         creds.aci_identity_key_pair,
         creds.registration_id,
         creds.address,
+        &creds.number,
         creds.api_pass,
     )?;
-    eprintln!("Stored credentials in state store.");
+    if let Some(name) = device_name {
+        state_store.set_device_name(name)?;
+    }
+    if let Some(profile_key) = &creds.profile_key {
+        state_store.set_profile_key(profile_key)?;
+    }
+    info!("stored credentials in state store");
 
-    let mut account_manager = AccountManager::with_store(state_store, csprng, &api_config)?;
+    let mut account_manager = AccountManager::with_store(state_store, OsRng, api_config)?;
     account_manager.initialize_pre_keys().await?;
-    eprintln!("Initialized pre keys.");
+    info!("initialized pre keys");
+
+    Ok(account_manager)
+}
+
+#[instrument(skip(data_dir, options))]
+pub async fn register(data_dir: PathBuf, name: &str, options: &LinkingOptions) -> Result<()> {
+    if options.qr_format == QrCodeFormat::Png && options.qr_output.is_none() && !options.url_only {
+        return Err(Error::ConfigError(String::from(
+            "PNG QR code has to be written into a file",
+        )));
+    }
+    let api_config = ApiConfig::default();
+
+    let provision_message = provision::get_provision_message(&api_config, options).await?;
+    eprintln!("Received provision message.");
+    let creds = register_device::register_device(&api_config, &provision_message, name).await?;
+    eprintln!("Device registered successfuly.");
+
+    let account_manager = store_account(data_dir, creds, Some(name), &api_config).await?;
+
+    // Device is already linked, sync can be requested again with `sync`
+    match account_manager.request_sync().await {
+        Ok(()) => {
+            eprintln!("Requested sync from the primary device. Run receive to process it.")
+        }
+        Err(err) => warn!(error = %err, "failed to request sync, run sync to retry"),
+    }
+    Ok(())
+}
+
+fn prompt_verification_code() -> Result<String> {
+    eprint!("Enter the verification code: ");
+    std::io::stderr().flush()?;
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// Registers new account with this client as the primary device.
+/// Verification code is requested over SMS or voice call, unless it is already provided.
+#[instrument(skip(data_dir, captcha, verification_code))]
+pub async fn register_primary(
+    data_dir: PathBuf,
+    number: &str,
+    voice: bool,
+    captcha: Option<&str>,
+    verification_code: Option<&str>,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+
+    let verification_code = match verification_code {
+        Some(code) => code.to_string(),
+        None => {
+            primary::request_verification_code(&api_config, number, voice, captcha).await?;
+            eprintln!("Verification code requested.");
+            prompt_verification_code()?
+        }
+    };
+    let creds = primary::verify_account(&api_config, number, &verification_code).await?;
+    eprintln!("Account registered successfuly.");
 
+    store_account(data_dir, creds, None, &api_config).await?;
     Ok(())
 }
@@ -0,0 +1,90 @@
+use base64::engine::{general_purpose::STANDARD_NO_PAD, Engine as _};
+use hyper::Method;
+use libsignal_protocol::{IdentityKeyPair, ProtocolAddress};
+use uuid::Uuid;
+
+use rand::{rngs::OsRng, RngCore};
+
+use serde::Deserialize;
+
+use super::credentials::Credentials;
+use crate::account::attributes::AccountAttributes;
+use crate::common::{ApiConfig, ApiPath};
+use crate::error::{Error, Result};
+use crate::utils::HttpClient;
+
+const PRIMARY_DEVICE_ID: u32 = 1;
+
+#[derive(Deserialize, Debug)]
+struct VerifyAccountResponse {
+    uuid: Uuid,
+    #[allow(dead_code)]
+    pni: Uuid,
+}
+
+pub(super) async fn request_verification_code(
+    api_config: &ApiConfig,
+    number: &str,
+    voice: bool,
+    captcha: Option<&str>,
+) -> Result<()> {
+    let transport = if voice { "voice" } else { "sms" };
+    let http_client = HttpClient::unauthenticated(api_config)?;
+    let response = http_client
+        .send(
+            Method::GET,
+            ApiPath::RequestVerificationCode {
+                transport,
+                number,
+                captcha,
+            },
+        )
+        .await;
+
+    match response {
+        Ok(_) => Ok(()),
+        Err(Error::HttpError(status_code, _)) if status_code == 402 => Err(Error::CaptchaRequired),
+        Err(err) => Err(err),
+    }
+}
+
+pub(super) async fn verify_account(
+    api_config: &ApiConfig,
+    number: &str,
+    verification_code: &str,
+) -> Result<Credentials> {
+    let registration_id = OsRng.next_u32() & 0x00003fff;
+    let mut profile_key = [0u8; 32];
+    OsRng.fill_bytes(&mut profile_key);
+    let attributes = AccountAttributes::new(None, registration_id).with_profile_key(&profile_key);
+
+    let mut api_pass = [0u8; 16];
+    OsRng.fill_bytes(&mut api_pass);
+    let api_pass = STANDARD_NO_PAD.encode(api_pass);
+
+    // Codes are presented as "123-456", but server expects only digits
+    let verification_code = verification_code.replace('-', "");
+    let http_client = HttpClient::new(number, &api_pass, api_config)?;
+    let response: VerifyAccountResponse = http_client
+        .send_json(
+            Method::PUT,
+            ApiPath::VerifyAccount {
+                verification_code: &verification_code,
+            },
+            &attributes,
+        )
+        .await?
+        .json()
+        .await?;
+
+    let address = ProtocolAddress::new(response.uuid.to_string(), PRIMARY_DEVICE_ID.into());
+
+    Ok(Credentials {
+        address,
+        number: number.to_string(),
+        api_pass,
+        aci_identity_key_pair: IdentityKeyPair::generate(&mut OsRng),
+        registration_id,
+        profile_key: Some(profile_key),
+    })
+}
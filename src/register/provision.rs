@@ -1,27 +1,95 @@
+use std::fs;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 
 use futures_util::{Sink, SinkExt, Stream, StreamExt};
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::{Error as TungError, Message as TungMessage};
+use tracing::{info, warn};
 
 use signal_provisioning_api::{ProvisionMessage, ProvisioningSocket, ProvisioningState};
 
 use crate::common::ApiConfig;
+use crate::dbus_server::emit_linking_url;
 use crate::error::{Error, Result};
-use crate::utils::{connect_wss, qrcode_image};
+use crate::utils::{connect_wss, qrcode_image, qrcode_png, qrcode_svg, QrCodeFormat};
 
-async fn process_stream<Si, St>(
+use super::LinkingOptions;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+async fn present_provisioning_url(url: &str, options: &LinkingOptions) -> Result<()> {
+    if options.url_only {
+        println!("{}", url);
+    } else if let Some(path) = &options.qr_output {
+        match options.qr_format {
+            QrCodeFormat::Text => fs::write(path, qrcode_image(url, false))?,
+            QrCodeFormat::Svg => fs::write(path, qrcode_svg(url))?,
+            QrCodeFormat::Png => qrcode_png(url, path)?,
+        }
+        eprintln!(
+            "Scan the QR code stored in {} with your app:\n{}",
+            path.display(),
+            url
+        );
+    } else {
+        match options.qr_format {
+            QrCodeFormat::Text => {
+                let image = qrcode_image(url, true);
+                eprintln!("Scan the QR code with your app:\n{}\n{}", url, image);
+            }
+            QrCodeFormat::Svg => println!("{}", qrcode_svg(url)),
+            QrCodeFormat::Png => unreachable!("PNG format requires output file"),
+        }
+    }
+
+    if options.dbus_signal {
+        if let Err(err) = emit_linking_url(url).await {
+            warn!(error = %err, "failed to emit D-Bus signal with provisioning URL");
+        }
+    }
+    Ok(())
+}
+
+/// Result of a single provisioning connection
+enum ConnectionOutcome {
+    Provisioned(Box<ProvisionMessage>),
+    /// Server closed the socket after handing out provisioning UUID, so the URL is no longer valid
+    Expired,
+}
+
+async fn close_socket<Si>(sink: &Mutex<Si>)
+where
+    Si: Sink<TungMessage, Error = TungError> + Unpin,
+{
+    if let Err(err) = sink.lock().await.close().await {
+        warn!(error = %err, "failed to close provisioning socket");
+    }
+}
+
+async fn process_stream<Si, St, C>(
     sink: Arc<Mutex<Si>>,
     mut stream: St,
-) -> Result<Box<ProvisionMessage>>
+    options: &LinkingOptions,
+    cancel: &mut Pin<&mut C>,
+) -> Result<ConnectionOutcome>
 where
     Si: Sink<TungMessage, Error = TungError> + Unpin,
     St: Stream<Item = std::result::Result<TungMessage, TungError>> + Unpin,
+    C: Future<Output = Error>,
 {
     let mut socket = ProvisioningSocket::new();
     loop {
-        match stream.next().await {
+        let next = tokio::select! {
+            next = stream.next() => next,
+            err = cancel.as_mut() => {
+                close_socket(&sink).await;
+                return Err(err);
+            }
+        };
+        match next {
             Some(Ok(msg)) => match msg {
                 TungMessage::Ping(data) => {
                     sink.lock()
@@ -33,7 +101,7 @@ where
                 | TungMessage::Text(_)
                 | TungMessage::Close(_) => {}
                 TungMessage::Binary(data) => {
-                    if let Some(request_id) = socket.process_message(&data).unwrap() {
+                    if let Some(request_id) = socket.process_message(&data)? {
                         let ack = ProvisioningSocket::acknowledge(request_id);
                         let ack = ProvisioningSocket::serialize(ack);
                         sink.lock()
@@ -41,9 +109,7 @@ where
                             .start_send_unpin(TungMessage::Binary(ack))?;
                         if let ProvisioningState::UuidReceived(uuid) = &socket.state {
                             let url = uuid.provisioning_url(socket.ephemeral_key_pair.public_key);
-                            let image = qrcode_image(&url, true);
-
-                            eprintln!("Scan the QR code with your app:\n{}\n{}", url, image);
+                            present_provisioning_url(&url, options).await?;
                         } else if let ProvisioningState::Provisioned(_) = &socket.state {
                             sink.lock().await.close().await?;
                         }
@@ -67,34 +133,79 @@ where
         }
     }
 
-    if let ProvisioningState::Provisioned(msg) = socket.state {
-        Ok(msg)
-    } else {
-        Err(Error::ProvisioningFailed)
+    match socket.state {
+        ProvisioningState::Provisioned(msg) => Ok(ConnectionOutcome::Provisioned(msg)),
+        ProvisioningState::UuidReceived(_) => Ok(ConnectionOutcome::Expired),
+        _ => Err(Error::ProvisioningFailed),
     }
 }
 
-pub(super) async fn get_provision_message(api_config: &ApiConfig) -> Result<Box<ProvisionMessage>> {
-    let (sink, stream) = connect_wss(api_config).await?.split();
+async fn provision_once<C>(
+    api_config: &ApiConfig,
+    options: &LinkingOptions,
+    cancel: &mut Pin<&mut C>,
+) -> Result<ConnectionOutcome>
+where
+    C: Future<Output = Error>,
+{
+    let connection = tokio::select! {
+        connection = connect_wss(api_config) => connection?,
+        err = cancel.as_mut() => return Err(err),
+    };
+    let (sink, stream) = connection.split();
     let sink = Arc::new(Mutex::new(sink));
     let clone = Arc::clone(&sink);
     let jh = tokio::spawn(async move {
         loop {
-            tokio::time::sleep(Duration::from_secs(15)).await;
+            tokio::time::sleep(HEARTBEAT_INTERVAL).await;
             let hb = ProvisioningSocket::hb_request();
             let hb = ProvisioningSocket::serialize(hb);
-            clone
-                .lock()
-                .await
-                .start_send_unpin(TungMessage::Binary(hb))
-                .unwrap();
+            if let Err(err) = clone.lock().await.start_send_unpin(TungMessage::Binary(hb)) {
+                // Failed connection is reported by the stream, just stop sending heartbeats
+                warn!(error = %err, "failed to send provisioning heartbeat");
+                break;
+            }
         }
     });
-    let result = process_stream(sink, stream).await;
+    let result = process_stream(sink, stream, options, cancel).await;
 
     jh.abort();
-    jh.await
-        .expect_err("We are cancelling the job, so error is expected");
 
     result
 }
+
+/// Waits for the provision message from the primary device.
+///
+/// Server expires the provisioning UUID after a while, in which case new connection is opened
+/// and new URL is presented. Fails with `ProvisioningTimeout` once `options.timeout` elapses
+/// and with `ProvisioningCancelled` on Ctrl-C.
+pub(super) async fn get_provision_message(
+    api_config: &ApiConfig,
+    options: &LinkingOptions,
+) -> Result<Box<ProvisionMessage>> {
+    let cancel = async {
+        let timeout = async {
+            match options.timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = timeout => Error::ProvisioningTimeout,
+            result = tokio::signal::ctrl_c() => match result {
+                Ok(()) => Error::ProvisioningCancelled,
+                Err(err) => err.into(),
+            },
+        }
+    };
+    tokio::pin!(cancel);
+
+    loop {
+        match provision_once(api_config, options, &mut cancel).await? {
+            ConnectionOutcome::Provisioned(msg) => return Ok(msg),
+            ConnectionOutcome::Expired => {
+                info!("provisioning URL expired, requesting a new one");
+            }
+        }
+    }
+}
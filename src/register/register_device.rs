@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use base64::engine::{general_purpose::STANDARD_NO_PAD, Engine as _};
 use hyper::Method;
 use libsignal_protocol::ProtocolAddress;
@@ -8,52 +6,15 @@ use uuid::Uuid;
 
 use rand::{rngs::OsRng, RngCore};
 
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 
 use super::credentials::Credentials;
+use crate::account::attributes::AccountAttributes;
+use crate::account::device_name::encrypt_device_name;
 use crate::common::{ApiConfig, ApiPath};
 use crate::error::Result;
 use crate::utils::HttpClient;
 
-#[derive(Serialize, Debug)]
-struct DeviceRegistrationRequest {
-    capabilities: HashMap<String, bool>,
-    #[serde(rename = "fetchesMessages")]
-    fetches_messages: bool,
-    name: String,
-    #[serde(rename = "registrationId")]
-    registration_id: u32,
-    #[serde(rename = "supportsSms")]
-    supports_sms: bool,
-    #[serde(rename = "unidentifiedAccessKey")]
-    unidentified_access_key: Option<String>,
-    #[serde(rename = "unrestrictedUnidentifiedAccess")]
-    unrestricted_unidentified_access: bool,
-}
-
-impl DeviceRegistrationRequest {
-    pub fn new(name: String, registration_id: u32) -> Self {
-        // TODO: we send capabilities that we don't have!
-        let mut capabilities = HashMap::new();
-        capabilities.insert("gv2-3".to_string(), true);
-        capabilities.insert("gv1-migration".to_string(), true);
-        capabilities.insert("senderKey".to_string(), true);
-        capabilities.insert("changeNumber".to_string(), true);
-        capabilities.insert("announcementGroup".to_string(), true);
-        capabilities.insert("giftBadges".to_string(), true);
-        capabilities.insert("stories".to_string(), true);
-        Self {
-            capabilities,
-            fetches_messages: true,
-            name,
-            registration_id,
-            supports_sms: false,
-            unidentified_access_key: None,
-            unrestricted_unidentified_access: false,
-        }
-    }
-}
-
 #[derive(Deserialize, Debug)]
 struct DeviceRegistrationResponse {
     // TODO: should we rename this to ACI?
@@ -71,8 +32,12 @@ pub(super) async fn register_device(
     name: &str,
 ) -> Result<Credentials> {
     let registration_id = OsRng.next_u32() & 0x00003fff;
-    // Should we encrypt device name as in TS sources?
-    let registration_request = DeviceRegistrationRequest::new(name.to_string(), registration_id);
+    let encrypted_name = encrypt_device_name(
+        name,
+        message.aci_identity_key_pair().identity_key(),
+        &mut OsRng,
+    )?;
+    let registration_request = AccountAttributes::new(Some(encrypted_name), registration_id);
 
     let mut api_pass = [0u8; 16];
     OsRng.fill_bytes(&mut api_pass);
@@ -91,12 +56,17 @@ pub(super) async fn register_device(
         .json()
         .await?;
 
-    let address = ProtocolAddress::new(response.uuid.to_string(), response.device_id.unwrap_or(1).into());
+    let address = ProtocolAddress::new(
+        response.uuid.to_string(),
+        response.device_id.unwrap_or(1).into(),
+    );
 
     Ok(Credentials {
         address,
+        number: message.number().to_string(),
         api_pass,
         aci_identity_key_pair: *message.aci_identity_key_pair(),
         registration_id,
+        profile_key: None,
     })
 }
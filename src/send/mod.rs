@@ -1,16 +1,166 @@
-use std::path::PathBuf;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use rand::rngs::OsRng;
 
 use crate::account::AccountManager;
-use crate::{common::ApiConfig, error::Result};
+use crate::common::{ApiConfig, WebConfig};
+use crate::error::Result;
 
-pub async fn send_message(data_dir: PathBuf, recipient: &str, message: &str) -> Result<()> {
-    let csprng = &mut OsRng;
-    let api_config = ApiConfig::default();
-    let account_manager = AccountManager::new(data_dir, csprng, &api_config)?;
+/// Controls how the outgoing message is sent.
+#[derive(Debug, Clone, Default)]
+pub struct SendOptions {
+    /// Sends the message even if the recipient is blocked
+    pub allow_blocked: bool,
+    /// Message the outgoing message replies to
+    pub quote: Option<Quote>,
+    /// Contacts mentioned in the body
+    pub mentions: Vec<Mention>,
+    /// Parses bold, italic, strikethrough, spoiler and monospace Markdown into text styles.
+    /// Offsets of mentions still refer to the body as given, including the Markdown syntax.
+    pub markdown: bool,
+    /// Attaches preview of the first HTTPS link in the body, unless link previews
+    /// are disabled in the configuration synced from the primary device
+    pub link_preview: bool,
+    /// vCards, i.e. content of `.vcf` files, whose contacts are shared with the message.
+    /// The body may then be empty
+    pub contacts: Vec<String>,
+    /// Files attached to the message
+    pub attachments: Vec<Attachment>,
+    /// Lets the recipient open the single image or video attachment only once.
+    /// View-once messages have no body
+    pub view_once: bool,
+}
+
+/// File attached to the message
+#[derive(Clone)]
+pub struct Attachment {
+    /// Name of the file shown to the recipient. Its extension gives the content type
+    pub file_name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    /// Reads the file, named by the last component of the path.
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(Self {
+            file_name: path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(str::to_string),
+            data: fs::read(path)?,
+        })
+    }
+}
+
+impl fmt::Debug for Attachment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Attachment")
+            .field("file_name", &self.file_name)
+            .field("size", &self.data.len())
+            .finish()
+    }
+}
+
+/// How the message was handed over to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendOutcome {
+    /// Message was delivered to the server
+    Sent,
+    /// Message failed to be delivered for a temporary reason or waits for earlier messages
+    /// to the recipient. It stays in the outbox under the id and is retried later
+    Queued { id: u64 },
+}
+
+impl SendOutcome {
+    /// Tells the user the message is only queued.
+    pub(crate) fn report(self) {
+        if let Self::Queued { id } = self {
+            eprintln!(
+                "Message could not be sent yet, it is queued for retry with id {}.",
+                id
+            );
+        }
+    }
+}
 
-    account_manager.send_message(recipient, message).await?;
+/// Reference to the quoted message by its author and sent timestamp.
+#[derive(Debug, Clone)]
+pub struct Quote {
+    /// Author of the quoted message. Either E164 telephone format, UUID or contact name
+    pub author: String,
+    pub timestamp: u64,
+}
+
+/// Mention of the contact, which replaces `length` characters of the body starting at `start`.
+#[derive(Debug, Clone)]
+pub struct Mention {
+    pub start: usize,
+    pub length: usize,
+    /// Mentioned contact. Either E164 telephone format, UUID or contact name
+    pub recipient: String,
+}
+
+impl FromStr for Mention {
+    type Err = String;
+
+    /// Parses mention in the `start:length:recipient` format.
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parts = value.splitn(3, ':');
+        let mut number = || {
+            parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .ok_or_else(|| String::from("Expected mention in start:length:recipient format"))
+        };
+        let start = number()?;
+        let length = number()?;
+        match parts.next() {
+            Some(recipient) if !recipient.is_empty() => Ok(Self {
+                start,
+                length,
+                recipient: recipient.to_string(),
+            }),
+            _ => Err(String::from("Missing recipient of the mention")),
+        }
+    }
+}
+
+pub async fn send_message(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    recipient: &str,
+    message: &str,
+    options: &SendOptions,
+    web_config: &WebConfig,
+) -> Result<()> {
+    let api_config = ApiConfig {
+        web: web_config.clone(),
+        ..ApiConfig::default()
+    };
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    account_manager
+        .send_message(recipient, message, options)
+        .await?
+        .report();
+    Ok(())
+}
+
+/// Sets disappearing messages timer of the conversation, zero seconds turns it off.
+pub async fn set_expire_timer(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    recipient: &str,
+    seconds: u32,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
 
+    let outcome = account_manager.set_expire_timer(recipient, seconds).await?;
+    eprintln!("Disappearing messages timer set to {} seconds.", seconds);
+    outcome.report();
     Ok(())
 }
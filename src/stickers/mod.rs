@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rand::rngs::OsRng;
+
+use crate::account::AccountManager;
+use crate::common::ApiConfig;
+use crate::error::{Error, Result};
+use crate::store::AccountRegistry;
+
+pub fn list_sticker_packs(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    for pack in state_store.sticker_packs()? {
+        println!(
+            "{}\t{}\t{}\t{}",
+            pack.id,
+            pack.title.as_deref().unwrap_or("-"),
+            pack.author.as_deref().unwrap_or("-"),
+            pack.stickers.len(),
+        );
+        for sticker in &pack.stickers {
+            println!(
+                "\t{}\t{}",
+                sticker.id,
+                sticker.emoji.as_deref().unwrap_or("-")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Downloads the sticker pack given by hex encoded id and key, as found in pack links.
+pub async fn install_sticker_pack(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    pack_id: &str,
+    pack_key: &str,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    let pack = account_manager
+        .install_sticker_pack(pack_id, pack_key)
+        .await?;
+    eprintln!(
+        "Installed sticker pack {} with {} stickers.",
+        pack.title.as_deref().unwrap_or(&pack.id),
+        pack.stickers.len()
+    );
+    Ok(())
+}
+
+pub async fn remove_sticker_pack(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    pack_id: &str,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    account_manager.remove_sticker_pack(pack_id).await?;
+    eprintln!("Sticker pack {} removed.", pack_id);
+    Ok(())
+}
+
+/// Uploads WebP images of the directory as a new sticker pack, ordered by their file names.
+/// Prints the link for sharing the pack.
+pub async fn upload_sticker_pack(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    dir: &Path,
+    title: &str,
+    author: &str,
+) -> Result<()> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    paths.retain(|path| {
+        path.extension()
+            .map_or(false, |extension| extension.eq_ignore_ascii_case("webp"))
+    });
+    paths.sort();
+    if paths.is_empty() {
+        return Err(Error::StickerError(format!(
+            "No WebP images found in {}",
+            dir.display()
+        )));
+    }
+    let stickers = paths.iter().map(fs::read).collect::<Result<Vec<_>, _>>()?;
+
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    let url = account_manager
+        .upload_sticker_pack(title, author, &stickers)
+        .await?;
+    eprintln!("Uploaded sticker pack with {} stickers.", stickers.len());
+    println!("{}", url);
+    Ok(())
+}
+
+pub async fn send_sticker(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    recipient: &str,
+    pack_id: &str,
+    sticker_id: u32,
+    allow_blocked: bool,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    account_manager
+        .send_sticker(recipient, pack_id, sticker_id, allow_blocked)
+        .await?
+        .report();
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use crate::error::Result;
+use crate::proto::signal_service::sync_message::Blocked;
+use crate::utils::serde::{deserialize_byte_vec, serialize_byte_vec};
+
+const BLOCKED_KEY: &[u8] = b"blocked";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GroupId(
+    #[serde(
+        serialize_with = "serialize_byte_vec",
+        deserialize_with = "deserialize_byte_vec"
+    )]
+    Vec<u8>,
+);
+
+/// Blocked contacts and groups, kept in the same shape as `SyncMessage.Blocked`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct BlockedList {
+    pub(crate) numbers: Vec<String>,
+    pub(crate) uuids: Vec<String>,
+    #[serde(rename = "groupIds")]
+    group_ids: Vec<GroupId>,
+}
+
+impl BlockedList {
+    /// Checks the contact given by any of its identifiers.
+    pub(crate) fn contains(&self, uuid: Option<&str>, number: Option<&str>) -> bool {
+        uuid.map_or(false, |uuid| {
+            self.uuids.iter().any(|blocked| blocked == uuid)
+        }) || number.map_or(false, |number| {
+            self.numbers.iter().any(|blocked| blocked == number)
+        })
+    }
+
+    pub(crate) fn contains_group(&self, group_id: &[u8]) -> bool {
+        self.group_ids.iter().any(|blocked| blocked.0 == group_id)
+    }
+
+    pub(crate) fn add(&mut self, uuid: Option<&str>, number: Option<&str>) {
+        self.remove(uuid, number);
+        self.uuids.extend(uuid.map(str::to_string));
+        self.numbers.extend(number.map(str::to_string));
+    }
+
+    pub(crate) fn remove(&mut self, uuid: Option<&str>, number: Option<&str>) {
+        self.uuids.retain(|blocked| Some(blocked.as_str()) != uuid);
+        self.numbers
+            .retain(|blocked| Some(blocked.as_str()) != number);
+    }
+
+    pub(crate) fn add_group(&mut self, group_id: &[u8]) {
+        self.remove_group(group_id);
+        self.group_ids.push(GroupId(group_id.to_vec()));
+    }
+
+    pub(crate) fn remove_group(&mut self, group_id: &[u8]) {
+        self.group_ids.retain(|blocked| blocked.0 != group_id);
+    }
+
+    pub(crate) fn group_ids(&self) -> impl Iterator<Item = &[u8]> {
+        self.group_ids.iter().map(|id| id.0.as_slice())
+    }
+}
+
+impl From<&BlockedList> for Blocked {
+    fn from(blocked: &BlockedList) -> Self {
+        Self {
+            numbers: blocked.numbers.clone(),
+            uuids: blocked.uuids.clone(),
+            group_ids: blocked.group_ids().map(<[u8]>::to_vec).collect(),
+        }
+    }
+}
+
+impl From<Blocked> for BlockedList {
+    fn from(blocked: Blocked) -> Self {
+        Self {
+            numbers: blocked.numbers,
+            uuids: blocked.uuids,
+            group_ids: blocked.group_ids.into_iter().map(GroupId).collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SledBlockedStore(Tree);
+
+impl TryFrom<&Db> for SledBlockedStore {
+    type Error = sled::Error;
+    fn try_from(db: &Db) -> std::result::Result<Self, Self::Error> {
+        Ok(Self(db.open_tree("blocked")?))
+    }
+}
+
+impl SledBlockedStore {
+    pub(crate) fn get(&self) -> Result<BlockedList> {
+        match self.0.get(BLOCKED_KEY)? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Ok(BlockedList::default()),
+        }
+    }
+
+    pub(crate) fn set(&self, blocked: &BlockedList) -> Result<()> {
+        self.0.insert(BLOCKED_KEY, serde_json::to_vec(blocked)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db, Tree};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::proto::signal_service::ContactDetails;
+use crate::utils::serde::{deserialize_opt_byte_vec, serialize_opt_byte_vec};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Contact {
+    pub(crate) uuid: Option<String>,
+    pub(crate) number: Option<String>,
+    pub(crate) name: Option<String>,
+    #[serde(
+        rename = "profileKey",
+        default,
+        serialize_with = "serialize_opt_byte_vec",
+        deserialize_with = "deserialize_opt_byte_vec"
+    )]
+    pub(crate) profile_key: Option<Vec<u8>>,
+    /// Name from the decrypted profile of the contact
+    #[serde(rename = "profileName", default)]
+    pub(crate) profile_name: Option<String>,
+    /// Disappearing messages timer of the conversation in seconds
+    #[serde(rename = "expireTimer", default)]
+    pub(crate) expire_timer: Option<u32>,
+}
+
+impl Contact {
+    /// Identifier of the contact used as the name of its `ProtocolAddress`.
+    /// UUID is preferred, since it doesn't change with the phone number.
+    pub(crate) fn identifier(&self) -> Option<&str> {
+        self.uuid.as_deref().or(self.number.as_deref())
+    }
+
+    /// Name suitable for displaying the contact to the user
+    pub(crate) fn display_name(&self) -> &str {
+        self.name
+            .as_deref()
+            .or(self.profile_name.as_deref())
+            .or(self.number.as_deref())
+            .or(self.uuid.as_deref())
+            .unwrap_or("-")
+    }
+
+    /// Overwrites fields with those set in `other`.
+    fn merge(&mut self, other: Contact) {
+        if other.uuid.is_some() {
+            self.uuid = other.uuid;
+        }
+        if other.number.is_some() {
+            self.number = other.number;
+        }
+        if other.name.is_some() {
+            self.name = other.name;
+        }
+        if other.profile_key.is_some() {
+            self.profile_key = other.profile_key;
+        }
+        if other.profile_name.is_some() {
+            self.profile_name = other.profile_name;
+        }
+        if other.expire_timer.is_some() {
+            self.expire_timer = other.expire_timer;
+        }
+    }
+}
+
+impl From<ContactDetails> for Contact {
+    fn from(details: ContactDetails) -> Self {
+        Self {
+            uuid: details.uuid,
+            number: details.number,
+            name: details.name.filter(|name| !name.is_empty()),
+            profile_key: details.profile_key,
+            profile_name: None,
+            expire_timer: details.expire_timer,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SledContactStore(Tree);
+
+impl TryFrom<&Db> for SledContactStore {
+    type Error = sled::Error;
+    fn try_from(db: &Db) -> std::result::Result<Self, Self::Error> {
+        Ok(Self(db.open_tree("contacts")?))
+    }
+}
+
+impl SledContactStore {
+    fn get(&self, identifier: &str) -> Result<Option<Contact>> {
+        match self.0.get(identifier)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn list(&self) -> Result<Vec<Contact>> {
+        self.0
+            .iter()
+            .map(|pair| Ok(serde_json::from_slice(&pair?.1)?))
+            .collect()
+    }
+
+    fn find_by_number(&self, number: &str) -> Result<Option<Contact>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .find(|contact| contact.number.as_deref() == Some(number)))
+    }
+
+    /// Finds contact by its UUID, phone number or name.
+    pub(crate) fn find(&self, query: &str) -> Result<Option<Contact>> {
+        if let Ok(uuid) = Uuid::parse_str(query) {
+            return self.get(&uuid.to_string());
+        }
+        if let Some(contact) = self.find_by_number(query)? {
+            return Ok(Some(contact));
+        }
+
+        let mut matches = self.list()?.into_iter().filter(|contact| {
+            contact
+                .name
+                .as_deref()
+                .map_or(false, |name| name.to_lowercase() == query.to_lowercase())
+        });
+        match (matches.next(), matches.next()) {
+            (Some(_), Some(_)) => Err(Error::AmbiguousContact(query.to_string())),
+            (contact, _) => Ok(contact),
+        }
+    }
+
+    /// Merges `update` into the stored contact with the same UUID or phone number
+    /// and returns the result. Contact known only by the phone number is re-keyed,
+    /// once its UUID is learned.
+    pub(crate) fn upsert(&self, update: &Contact) -> Result<Contact> {
+        // Contact with UUID is never merged into another UUID
+        let by_number = match &update.number {
+            Some(number) => self.list()?.into_iter().find(|contact| {
+                contact.number.as_deref() == Some(number)
+                    && (contact.uuid.is_none() || update.uuid.is_none())
+            }),
+            None => None,
+        };
+        let by_uuid = match &update.uuid {
+            Some(uuid) => self.get(uuid)?,
+            None => None,
+        };
+
+        let mut batch = Batch::default();
+        let mut contact = by_uuid.unwrap_or_default();
+        if let Some(mut other) = by_number {
+            if let Some(identifier) = other.identifier() {
+                batch.remove(identifier.as_bytes());
+            }
+            other.merge(contact);
+            contact = other;
+        }
+        contact.merge(update.clone());
+
+        let identifier = contact.identifier().ok_or_else(|| {
+            Error::InvalidContact(String::from("Contact needs UUID or phone number"))
+        })?;
+        batch.insert(identifier.as_bytes(), serde_json::to_vec(&contact)?);
+        self.0.apply_batch(batch)?;
+        Ok(contact)
+    }
+
+    pub(crate) fn remove(&self, contact: &Contact) -> Result<()> {
+        if let Some(identifier) = contact.identifier() {
+            self.0.remove(identifier)?;
+        }
+        Ok(())
+    }
+
+    /// Resolves recipient given by UUID, phone number or contact name
+    /// to the identifier used for sending messages.
+    pub(crate) fn resolve(&self, recipient: &str) -> Result<String> {
+        if let Some(identifier) = self.find(recipient)?.as_ref().and_then(Contact::identifier) {
+            return Ok(identifier.to_string());
+        }
+        if let Ok(uuid) = Uuid::parse_str(recipient) {
+            Ok(uuid.to_string())
+        } else if recipient.starts_with('+') {
+            Ok(recipient.to_string())
+        } else {
+            Err(Error::UnknownContact(recipient.to_string()))
+        }
+    }
+}
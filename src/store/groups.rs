@@ -0,0 +1,67 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use crate::error::Result;
+use crate::proto::signal_service::GroupDetails;
+use crate::utils::serde::{deserialize_byte_vec, serialize_byte_vec};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Group {
+    #[serde(
+        serialize_with = "serialize_byte_vec",
+        deserialize_with = "deserialize_byte_vec"
+    )]
+    pub(crate) id: Vec<u8>,
+    pub(crate) name: Option<String>,
+    /// UUIDs of the members, or phone numbers of members with unknown UUID
+    pub(crate) members: Vec<String>,
+    pub(crate) active: bool,
+}
+
+impl From<GroupDetails> for Group {
+    fn from(details: GroupDetails) -> Self {
+        let mut members: Vec<String> = details
+            .members
+            .iter()
+            .filter_map(|member| member.uuid.clone().or_else(|| member.e164.clone()))
+            .collect();
+        if members.is_empty() {
+            // Older clients sync only phone numbers of the members
+            members = details.members_e164.clone();
+        }
+
+        Self {
+            active: details.active(),
+            id: details.id.unwrap_or_default(),
+            name: details.name.filter(|name| !name.is_empty()),
+            members,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SledGroupStore(Tree);
+
+impl TryFrom<&Db> for SledGroupStore {
+    type Error = sled::Error;
+    fn try_from(db: &Db) -> std::result::Result<Self, Self::Error> {
+        Ok(Self(db.open_tree("groups")?))
+    }
+}
+
+impl SledGroupStore {
+    pub(crate) fn list(&self) -> Result<Vec<Group>> {
+        self.0
+            .iter()
+            .map(|pair| Ok(serde_json::from_slice(&pair?.1)?))
+            .collect()
+    }
+
+    pub(crate) fn save(&self, group: &Group) -> Result<()> {
+        self.0
+            .insert(group.id.as_slice(), serde_json::to_vec(group)?)?;
+        Ok(())
+    }
+}
@@ -3,19 +3,24 @@ use std::convert::{TryFrom, TryInto};
 use async_trait::async_trait;
 use libsignal_protocol::error::Result as SignalResult;
 use libsignal_protocol::{
-    Context, Direction, IdentityKey, IdentityKeyPair, IdentityKeyStore, ProtocolAddress,
+    Context, DeviceId, Direction, IdentityKey, IdentityKeyPair, IdentityKeyStore, ProtocolAddress,
     SignalProtocolError,
 };
-use sled::{Db, Tree};
+use sled::{Batch, Db, Tree};
 
 use crate::error::{Error, Result};
 
-use super::utils::{sled_to_signal_error, ProtocolAddressBytes};
+use super::utils::{rename_addresses, sled_to_signal_error, ProtocolAddressBytes};
+use super::TreeDump;
 
 const IDENTITY_KEY_PAIR_KEY: &[u8] = b"identity_key_pair";
 const REGISTRATION_ID_KEY: &[u8] = b"registration_id";
 const ADDRESS_KEY: &[u8] = b"address";
 const API_PASS_KEY: &[u8] = b"api_pass";
+const NUMBER_KEY: &[u8] = b"number";
+const DEVICE_NAME_KEY: &[u8] = b"device_name";
+const PROFILE_KEY_KEY: &[u8] = b"profile_key";
+const CREDENTIALS_TREE: &[u8] = b"credentials";
 
 #[derive(Clone)]
 pub(crate) struct SledIdentityStore {
@@ -28,7 +33,7 @@ impl TryFrom<&Db> for SledIdentityStore {
     fn try_from(value: &Db) -> std::result::Result<Self, Self::Error> {
         Ok(Self {
             known_keys: value.open_tree("identities")?,
-            credentials: value.open_tree("credentials")?,
+            credentials: value.open_tree(CREDENTIALS_TREE)?,
         })
     }
 }
@@ -41,6 +46,68 @@ impl SledIdentityStore {
         }
     }
 
+    /// Moves known identity keys of the recipient from one name to another.
+    pub(crate) fn rename_identities(&self, from: &str, to: &str) -> Result<()> {
+        Ok(rename_addresses(&self.known_keys, from, to)?)
+    }
+
+    /// Trusts the identity key for all known devices of the recipient and for `devices`,
+    /// replacing keys trusted before.
+    pub(crate) fn trust_identity(
+        &self,
+        name: &str,
+        devices: &[DeviceId],
+        identity: &IdentityKey,
+    ) -> Result<()> {
+        let identity = identity.serialize();
+        let mut batch = Batch::default();
+        for key in self.known_keys.scan_prefix(name).keys() {
+            let key = key?;
+            let bytes = ProtocolAddressBytes::new(key.to_vec().into_boxed_slice());
+            if bytes.name_bytes() == name.as_bytes() {
+                batch.insert(key, &*identity);
+            }
+        }
+        for device_id in devices {
+            let address = ProtocolAddress::new(name.to_string(), *device_id);
+            batch.insert(ProtocolAddressBytes::from(&address).as_ref(), &*identity);
+        }
+        Ok(self.known_keys.apply_batch(batch)?)
+    }
+
+    pub(crate) fn is_registered(&self) -> Result<bool> {
+        Ok(self.credentials.contains_key(ADDRESS_KEY)?)
+    }
+
+    pub(crate) fn get_number(&self) -> Result<Option<String>> {
+        Ok(self
+            .credentials
+            .get(NUMBER_KEY)?
+            .map(|value| String::from_utf8_lossy(&value).to_string()))
+    }
+
+    /// Reads the address and phone number of the account stored in a database dump.
+    pub(crate) fn account_from_dump(
+        trees: &[TreeDump],
+    ) -> Result<(ProtocolAddress, Option<String>)> {
+        let credentials = trees
+            .iter()
+            .find(|(name, _)| name == CREDENTIALS_TREE)
+            .map(|(_, entries)| entries)
+            .ok_or(Error::Uninitialized)?;
+        let find = |key: &[u8]| {
+            credentials
+                .iter()
+                .find(|(entry_key, _)| entry_key == key)
+                .map(|(_, value)| value.clone())
+        };
+
+        let address = find(ADDRESS_KEY).ok_or(Error::Uninitialized)?;
+        let address = ProtocolAddressBytes::new(address.into_boxed_slice()).into();
+        let number = find(NUMBER_KEY).map(|value| String::from_utf8_lossy(&value).to_string());
+        Ok((address, number))
+    }
+
     pub(crate) fn get_api_user(&self) -> Result<String> {
         Ok(self.get_address()?.to_string())
     }
@@ -51,11 +118,39 @@ impl SledIdentityStore {
             None => Err(Error::Uninitialized),
         }
     }
+
+    pub(crate) fn get_device_name(&self) -> Result<Option<String>> {
+        Ok(self
+            .credentials
+            .get(DEVICE_NAME_KEY)?
+            .map(|value| String::from_utf8_lossy(&value).to_string()))
+    }
+
+    pub(crate) fn set_device_name(&self, name: &str) -> Result<()> {
+        self.credentials.insert(DEVICE_NAME_KEY, name.as_bytes())?;
+        Ok(())
+    }
+
+    pub(crate) fn get_profile_key(&self) -> Result<Option<[u8; 32]>> {
+        Ok(self.credentials.get(PROFILE_KEY_KEY)?.map(|value| {
+            value
+                .as_ref()
+                .try_into()
+                .expect("Stored bytes are valid profile key")
+        }))
+    }
+
+    pub(crate) fn set_profile_key(&self, profile_key: &[u8; 32]) -> Result<()> {
+        self.credentials.insert(PROFILE_KEY_KEY, &profile_key[..])?;
+        Ok(())
+    }
+
     pub(crate) fn register_new_account(
         &self,
         identity_key_pair: IdentityKeyPair,
         registration_id: u32,
         address: ProtocolAddress,
+        number: &str,
         api_pass: String,
     ) -> Result<()> {
         self.credentials
@@ -64,6 +159,7 @@ impl SledIdentityStore {
             .insert(REGISTRATION_ID_KEY, &registration_id.to_le_bytes())?;
         self.credentials
             .insert(ADDRESS_KEY, ProtocolAddressBytes::from(&address).as_ref())?;
+        self.credentials.insert(NUMBER_KEY, number.as_bytes())?;
         self.credentials.insert(API_PASS_KEY, api_pass.as_bytes())?;
         Ok(())
     }
@@ -0,0 +1,105 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use crate::error::Result;
+use crate::utils::serde::{
+    deserialize_byte_vec, deserialize_opt_byte_vec, serialize_byte_vec, serialize_opt_byte_vec,
+};
+
+/// Incoming message taken off the server, which was not handed over to the user yet.
+/// Messages stay here when their decryption or processing fails, so they can be retried
+/// or inspected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct InboxEntry {
+    /// Increasing id, which keeps the order in which messages were received
+    pub(crate) id: u64,
+    /// Identifier of the envelope assigned by the server
+    pub(crate) guid: String,
+    /// Serialized `Envelope` of the message, until it is decrypted
+    #[serde(
+        default,
+        serialize_with = "serialize_opt_byte_vec",
+        deserialize_with = "deserialize_opt_byte_vec"
+    )]
+    pub(crate) envelope: Option<Vec<u8>>,
+    /// Decrypted message. Decryption can't be repeated, so it is kept once decrypted.
+    pub(crate) message: Option<InboxMessage>,
+    /// Whether the message was processed and only waits for the user
+    pub(crate) processed: bool,
+    /// Number of failed attempts to decrypt or process the message
+    pub(crate) attempts: u32,
+    #[serde(rename = "lastError")]
+    pub(crate) last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct InboxMessage {
+    /// UUID of the sender
+    pub(crate) sender: String,
+    #[serde(rename = "senderDevice")]
+    pub(crate) sender_device: u32,
+    #[serde(rename = "senderName")]
+    pub(crate) sender_name: String,
+    pub(crate) timestamp: u64,
+    /// Serialized `Content` of the message
+    #[serde(
+        serialize_with = "serialize_byte_vec",
+        deserialize_with = "deserialize_byte_vec"
+    )]
+    pub(crate) content: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub(crate) struct SledInboxStore {
+    db: Db,
+    tree: Tree,
+}
+
+impl TryFrom<&Db> for SledInboxStore {
+    type Error = sled::Error;
+    fn try_from(db: &Db) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            db: db.clone(),
+            tree: db.open_tree("inbox")?,
+        })
+    }
+}
+
+impl SledInboxStore {
+    /// Lists received messages in the order they were received.
+    pub(crate) fn list(&self) -> Result<Vec<InboxEntry>> {
+        self.tree
+            .iter()
+            .map(|pair| Ok(serde_json::from_slice(&pair?.1)?))
+            .collect()
+    }
+
+    /// Stores the encrypted envelope. The tree is flushed, since the server
+    /// deletes the envelope once we acknowledge it.
+    pub(crate) fn push(&self, guid: &str, envelope: Vec<u8>) -> Result<InboxEntry> {
+        let entry = InboxEntry {
+            id: self.db.generate_id()?,
+            guid: guid.to_string(),
+            envelope: Some(envelope),
+            message: None,
+            processed: false,
+            attempts: 0,
+            last_error: None,
+        };
+        self.save(&entry)?;
+        self.tree.flush()?;
+        Ok(entry)
+    }
+
+    pub(crate) fn save(&self, entry: &InboxEntry) -> Result<()> {
+        self.tree
+            .insert(entry.id.to_be_bytes(), serde_json::to_vec(entry)?)?;
+        Ok(())
+    }
+
+    pub(crate) fn remove(&self, id: u64) -> Result<bool> {
+        Ok(self.tree.remove(id.to_be_bytes())?.is_some())
+    }
+}
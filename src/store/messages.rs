@@ -0,0 +1,196 @@
+use std::convert::TryFrom;
+
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db, Tree};
+
+use crate::error::Result;
+use crate::proto::signal_service::data_message::BodyRange;
+use crate::proto::signal_service::AttachmentPointer;
+use crate::utils::serde::{deserialize_byte_vec, serialize_byte_vec};
+
+/// Text message kept in the local history of the conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StoredMessage {
+    /// Identifier of the contact the conversation is with, base64 encoded id for groups
+    pub(crate) conversation: String,
+    /// Identifier of the sender, our own UUID for outgoing messages
+    pub(crate) author: String,
+    pub(crate) timestamp: u64,
+    pub(crate) body: Option<String>,
+    #[serde(rename = "bodyRanges", default)]
+    pub(crate) body_ranges: Vec<StoredBodyRange>,
+    #[serde(default)]
+    pub(crate) attachments: Vec<StoredAttachment>,
+    /// Time in milliseconds since epoch, after which the message is purged.
+    /// Set only in conversations with disappearing messages timer.
+    #[serde(rename = "expiresAt")]
+    pub(crate) expires_at: Option<u64>,
+}
+
+/// Mention or style of the part of the body, offsets are in UTF-16 code units
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StoredBodyRange {
+    pub(crate) start: u32,
+    pub(crate) length: u32,
+    #[serde(rename = "mentionUuid")]
+    pub(crate) mention_uuid: Option<String>,
+    #[serde(default)]
+    pub(crate) style: Option<i32>,
+}
+
+impl From<&BodyRange> for StoredBodyRange {
+    fn from(range: &BodyRange) -> Self {
+        Self {
+            start: range.start(),
+            length: range.length(),
+            mention_uuid: range.mention_uuid.clone(),
+            style: range.style,
+        }
+    }
+}
+
+impl From<&StoredBodyRange> for BodyRange {
+    fn from(range: &StoredBodyRange) -> Self {
+        Self {
+            start: Some(range.start),
+            length: Some(range.length),
+            mention_uuid: range.mention_uuid.clone(),
+            style: range.style,
+        }
+    }
+}
+
+/// Attachment of the message, kept so it can be referenced by quotes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StoredAttachment {
+    #[serde(rename = "contentType")]
+    pub(crate) content_type: Option<String>,
+    #[serde(rename = "fileName")]
+    pub(crate) file_name: Option<String>,
+    /// Serialized `AttachmentPointer` of the attachment
+    #[serde(
+        serialize_with = "serialize_byte_vec",
+        deserialize_with = "deserialize_byte_vec"
+    )]
+    pub(crate) pointer: Vec<u8>,
+}
+
+impl From<&AttachmentPointer> for StoredAttachment {
+    fn from(pointer: &AttachmentPointer) -> Self {
+        Self {
+            content_type: pointer.content_type.clone(),
+            file_name: pointer.file_name.clone(),
+            pointer: pointer.encode_to_vec(),
+        }
+    }
+}
+
+impl StoredMessage {
+    fn key(conversation: &str, timestamp: u64) -> Vec<u8> {
+        let mut key = Vec::with_capacity(conversation.len() + 9);
+        key.extend_from_slice(conversation.as_bytes());
+        key.push(b'/');
+        key.extend_from_slice(&timestamp.to_be_bytes());
+        key
+    }
+
+    /// Expiry index is keyed by the expiry time followed by the key of the message,
+    /// so expired messages are found without scanning the history.
+    fn expiry_key(&self) -> Option<Vec<u8>> {
+        self.expires_at.map(|expires_at| {
+            let mut key = expires_at.to_be_bytes().to_vec();
+            key.extend_from_slice(&Self::key(&self.conversation, self.timestamp));
+            key
+        })
+    }
+}
+
+/// Local history of conversations. Messages are kept until removed, only those of
+/// conversations with a disappearing messages timer expire and are purged.
+#[derive(Clone)]
+pub(crate) struct SledMessageStore {
+    messages: Tree,
+    expiry: Tree,
+}
+
+impl TryFrom<&Db> for SledMessageStore {
+    type Error = sled::Error;
+    fn try_from(db: &Db) -> std::result::Result<Self, Self::Error> {
+        let indexed = db
+            .tree_names()
+            .iter()
+            .any(|name| &name[..] == b"message_expiry");
+        let store = Self {
+            messages: db.open_tree("messages")?,
+            expiry: db.open_tree("message_expiry")?,
+        };
+        if !indexed {
+            store.rebuild_expiry_index()?;
+        }
+        Ok(store)
+    }
+}
+
+impl SledMessageStore {
+    /// Indexes the history anew, e.g. messages stored before the index existed.
+    pub(crate) fn rebuild_expiry_index(&self) -> std::result::Result<(), sled::Error> {
+        self.expiry.clear()?;
+        let mut batch = Batch::default();
+        for pair in self.messages.iter() {
+            let (_, value) = pair?;
+            // Malformed messages never expire, as they never did before
+            if let Ok(message) = serde_json::from_slice::<StoredMessage>(&value) {
+                if let Some(key) = message.expiry_key() {
+                    batch.insert(key, Vec::new());
+                }
+            }
+        }
+        self.expiry.apply_batch(batch)
+    }
+
+    pub(crate) fn get(&self, conversation: &str, timestamp: u64) -> Result<Option<StoredMessage>> {
+        match self
+            .messages
+            .get(StoredMessage::key(conversation, timestamp))?
+        {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn save(&self, message: &StoredMessage) -> Result<()> {
+        if let Some(previous) = self.get(&message.conversation, message.timestamp)? {
+            if let Some(key) = previous.expiry_key() {
+                self.expiry.remove(key)?;
+            }
+        }
+        self.messages.insert(
+            StoredMessage::key(&message.conversation, message.timestamp),
+            serde_json::to_vec(message)?,
+        )?;
+        if let Some(key) = message.expiry_key() {
+            self.expiry.insert(key, Vec::new())?;
+        }
+        Ok(())
+    }
+
+    /// Removes messages whose disappearing timer elapsed before `now`.
+    /// Returns the number of removed messages.
+    pub(crate) fn purge_expired(&self, now: u64) -> Result<usize> {
+        let mut messages = Batch::default();
+        let mut expiry = Batch::default();
+        let mut count = 0;
+        for pair in self.expiry.range(..now.saturating_add(1).to_be_bytes()) {
+            let (key, _) = pair?;
+            messages.remove(&key[8..]);
+            expiry.remove(key);
+            count += 1;
+        }
+        if count > 0 {
+            self.messages.apply_batch(messages)?;
+            self.expiry.apply_batch(expiry)?;
+        }
+        Ok(count)
+    }
+}
@@ -1,13 +1,45 @@
+mod blocked;
+mod contacts;
+mod groups;
 mod identity;
+mod inbox;
+mod messages;
+mod outbox;
 mod pre_key;
+mod profiles;
+mod registry;
 mod session;
+mod settings;
 mod signed_pre_key;
 mod state_store;
+mod stickers;
 mod utils;
+mod view_once;
 
+use blocked::SledBlockedStore;
+use contacts::SledContactStore;
+use groups::SledGroupStore;
 use identity::SledIdentityStore;
+use inbox::SledInboxStore;
+use messages::SledMessageStore;
+use outbox::SledOutboxStore;
 use pre_key::SledPreKeyStore;
+use profiles::SledProfileStore;
 use session::SledSessionStore;
+use settings::SledSettingsStore;
 use signed_pre_key::SledSignedPreKeyStore;
+use stickers::SledStickerStore;
+use view_once::{SledViewOnceStore, VIEW_ONCE_TREE};
 
-pub(crate) use state_store::SledStateStore;
+pub(crate) use blocked::BlockedList;
+pub(crate) use contacts::Contact;
+pub(crate) use groups::Group;
+pub(crate) use inbox::{InboxEntry, InboxMessage};
+pub(crate) use messages::{StoredAttachment, StoredBodyRange, StoredMessage};
+pub(crate) use outbox::OutboxEntry;
+pub(crate) use profiles::Profile;
+pub(crate) use registry::{AccountEntry, AccountRegistry};
+pub(crate) use settings::Settings;
+pub(crate) use state_store::{SledStateStore, TreeDump};
+pub(crate) use stickers::StoredStickerPack;
+pub(crate) use view_once::ViewOnceMedia;
@@ -0,0 +1,164 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use super::StoredMessage;
+use crate::error::Result;
+use crate::utils::serde::{deserialize_byte_vec, serialize_byte_vec};
+
+/// Outgoing message waiting for delivery
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct OutboxEntry {
+    /// Increasing id, which keeps the order of messages to the same recipient
+    pub(crate) id: u64,
+    /// Identifier of the recipient
+    pub(crate) recipient: String,
+    /// Sent timestamp of the message
+    pub(crate) timestamp: u64,
+    /// Serialized `Content` of the message
+    #[serde(
+        serialize_with = "serialize_byte_vec",
+        deserialize_with = "deserialize_byte_vec"
+    )]
+    pub(crate) content: Vec<u8>,
+    /// Number of failed delivery attempts
+    pub(crate) attempts: u32,
+    /// Time in milliseconds since epoch, before which the delivery is not retried
+    #[serde(rename = "nextAttempt")]
+    pub(crate) next_attempt: u64,
+    #[serde(rename = "lastError")]
+    pub(crate) last_error: Option<String>,
+    /// Message added to the history once the message is delivered
+    #[serde(default)]
+    pub(crate) history: Option<StoredMessage>,
+}
+
+impl OutboxEntry {
+    /// Entries are keyed by the recipient and id, so they are iterated grouped
+    /// by the recipient in the order they were queued.
+    fn key(recipient: &str, id: u64) -> Vec<u8> {
+        let mut key = Vec::with_capacity(recipient.len() + 9);
+        key.extend_from_slice(recipient.as_bytes());
+        key.push(b'/');
+        key.extend_from_slice(&id.to_be_bytes());
+        key
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SledOutboxStore {
+    db: Db,
+    tree: Tree,
+}
+
+impl TryFrom<&Db> for SledOutboxStore {
+    type Error = sled::Error;
+    fn try_from(db: &Db) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            db: db.clone(),
+            tree: db.open_tree("outbox")?,
+        })
+    }
+}
+
+impl SledOutboxStore {
+    /// Lists queued messages, only those to `recipient` if given.
+    pub(crate) fn list(&self, recipient: Option<&str>) -> Result<Vec<OutboxEntry>> {
+        let entries = match recipient {
+            Some(recipient) => {
+                let mut prefix = recipient.as_bytes().to_vec();
+                prefix.push(b'/');
+                self.tree.scan_prefix(prefix)
+            }
+            None => self.tree.iter(),
+        };
+        entries
+            .map(|pair| Ok(serde_json::from_slice(&pair?.1)?))
+            .collect()
+    }
+
+    pub(crate) fn get(&self, recipient: &str, id: u64) -> Result<Option<OutboxEntry>> {
+        match self.tree.get(OutboxEntry::key(recipient, id))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Queues the message behind those already queued for the recipient.
+    pub(crate) fn push(
+        &self,
+        recipient: &str,
+        content: Vec<u8>,
+        timestamp: u64,
+        history: Option<StoredMessage>,
+    ) -> Result<OutboxEntry> {
+        let entry = OutboxEntry {
+            id: self.db.generate_id()?,
+            recipient: recipient.to_string(),
+            timestamp,
+            content,
+            attempts: 0,
+            next_attempt: 0,
+            last_error: None,
+            history,
+        };
+        self.save(&entry)?;
+        Ok(entry)
+    }
+
+    pub(crate) fn save(&self, entry: &OutboxEntry) -> Result<()> {
+        self.tree.insert(
+            OutboxEntry::key(&entry.recipient, entry.id),
+            serde_json::to_vec(entry)?,
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn remove(&self, entry: &OutboxEntry) -> Result<()> {
+        self.tree
+            .remove(OutboxEntry::key(&entry.recipient, entry.id))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> SledOutboxStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        SledOutboxStore::try_from(&db).unwrap()
+    }
+
+    #[test]
+    fn messages_are_listed_in_queued_order_per_recipient() {
+        let store = store();
+        let first = store.push("bob", vec![1], 1, None).unwrap();
+        store.push("alice", vec![2], 2, None).unwrap();
+        let third = store.push("bob", vec![3], 3, None).unwrap();
+        store.push("alice", vec![4], 4, None).unwrap();
+
+        let contents = |entries: Vec<OutboxEntry>| {
+            entries
+                .into_iter()
+                .map(|entry| entry.content[0])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(contents(store.list(None).unwrap()), [2, 4, 1, 3]);
+        assert_eq!(contents(store.list(Some("bob")).unwrap()), [1, 3]);
+
+        store.remove(&first).unwrap();
+        assert_eq!(contents(store.list(Some("bob")).unwrap()), [3]);
+        assert_eq!(store.get("bob", third.id).unwrap().unwrap().timestamp, 3);
+        assert!(store.get("bob", first.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn recipient_prefix_doesnt_match_other_recipients() {
+        let store = store();
+        store.push("alice", vec![1], 1, None).unwrap();
+        store.push("alice2", vec![2], 2, None).unwrap();
+        assert_eq!(store.list(Some("alice")).unwrap().len(), 1);
+    }
+}
@@ -7,6 +7,7 @@ use sled::{Db, Tree};
 
 use super::utils::sled_to_signal_error;
 
+#[derive(Clone)]
 pub(crate) struct SledPreKeyStore(Tree);
 
 impl TryFrom<&Db> for SledPreKeyStore {
@@ -0,0 +1,54 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use crate::error::Result;
+use crate::utils::serde::{deserialize_opt_byte_vec, serialize_opt_byte_vec};
+
+/// Decrypted profile of a contact
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Profile {
+    #[serde(rename = "givenName")]
+    pub(crate) given_name: Option<String>,
+    #[serde(rename = "familyName")]
+    pub(crate) family_name: Option<String>,
+    pub(crate) about: Option<String>,
+    #[serde(rename = "aboutEmoji")]
+    pub(crate) about_emoji: Option<String>,
+    #[serde(
+        default,
+        serialize_with = "serialize_opt_byte_vec",
+        deserialize_with = "deserialize_opt_byte_vec"
+    )]
+    pub(crate) avatar: Option<Vec<u8>>,
+    /// Time of the fetch in milliseconds since epoch
+    pub(crate) fetched: u64,
+}
+
+impl Profile {
+    pub(crate) fn display_name(&self) -> Option<String> {
+        match (&self.given_name, &self.family_name) {
+            (Some(given), Some(family)) => Some(format!("{} {}", given, family)),
+            (Some(name), None) | (None, Some(name)) => Some(name.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SledProfileStore(Tree);
+
+impl TryFrom<&Db> for SledProfileStore {
+    type Error = sled::Error;
+    fn try_from(db: &Db) -> std::result::Result<Self, Self::Error> {
+        Ok(Self(db.open_tree("profiles")?))
+    }
+}
+
+impl SledProfileStore {
+    pub(crate) fn save(&self, uuid: &str, profile: &Profile) -> Result<()> {
+        self.0.insert(uuid, serde_json::to_vec(profile)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use libsignal_protocol::ProtocolAddress;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::error::{Error, Result};
+
+use super::SledStateStore;
+
+const REGISTRY_FILE: &str = "accounts.json";
+// Marker file of sled database created directly in the data dir by older versions
+const LEGACY_DB_FILE: &str = "db";
+const LEGACY_ACCOUNT_DIR: &str = ".";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AccountEntry {
+    pub(crate) uuid: String,
+    pub(crate) number: Option<String>,
+    #[serde(rename = "deviceId")]
+    pub(crate) device_id: u32,
+    path: PathBuf,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct RegistryFile {
+    accounts: Vec<AccountEntry>,
+}
+
+/// Keeps track of all accounts stored in a data dir. Every account has its own
+/// sled database in a subdirectory named by the account UUID.
+pub(crate) struct AccountRegistry {
+    data_dir: PathBuf,
+    accounts: Vec<AccountEntry>,
+}
+
+impl AccountEntry {
+    fn matches(&self, selector: &str) -> bool {
+        self.uuid == selector || self.number.as_deref() == Some(selector)
+    }
+}
+
+impl AccountRegistry {
+    pub(crate) fn load(data_dir: PathBuf) -> Result<Self> {
+        let registry_path = data_dir.join(REGISTRY_FILE);
+        let mut registry = if registry_path.exists() {
+            let file: RegistryFile = serde_json::from_slice(&fs::read(&registry_path)?)?;
+            Self {
+                data_dir,
+                accounts: file.accounts,
+            }
+        } else {
+            Self {
+                data_dir,
+                accounts: Vec::new(),
+            }
+        };
+
+        if !registry_path.exists() && registry.data_dir.join(LEGACY_DB_FILE).exists() {
+            registry.adopt_legacy_account()?;
+        }
+
+        Ok(registry)
+    }
+
+    /// Registers account from database created before multi-account support.
+    fn adopt_legacy_account(&mut self) -> Result<()> {
+        let state_store = SledStateStore::new(&self.data_dir)?;
+        if state_store.is_registered()? {
+            let address = state_store.address()?;
+            self.accounts.push(AccountEntry {
+                uuid: address.name().to_string(),
+                number: state_store.number()?,
+                device_id: address.device_id().into(),
+                path: PathBuf::from(LEGACY_ACCOUNT_DIR),
+            });
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        let file = RegistryFile {
+            accounts: self.accounts.clone(),
+        };
+        // Custom data dir may not exist yet, sled used to create it along with the database
+        fs::create_dir_all(&self.data_dir)?;
+        let tmp_path = self.data_dir.join(format!("{}.tmp", REGISTRY_FILE));
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&file)?)?;
+        fs::rename(tmp_path, self.data_dir.join(REGISTRY_FILE))?;
+        Ok(())
+    }
+
+    pub(crate) fn accounts(&self) -> &[AccountEntry] {
+        &self.accounts
+    }
+
+    /// Finds account by UUID or E164 number. Selector can be omitted only if there is exactly
+    /// one account.
+    pub(crate) fn find(&self, selector: Option<&str>) -> Result<&AccountEntry> {
+        match selector {
+            Some(selector) => self
+                .accounts
+                .iter()
+                .find(|entry| entry.matches(selector))
+                .ok_or_else(|| Error::UnknownAccount(selector.to_string())),
+            None => match self.accounts.as_slice() {
+                [entry] => Ok(entry),
+                [] => Err(Error::Uninitialized),
+                _ => Err(Error::AccountSelectionRequired),
+            },
+        }
+    }
+
+    pub(crate) fn account_dir(&self, entry: &AccountEntry) -> PathBuf {
+        self.data_dir.join(&entry.path)
+    }
+
+    fn is_legacy(entry: &AccountEntry) -> bool {
+        entry.path == Path::new(LEGACY_ACCOUNT_DIR)
+    }
+
+    /// Adds the account to the registry or updates already known one.
+    /// Returns directory where the account data should be stored.
+    pub(crate) fn add(
+        &mut self,
+        address: &ProtocolAddress,
+        number: Option<&str>,
+    ) -> Result<PathBuf> {
+        let uuid = address.name();
+        let device_id = address.device_id().into();
+        let index = match self.accounts.iter().position(|entry| entry.uuid == uuid) {
+            Some(index) => {
+                let entry = &mut self.accounts[index];
+                entry.device_id = device_id;
+                if number.is_some() {
+                    entry.number = number.map(ToString::to_string);
+                }
+                index
+            }
+            None => {
+                self.accounts.push(AccountEntry {
+                    uuid: uuid.to_string(),
+                    number: number.map(ToString::to_string),
+                    device_id,
+                    path: PathBuf::from(uuid),
+                });
+                self.accounts.len() - 1
+            }
+        };
+        self.save()?;
+        Ok(self.account_dir(&self.accounts[index]))
+    }
+
+    /// Removes the account from the registry and deletes all its data.
+    pub(crate) fn remove(&mut self, selector: &str) -> Result<AccountEntry> {
+        let index = self
+            .accounts
+            .iter()
+            .position(|entry| entry.matches(selector))
+            .ok_or_else(|| Error::UnknownAccount(selector.to_string()))?;
+        let entry = self.accounts.remove(index);
+
+        if Self::is_legacy(&entry) {
+            // Legacy database shares the directory with the registry, so only wipe its content
+            SledStateStore::new(&self.data_dir)?.wipe()?;
+        } else {
+            let account_dir = self.account_dir(&entry);
+            if account_dir.exists() {
+                fs::remove_dir_all(account_dir)?;
+            }
+        }
+        self.save()?;
+        Ok(entry)
+    }
+
+    #[instrument(skip(self))]
+    pub(crate) fn open(&self, selector: Option<&str>) -> Result<SledStateStore> {
+        let entry = self.find(selector)?;
+        SledStateStore::new(self.account_dir(entry))
+    }
+
+    /// Opens stores of all accounts. Each account has separate database,
+    /// so all of them can be used at the same time.
+    pub(crate) fn open_all(&self) -> Result<Vec<(&AccountEntry, SledStateStore)>> {
+        self.accounts
+            .iter()
+            .map(|entry| Ok((entry, SledStateStore::new(self.account_dir(entry))?)))
+            .collect()
+    }
+}
@@ -5,7 +5,7 @@ use libsignal_protocol::error::Result as SignalResult;
 use libsignal_protocol::{Context, ProtocolAddress, SessionRecord, SessionStore};
 use sled::{Db, Tree};
 
-use super::utils::{sled_to_signal_error, ProtocolAddressBytes};
+use super::utils::{rename_addresses, sled_to_signal_error, ProtocolAddressBytes};
 
 #[derive(Clone)]
 pub(crate) struct SledSessionStore(Tree);
@@ -18,23 +18,35 @@ impl TryFrom<&Db> for SledSessionStore {
 }
 
 impl SledSessionStore {
-    pub(crate) async fn load_sessions_by_prefix(
+    /// Loads sessions with all devices of the recipient.
+    pub(crate) async fn load_sessions_by_name(
         &self,
-        prefix: &str,
+        name: &str,
     ) -> SignalResult<Vec<(ProtocolAddress, SessionRecord)>> {
         self.0
-            .scan_prefix(prefix)
-            .map(|pair| {
-                let (key, value) =
-                    pair.map_err(|err| sled_to_signal_error("load_sessions_by_prefix", err))?;
+            .scan_prefix(name)
+            .filter_map(|pair| {
+                let (key, value) = match pair {
+                    Ok(pair) => pair,
+                    Err(err) => {
+                        return Some(Err(sled_to_signal_error("load_sessions_by_name", err)))
+                    }
+                };
+                let bytes = ProtocolAddressBytes::new(key.to_vec().into_boxed_slice());
+                // Phone number can be a prefix of another phone number
+                if bytes.name_bytes() != name.as_bytes() {
+                    return None;
+                }
 
-                let address = ProtocolAddressBytes::new(key.to_vec().into_boxed_slice()).into();
-                let record = SessionRecord::deserialize(&value)?;
-
-                Ok((address, record))
+                Some(SessionRecord::deserialize(&value).map(|record| (bytes.into(), record)))
             })
             .collect()
     }
+
+    /// Moves sessions of the recipient from one name to another, e.g. from phone number to UUID.
+    pub(crate) fn rename(&self, from: &str, to: &str) -> sled::Result<()> {
+        rename_addresses(&self.0, from, to)
+    }
 }
 
 #[async_trait(?Send)]
@@ -0,0 +1,76 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use crate::error::Result;
+use crate::proto::signal_service::sync_message::Configuration;
+
+const CONFIGURATION_KEY: &[u8] = b"configuration";
+
+/// Account-wide preferences synced from the primary device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Settings {
+    #[serde(rename = "readReceipts")]
+    pub(crate) read_receipts: bool,
+    #[serde(rename = "unidentifiedDeliveryIndicators")]
+    pub(crate) unidentified_delivery_indicators: bool,
+    #[serde(rename = "typingIndicators")]
+    pub(crate) typing_indicators: bool,
+    #[serde(rename = "linkPreviews")]
+    pub(crate) link_previews: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            read_receipts: false,
+            unidentified_delivery_indicators: false,
+            typing_indicators: false,
+            link_previews: true,
+        }
+    }
+}
+
+impl Settings {
+    /// Applies values present in the configuration sync message.
+    pub(crate) fn update(&mut self, configuration: &Configuration) {
+        if let Some(value) = configuration.read_receipts {
+            self.read_receipts = value;
+        }
+        if let Some(value) = configuration.unidentified_delivery_indicators {
+            self.unidentified_delivery_indicators = value;
+        }
+        if let Some(value) = configuration.typing_indicators {
+            self.typing_indicators = value;
+        }
+        if let Some(value) = configuration.link_previews {
+            self.link_previews = value;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SledSettingsStore(Tree);
+
+impl TryFrom<&Db> for SledSettingsStore {
+    type Error = sled::Error;
+    fn try_from(db: &Db) -> std::result::Result<Self, Self::Error> {
+        Ok(Self(db.open_tree("settings")?))
+    }
+}
+
+impl SledSettingsStore {
+    pub(crate) fn get(&self) -> Result<Settings> {
+        match self.0.get(CONFIGURATION_KEY)? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Ok(Settings::default()),
+        }
+    }
+
+    pub(crate) fn set(&self, settings: &Settings) -> Result<()> {
+        self.0
+            .insert(CONFIGURATION_KEY, serde_json::to_vec(settings)?)?;
+        Ok(())
+    }
+}
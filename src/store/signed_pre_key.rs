@@ -9,6 +9,7 @@ use sled::{Db, Tree};
 
 use super::utils::sled_to_signal_error;
 
+#[derive(Clone)]
 pub(crate) struct SledSignedPreKeyStore(Tree);
 
 impl TryFrom<&Db> for SledSignedPreKeyStore {
@@ -1,37 +1,368 @@
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::path::Path;
 
 use async_trait::async_trait;
 use libsignal_protocol::error::Result as SignalResult;
 use libsignal_protocol::{
-    Context, Direction, IdentityKey, IdentityKeyPair, IdentityKeyStore, PreKeyId, PreKeyRecord,
-    PreKeyStore, ProtocolAddress, ProtocolStore, SessionRecord, SessionStore, SignedPreKeyId,
-    SignedPreKeyRecord, SignedPreKeyStore,
+    Context, DeviceId, Direction, IdentityKey, IdentityKeyPair, IdentityKeyStore, PreKeyId,
+    PreKeyRecord, PreKeyStore, ProtocolAddress, ProtocolStore, SessionRecord, SessionStore,
+    SignedPreKeyId, SignedPreKeyRecord, SignedPreKeyStore,
 };
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+use sled::Db;
+use tracing::{debug, instrument};
 
 use crate::error::Result;
 
-use super::{SledIdentityStore, SledPreKeyStore, SledSessionStore, SledSignedPreKeyStore};
+use super::{
+    BlockedList, Contact, Group, InboxEntry, OutboxEntry, Profile, Settings, SledBlockedStore,
+    SledContactStore, SledGroupStore, SledIdentityStore, SledInboxStore, SledMessageStore,
+    SledOutboxStore, SledPreKeyStore, SledProfileStore, SledSessionStore, SledSettingsStore,
+    SledSignedPreKeyStore, SledStickerStore, SledViewOnceStore, StoredMessage, StoredStickerPack,
+    ViewOnceMedia, VIEW_ONCE_TREE,
+};
+
+/// Name of a sled tree together with all of its key-value pairs.
+pub(crate) type TreeDump = (Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>);
 
 pub(crate) struct SledStateStore {
+    db: Db,
     pub(crate) session_store: SledSessionStore,
     pub(crate) pre_key_store: SledPreKeyStore,
     pub(crate) signed_pre_key_store: SledSignedPreKeyStore,
     pub(crate) identity_store: SledIdentityStore,
+    contact_store: SledContactStore,
+    group_store: SledGroupStore,
+    blocked_store: SledBlockedStore,
+    settings_store: SledSettingsStore,
+    profile_store: SledProfileStore,
+    message_store: SledMessageStore,
+    sticker_store: SledStickerStore,
+    view_once_store: SledViewOnceStore,
+    outbox_store: SledOutboxStore,
+    inbox_store: SledInboxStore,
 }
 
 impl SledStateStore {
+    #[instrument(skip_all, fields(dir = %data_dir.as_ref().display()))]
     pub(crate) fn new<P: AsRef<Path>>(data_dir: P) -> Result<Self> {
-        let db = &sled::open(data_dir)?;
+        let db = sled::open(data_dir)?;
+        debug!("opened database");
 
         Ok(Self {
-            session_store: db.try_into()?,
-            pre_key_store: db.try_into()?,
-            signed_pre_key_store: db.try_into()?,
-            identity_store: db.try_into()?,
+            session_store: (&db).try_into()?,
+            pre_key_store: (&db).try_into()?,
+            signed_pre_key_store: (&db).try_into()?,
+            identity_store: (&db).try_into()?,
+            contact_store: (&db).try_into()?,
+            group_store: (&db).try_into()?,
+            blocked_store: (&db).try_into()?,
+            settings_store: (&db).try_into()?,
+            profile_store: (&db).try_into()?,
+            message_store: (&db).try_into()?,
+            sticker_store: (&db).try_into()?,
+            view_once_store: (&db).try_into()?,
+            outbox_store: (&db).try_into()?,
+            inbox_store: (&db).try_into()?,
+            db,
+        })
+    }
+
+    pub(crate) fn is_registered(&self) -> Result<bool> {
+        self.identity_store.is_registered()
+    }
+
+    pub(crate) fn address(&self) -> Result<ProtocolAddress> {
+        self.identity_store.get_address()
+    }
+
+    pub(crate) fn number(&self) -> Result<Option<String>> {
+        self.identity_store.get_number()
+    }
+
+    /// Dumps every tree of the underlying database, except unopened view-once media.
+    pub(crate) fn export_trees(&self) -> Result<Vec<TreeDump>> {
+        self.db
+            .tree_names()
+            .into_iter()
+            .filter(|name| !is_unexported_tree(name))
+            .map(|name| {
+                let entries = self
+                    .db
+                    .open_tree(&name)?
+                    .iter()
+                    .map(|pair| pair.map(|(key, value)| (key.to_vec(), value.to_vec())))
+                    .collect::<sled::Result<Vec<_>>>()?;
+                Ok((name.to_vec(), entries))
+            })
+            .collect()
+    }
+
+    pub(crate) fn device_name(&self) -> Result<Option<String>> {
+        self.identity_store.get_device_name()
+    }
+
+    pub(crate) fn set_device_name(&self, name: &str) -> Result<()> {
+        self.identity_store.set_device_name(name)
+    }
+
+    pub(crate) fn profile_key(&self) -> Result<Option<[u8; 32]>> {
+        self.identity_store.get_profile_key()
+    }
+
+    pub(crate) fn set_profile_key(&self, profile_key: &[u8; 32]) -> Result<()> {
+        self.identity_store.set_profile_key(profile_key)
+    }
+
+    pub(crate) fn contacts(&self) -> Result<Vec<Contact>> {
+        self.contact_store.list()
+    }
+
+    pub(crate) fn find_contact(&self, query: &str) -> Result<Option<Contact>> {
+        self.contact_store.find(query)
+    }
+
+    pub(crate) fn resolve_recipient(&self, recipient: &str) -> Result<String> {
+        self.contact_store.resolve(recipient)
+    }
+
+    /// Stores what we learned about the contact. Once the phone number is paired with UUID,
+    /// sessions and identities kept under the phone number are moved under the UUID,
+    /// so the conversation isn't split between both forms.
+    pub(crate) fn save_contact(&self, contact: &Contact) -> Result<Contact> {
+        let contact = self.contact_store.upsert(contact)?;
+        if let (Some(uuid), Some(number)) = (&contact.uuid, &contact.number) {
+            self.session_store.rename(number, uuid)?;
+            self.identity_store.rename_identities(number, uuid)?;
+        }
+        Ok(contact)
+    }
+
+    pub(crate) fn trust_identity(
+        &self,
+        name: &str,
+        devices: &[DeviceId],
+        identity: &IdentityKey,
+    ) -> Result<()> {
+        self.identity_store.trust_identity(name, devices, identity)
+    }
+
+    pub(crate) fn remove_contact(&self, contact: &Contact) -> Result<()> {
+        self.contact_store.remove(contact)
+    }
+
+    /// Caches the profile and remembers its name as the profile name of the contact.
+    pub(crate) fn save_profile(&self, uuid: &str, profile: &Profile) -> Result<()> {
+        self.profile_store.save(uuid, profile)?;
+        self.save_contact(&Contact {
+            uuid: Some(uuid.to_string()),
+            profile_name: profile.display_name(),
+            ..Default::default()
+        })?;
+        Ok(())
+    }
+
+    pub(crate) fn message(
+        &self,
+        conversation: &str,
+        timestamp: u64,
+    ) -> Result<Option<StoredMessage>> {
+        self.message_store.get(conversation, timestamp)
+    }
+
+    pub(crate) fn save_message(&self, message: &StoredMessage) -> Result<()> {
+        self.message_store.save(message)
+    }
+
+    pub(crate) fn purge_expired_messages(&self, now: u64) -> Result<usize> {
+        self.message_store.purge_expired(now)
+    }
+
+    pub(crate) fn sticker_packs(&self) -> Result<Vec<StoredStickerPack>> {
+        self.sticker_store.list()
+    }
+
+    pub(crate) fn sticker_pack(&self, pack_id: &str) -> Result<Option<StoredStickerPack>> {
+        self.sticker_store.get(pack_id)
+    }
+
+    pub(crate) fn sticker_image(&self, pack_id: &str, sticker_id: u32) -> Result<Option<Vec<u8>>> {
+        self.sticker_store.image(pack_id, sticker_id)
+    }
+
+    pub(crate) fn save_sticker_pack(
+        &self,
+        pack: &StoredStickerPack,
+        images: &[(u32, Vec<u8>)],
+    ) -> Result<()> {
+        self.sticker_store.save(pack, images)
+    }
+
+    pub(crate) fn remove_sticker_pack(&self, pack_id: &str) -> Result<bool> {
+        self.sticker_store.remove(pack_id)
+    }
+
+    pub(crate) fn view_once_media(&self) -> Result<Vec<ViewOnceMedia>> {
+        self.view_once_store.list()
+    }
+
+    pub(crate) fn save_view_once_media(&self, media: &ViewOnceMedia) -> Result<()> {
+        self.view_once_store.save(media)
+    }
+
+    pub(crate) fn view_once_media_of(
+        &self,
+        sender: &str,
+        timestamp: u64,
+    ) -> Result<Option<ViewOnceMedia>> {
+        self.view_once_store.get(sender, timestamp)
+    }
+
+    pub(crate) fn remove_view_once_media(&self, sender: &str, timestamp: u64) -> Result<bool> {
+        self.view_once_store.remove(sender, timestamp)
+    }
+
+    pub(crate) fn outbox(&self, recipient: Option<&str>) -> Result<Vec<OutboxEntry>> {
+        self.outbox_store.list(recipient)
+    }
+
+    pub(crate) fn queued_message(&self, recipient: &str, id: u64) -> Result<Option<OutboxEntry>> {
+        self.outbox_store.get(recipient, id)
+    }
+
+    pub(crate) fn queue_message(
+        &self,
+        recipient: &str,
+        content: Vec<u8>,
+        timestamp: u64,
+        history: Option<StoredMessage>,
+    ) -> Result<OutboxEntry> {
+        self.outbox_store
+            .push(recipient, content, timestamp, history)
+    }
+
+    pub(crate) fn save_queued_message(&self, entry: &OutboxEntry) -> Result<()> {
+        self.outbox_store.save(entry)
+    }
+
+    pub(crate) fn remove_queued_message(&self, entry: &OutboxEntry) -> Result<()> {
+        self.outbox_store.remove(entry)
+    }
+
+    pub(crate) fn inbox(&self) -> Result<Vec<InboxEntry>> {
+        self.inbox_store.list()
+    }
+
+    pub(crate) fn push_inbox_entry(&self, guid: &str, envelope: Vec<u8>) -> Result<InboxEntry> {
+        self.inbox_store.push(guid, envelope)
+    }
+
+    pub(crate) fn save_inbox_entry(&self, entry: &InboxEntry) -> Result<()> {
+        self.inbox_store.save(entry)
+    }
+
+    pub(crate) fn remove_inbox_entry(&self, id: u64) -> Result<bool> {
+        self.inbox_store.remove(id)
+    }
+
+    pub(crate) fn groups(&self) -> Result<Vec<Group>> {
+        self.group_store.list()
+    }
+
+    pub(crate) fn save_group(&self, group: &Group) -> Result<()> {
+        self.group_store.save(group)
+    }
+
+    pub(crate) fn blocked_list(&self) -> Result<BlockedList> {
+        self.blocked_store.get()
+    }
+
+    pub(crate) fn set_blocked_list(&self, blocked: &BlockedList) -> Result<()> {
+        self.blocked_store.set(blocked)
+    }
+
+    /// Checks whether the recipient is blocked under any of the identifiers known from contacts.
+    pub(crate) fn is_blocked(&self, identifier: &str) -> Result<bool> {
+        let blocked = self.blocked_store.get()?;
+        Ok(match self.contact_store.find(identifier)? {
+            Some(contact) => blocked.contains(contact.uuid.as_deref(), contact.number.as_deref()),
+            None => blocked.contains(Some(identifier), Some(identifier)),
         })
     }
 
+    pub(crate) fn settings(&self) -> Result<Settings> {
+        self.settings_store.get()
+    }
+
+    pub(crate) fn set_settings(&self, settings: &Settings) -> Result<()> {
+        self.settings_store.set(settings)
+    }
+
+    /// Reads the address and phone number of the account stored in a database dump.
+    pub(crate) fn account_from_dump(
+        trees: &[TreeDump],
+    ) -> Result<(ProtocolAddress, Option<String>)> {
+        SledIdentityStore::account_from_dump(trees)
+    }
+
+    /// Removes all data of the account from the database.
+    #[instrument(skip(self))]
+    pub(crate) fn wipe(&self) -> Result<()> {
+        for name in self.db.tree_names() {
+            self.db.open_tree(name)?.clear()?;
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Replaces the content of the whole database with the given trees.
+    /// The trees are swapped in by a single transaction, so failed import leaves
+    /// the database as it was. Trees left out of exports are kept untouched.
+    pub(crate) fn import_trees(&self, trees: Vec<TreeDump>) -> Result<()> {
+        let mut imported: HashMap<Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>> = trees
+            .into_iter()
+            .filter(|(name, _)| !is_unexported_tree(name))
+            .collect();
+        let mut targets = Vec::new();
+        let mut changes = Vec::new();
+        for name in self.db.tree_names() {
+            if is_unexported_tree(&name) {
+                continue;
+            }
+            let tree = self.db.open_tree(&name)?;
+            let stale = tree.iter().keys().collect::<sled::Result<Vec<_>>>()?;
+            changes.push((stale, imported.remove(name.as_ref()).unwrap_or_default()));
+            targets.push(tree);
+        }
+        for (name, entries) in imported {
+            targets.push(self.db.open_tree(name)?);
+            changes.push((Vec::new(), entries));
+        }
+
+        targets
+            .as_slice()
+            .transaction(|views| {
+                for (view, (stale, entries)) in views.iter().zip(&changes) {
+                    for key in stale {
+                        view.remove(key.clone())?;
+                    }
+                    for (key, value) in entries {
+                        view.insert(key.as_slice(), value.as_slice())?;
+                    }
+                }
+                Ok::<_, ConflictableTransactionError<()>>(())
+            })
+            .map_err(|err| match err {
+                TransactionError::Abort(()) => unreachable!("Import never aborts"),
+                TransactionError::Storage(err) => err,
+            })?;
+        // Backups made before the expiry index existed don't carry it
+        self.message_store.rebuild_expiry_index()?;
+        self.db.flush()?;
+        Ok(())
+    }
+
     pub(crate) fn api_username(&self) -> Result<String> {
         self.identity_store.get_api_user()
     }
@@ -45,12 +376,14 @@ impl SledStateStore {
         identity_key_pair: IdentityKeyPair,
         registration_id: u32,
         address: ProtocolAddress,
+        number: &str,
         api_pass: String,
     ) -> Result<()> {
         self.identity_store.register_new_account(
             identity_key_pair,
             registration_id,
             address,
+            number,
             api_pass,
         )
     }
@@ -165,3 +498,7 @@ impl IdentityKeyStore for SledStateStore {
 }
 
 impl ProtocolStore for SledStateStore {}
+
+fn is_unexported_tree(name: &[u8]) -> bool {
+    name == VIEW_ONCE_TREE.as_bytes()
+}
@@ -0,0 +1,131 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Db, Tree};
+
+use crate::error::Result;
+use crate::proto::signal_service::{sticker_pack, StickerPack};
+
+/// Installed sticker pack with its decrypted manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StoredStickerPack {
+    /// Hex encoded pack id
+    pub(crate) id: String,
+    /// Hex encoded pack key
+    pub(crate) key: String,
+    pub(crate) title: Option<String>,
+    pub(crate) author: Option<String>,
+    pub(crate) cover: Option<u32>,
+    pub(crate) stickers: Vec<StoredSticker>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StoredSticker {
+    pub(crate) id: u32,
+    pub(crate) emoji: Option<String>,
+    #[serde(rename = "contentType")]
+    pub(crate) content_type: Option<String>,
+}
+
+impl StoredStickerPack {
+    pub(crate) fn new(id: String, key: String, manifest: &StickerPack) -> Self {
+        Self {
+            id,
+            key,
+            title: manifest.title.clone(),
+            author: manifest.author.clone(),
+            cover: manifest.cover.as_ref().and_then(|cover| cover.id),
+            stickers: manifest.stickers.iter().map(Into::into).collect(),
+        }
+    }
+
+    pub(crate) fn sticker(&self, id: u32) -> Option<&StoredSticker> {
+        self.stickers.iter().find(|sticker| sticker.id == id)
+    }
+}
+
+impl From<&sticker_pack::Sticker> for StoredSticker {
+    fn from(sticker: &sticker_pack::Sticker) -> Self {
+        Self {
+            id: sticker.id(),
+            emoji: sticker.emoji.clone().filter(|emoji| !emoji.is_empty()),
+            content_type: sticker.content_type.clone(),
+        }
+    }
+}
+
+/// Keeps manifests of installed packs in one tree and the decrypted stickers in another,
+/// keyed by the pack id and big endian sticker id.
+#[derive(Clone)]
+pub(crate) struct SledStickerStore {
+    packs: Tree,
+    images: Tree,
+}
+
+impl TryFrom<&Db> for SledStickerStore {
+    type Error = sled::Error;
+    fn try_from(db: &Db) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            packs: db.open_tree("sticker_packs")?,
+            images: db.open_tree("sticker_images")?,
+        })
+    }
+}
+
+impl SledStickerStore {
+    fn image_key(pack_id: &str, sticker_id: u32) -> Vec<u8> {
+        let mut key = Self::image_prefix(pack_id);
+        key.extend_from_slice(&sticker_id.to_be_bytes());
+        key
+    }
+
+    fn image_prefix(pack_id: &str) -> Vec<u8> {
+        let mut key = pack_id.as_bytes().to_vec();
+        key.push(b'/');
+        key
+    }
+
+    pub(crate) fn list(&self) -> Result<Vec<StoredStickerPack>> {
+        self.packs
+            .iter()
+            .map(|pair| Ok(serde_json::from_slice(&pair?.1)?))
+            .collect()
+    }
+
+    pub(crate) fn get(&self, pack_id: &str) -> Result<Option<StoredStickerPack>> {
+        match self.packs.get(pack_id)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn image(&self, pack_id: &str, sticker_id: u32) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .images
+            .get(Self::image_key(pack_id, sticker_id))?
+            .map(|value| value.to_vec()))
+    }
+
+    /// Stores the pack together with its stickers. The manifest is written last,
+    /// so the pack is listed only once all of its stickers are present.
+    pub(crate) fn save(&self, pack: &StoredStickerPack, images: &[(u32, Vec<u8>)]) -> Result<()> {
+        let mut batch = Batch::default();
+        for (sticker_id, data) in images {
+            batch.insert(Self::image_key(&pack.id, *sticker_id), data.as_slice());
+        }
+        self.images.apply_batch(batch)?;
+        self.packs.insert(&pack.id, serde_json::to_vec(pack)?)?;
+        Ok(())
+    }
+
+    /// Removes the pack and its stickers. Returns whether the pack was installed.
+    pub(crate) fn remove(&self, pack_id: &str) -> Result<bool> {
+        let removed = self.packs.remove(pack_id)?.is_some();
+        let mut batch = Batch::default();
+        for key in self.images.scan_prefix(Self::image_prefix(pack_id)).keys() {
+            batch.remove(key?);
+        }
+        self.images.apply_batch(batch)?;
+        Ok(removed)
+    }
+}
@@ -1,4 +1,5 @@
 use libsignal_protocol::{DeviceId, ProtocolAddress};
+use sled::{Batch, Tree};
 
 #[derive(Debug, Clone)]
 pub(super) struct ProtocolAddressBytes(Box<[u8]>);
@@ -53,3 +54,24 @@ pub(super) fn sled_to_signal_error(
 ) -> libsignal_protocol::SignalProtocolError {
     libsignal_protocol::error::SignalProtocolError::InvalidState(call, err.to_string())
 }
+
+/// Moves all entries keyed by addresses with name `from` under the name `to`.
+/// Entries already present under the new name are kept.
+pub(super) fn rename_addresses(tree: &Tree, from: &str, to: &str) -> sled::Result<()> {
+    let mut batch = Batch::default();
+    for pair in tree.scan_prefix(from) {
+        let (key, value) = pair?;
+        let bytes = ProtocolAddressBytes::new(key.to_vec().into_boxed_slice());
+        if bytes.name_bytes() != from.as_bytes() {
+            continue;
+        }
+        let address =
+            ProtocolAddress::new(to.to_string(), ProtocolAddress::from(bytes).device_id());
+        let new_key = ProtocolAddressBytes::from(&address);
+        if !tree.contains_key(&new_key)? {
+            batch.insert(new_key.as_ref(), value);
+        }
+        batch.remove(key);
+    }
+    tree.apply_batch(batch)
+}
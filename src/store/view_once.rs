@@ -0,0 +1,85 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use crate::error::Result;
+use crate::utils::serde::{deserialize_byte_vec, serialize_byte_vec};
+
+/// Decrypted attachment of a received view-once message, kept apart from the message
+/// history until it is opened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ViewOnceMedia {
+    /// Identifier of the sender
+    pub(crate) sender: String,
+    /// Sent timestamp of the message
+    pub(crate) timestamp: u64,
+    #[serde(rename = "contentType")]
+    pub(crate) content_type: Option<String>,
+    #[serde(rename = "fileName")]
+    pub(crate) file_name: Option<String>,
+    #[serde(
+        serialize_with = "serialize_byte_vec",
+        deserialize_with = "deserialize_byte_vec"
+    )]
+    pub(crate) data: Vec<u8>,
+}
+
+impl ViewOnceMedia {
+    fn key(sender: &str, timestamp: u64) -> Vec<u8> {
+        let mut key = Vec::with_capacity(sender.len() + 9);
+        key.extend_from_slice(sender.as_bytes());
+        key.push(b'/');
+        key.extend_from_slice(&timestamp.to_be_bytes());
+        key
+    }
+}
+
+/// Name of the tree. Unopened media are left out of backups, so a restored
+/// backup can't be used to view them again.
+pub(super) const VIEW_ONCE_TREE: &str = "view_once";
+
+#[derive(Clone)]
+pub(crate) struct SledViewOnceStore(Tree);
+
+impl TryFrom<&Db> for SledViewOnceStore {
+    type Error = sled::Error;
+    fn try_from(db: &Db) -> std::result::Result<Self, Self::Error> {
+        Ok(Self(db.open_tree(VIEW_ONCE_TREE)?))
+    }
+}
+
+impl SledViewOnceStore {
+    pub(crate) fn list(&self) -> Result<Vec<ViewOnceMedia>> {
+        self.0
+            .iter()
+            .map(|pair| Ok(serde_json::from_slice(&pair?.1)?))
+            .collect()
+    }
+
+    pub(crate) fn save(&self, media: &ViewOnceMedia) -> Result<()> {
+        self.0.insert(
+            ViewOnceMedia::key(&media.sender, media.timestamp),
+            serde_json::to_vec(media)?,
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, sender: &str, timestamp: u64) -> Result<Option<ViewOnceMedia>> {
+        match self.0.get(ViewOnceMedia::key(sender, timestamp))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the opened media. The tree is flushed, so the media can't be
+    /// viewed again after a crash.
+    pub(crate) fn remove(&self, sender: &str, timestamp: u64) -> Result<bool> {
+        let removed = self
+            .0
+            .remove(ViewOnceMedia::key(sender, timestamp))?
+            .is_some();
+        self.0.flush()?;
+        Ok(removed)
+    }
+}
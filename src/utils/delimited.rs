@@ -0,0 +1,27 @@
+use prost::Message;
+
+use crate::error::Result;
+
+/// Reads stream of length-delimited protobuf messages as used by contact and group sync blobs.
+/// Every message can be followed by attachment (avatar) of length given by `attachment_len`,
+/// which is skipped.
+pub(crate) fn read_delimited<M, F>(mut data: &[u8], attachment_len: F) -> Result<Vec<M>>
+where
+    M: Message + Default,
+    F: Fn(&M) -> usize,
+{
+    let mut messages = Vec::new();
+    while !data.is_empty() {
+        let len = prost::encoding::decode_varint(&mut data)? as usize;
+        if data.len() < len {
+            return Err(prost::DecodeError::new("buffer underflow").into());
+        }
+        let message = M::decode(&data[..len])?;
+        data = &data[len..];
+
+        let attachment_len = attachment_len(&message).min(data.len());
+        data = &data[attachment_len..];
+        messages.push(message);
+    }
+    Ok(messages)
+}
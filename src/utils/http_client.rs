@@ -1,15 +1,18 @@
 use std::io::Read;
 use std::ops::Deref;
+use std::time::Duration;
 
 use base64::engine::{general_purpose::STANDARD, Engine as _};
 use hyper::body::{Buf, HttpBody};
 use hyper::header::{
-    HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, HOST, USER_AGENT,
+    HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, HOST, RETRY_AFTER,
+    USER_AGENT,
 };
 use hyper::http::uri::{Authority, Scheme};
 use hyper::{Body, Client, Method, Request, Response, Uri};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use tracing::{debug, trace};
 
 use crate::common::{ApiConfig, ApiPath};
 use crate::error::{Error, Result};
@@ -25,15 +28,35 @@ pub(crate) struct WrappedResponse(Response<Body>);
 
 impl HttpClient {
     pub(crate) fn new(username: &str, password: &str, api_config: &ApiConfig) -> Result<Self> {
-        let connector = HttpsWssConnector::new(api_config)?;
-        let client = Client::builder().build(connector);
-        let mut default_headers = HeaderMap::new();
+        let mut client = Self::unauthenticated(api_config)?;
 
         let creds = format!("{}:{}", username, password);
         let auth_value = format!("Basic {}", STANDARD.encode(creds));
         let mut auth_value = HeaderValue::from_str(&auth_value).expect("Base64 chars are allowed.");
         auth_value.set_sensitive(true);
-        default_headers.insert(AUTHORIZATION, auth_value);
+        client.default_headers.insert(AUTHORIZATION, auth_value);
+
+        Ok(client)
+    }
+
+    /// Client for endpoints which don't require an account, e.g. requesting verification code.
+    pub(crate) fn unauthenticated(api_config: &ApiConfig) -> Result<Self> {
+        Self::with_authority(api_config, api_config.authority.clone())
+    }
+
+    /// Client downloading attachments from the CDN given by `cdnNumber` of the attachment.
+    pub(crate) fn cdn(api_config: &ApiConfig, cdn_number: u32) -> Result<Self> {
+        let authority = api_config
+            .cdn_authorities
+            .get(&cdn_number)
+            .ok_or_else(|| Error::ConfigError(format!("Unknown CDN {}", cdn_number)))?;
+        Self::with_authority(api_config, authority.clone())
+    }
+
+    fn with_authority(api_config: &ApiConfig, authority: Authority) -> Result<Self> {
+        let connector = HttpsWssConnector::new(api_config)?;
+        let client = Client::builder().build(connector);
+        let mut default_headers = HeaderMap::new();
 
         default_headers.insert(
             USER_AGENT,
@@ -42,14 +65,13 @@ impl HttpClient {
         );
         default_headers.insert(
             HOST,
-            HeaderValue::from_str(api_config.authority.host())
-                .expect("Host from Authority is valid."),
+            HeaderValue::from_str(authority.host()).expect("Host from Authority is valid."),
         );
 
         Ok(Self {
             client,
             default_headers,
-            authority: api_config.authority.clone(),
+            authority,
         })
     }
 
@@ -58,6 +80,7 @@ impl HttpClient {
         method: Method,
         path: ApiPath<'_>,
         body: Body,
+        content_type: HeaderValue,
     ) -> Result<WrappedResponse> {
         let uri = Uri::builder()
             .scheme(Scheme::HTTPS)
@@ -76,15 +99,28 @@ impl HttpClient {
                 CONTENT_LENGTH,
                 HeaderValue::from_str(&format!("{}", size)).expect("Numbers are always valid"),
             );
-            builder = builder.header(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            builder = builder.header(CONTENT_TYPE, content_type);
         }
 
         let req = builder.body(body)?;
-        eprintln!("{:?}", req);
+        debug!(method = %req.method(), uri = %req.uri(), "sending request");
+        trace!(headers = ?req.headers());
 
         let resp = self.client.request(req).await?;
+        debug!(status = %resp.status(), "received response");
         if resp.status().is_success() {
             Ok(resp.into())
+        } else if matches!(resp.status().as_u16(), 413 | 429) {
+            // Only the delay in seconds is used by the server, not the HTTP date
+            let retry_after = resp
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs);
+            Err(Error::RateLimited { retry_after })
+        } else if let Some(err) = path.status_error(resp.status()) {
+            Err(err)
         } else if resp.status().as_u16() == 499 {
             Err(Error::DeprecatedHttpError(
                 WrappedResponse(resp).text().await?,
@@ -98,7 +134,8 @@ impl HttpClient {
     }
 
     pub(crate) async fn send(&self, method: Method, path: ApiPath<'_>) -> Result<WrappedResponse> {
-        self.send_inner(method, path, Body::empty()).await
+        self.send_inner(method, path, Body::empty(), json_content_type())
+            .await
     }
 
     pub(crate) async fn send_json<S: Serialize>(
@@ -108,10 +145,53 @@ impl HttpClient {
         body: &S,
     ) -> Result<WrappedResponse> {
         let serialized_body = serde_json::to_vec(body)?;
-        self.send_inner(method, path, serialized_body.into()).await
+        self.send_inner(method, path, serialized_body.into(), json_content_type())
+            .await
+    }
+
+    /// Sends `multipart/form-data` body with the text fields followed by the file,
+    /// as expected by the CDN when uploading with pre-signed form.
+    pub(crate) async fn send_multipart(
+        &self,
+        method: Method,
+        path: ApiPath<'_>,
+        fields: &[(&str, &str)],
+        file: &[u8],
+    ) -> Result<WrappedResponse> {
+        let boundary = format!("----signal-dbus-client-{:016x}", rand::random::<u64>());
+        let mut body = Vec::with_capacity(file.len() + 1024);
+        for (name, value) in fields {
+            body.extend_from_slice(
+                format!(
+                    "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                    boundary, name, value
+                )
+                .as_bytes(),
+            );
+        }
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"file\"\r\n\
+                 Content-Type: application/octet-stream\r\n\r\n",
+                boundary
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(file);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let content_type =
+            HeaderValue::from_str(&format!("multipart/form-data; boundary={}", boundary))
+                .expect("Boundary contains allowed charset.");
+        self.send_inner(method, path, body.into(), content_type)
+            .await
     }
 }
 
+fn json_content_type() -> HeaderValue {
+    HeaderValue::from_static("application/json")
+}
+
 impl WrappedResponse {
     pub(crate) async fn bytes(self) -> Result<impl Buf> {
         hyper::body::aggregate(self.0.into_body())
@@ -119,6 +199,10 @@ impl WrappedResponse {
             .map_err(Into::into)
     }
 
+    pub(crate) async fn data(self) -> Result<Vec<u8>> {
+        Ok(hyper::body::to_bytes(self.0.into_body()).await?.to_vec())
+    }
+
     pub(crate) async fn json<T: DeserializeOwned>(self) -> Result<T> {
         let bytes = self.bytes().await?;
         serde_json::from_reader(bytes.reader()).map_err(Into::into)
@@ -1,5 +1,6 @@
 use std::convert::TryFrom;
 use std::future::Future;
+use std::net::IpAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -20,16 +21,32 @@ use super::TlsStream;
 pub struct HttpsWssConnector {
     http: HttpConnector<GaiResolver>,
     tls_config: Arc<ClientConfig>,
+    /// Refuses connections to loopback, private and link-local addresses
+    public_only: bool,
 }
 
 impl HttpsWssConnector {
     pub fn new(api_config: &ApiConfig) -> Result<Self> {
+        Ok(Self::with_tls_config(api_config.rustls_config()?))
+    }
+
+    pub fn with_tls_config(tls_config: ClientConfig) -> Self {
         let mut http = HttpConnector::new();
         http.enforce_http(false);
 
-        let tls_config = Arc::new(api_config.rustls_config()?);
+        Self {
+            http,
+            tls_config: Arc::new(tls_config),
+            public_only: false,
+        }
+    }
 
-        Ok(Self { http, tls_config })
+    /// Connects only to public addresses, so that fetched URLs can't reach services
+    /// of the local machine or network. The address is checked after the host name is
+    /// resolved, so DNS can't be used to get around it.
+    pub fn public_only(mut self) -> Self {
+        self.public_only = true;
+        self
     }
 }
 
@@ -38,6 +55,7 @@ impl Clone for HttpsWssConnector {
         Self {
             http: self.http.clone(),
             tls_config: Arc::clone(&self.tls_config),
+            public_only: self.public_only,
         }
     }
 }
@@ -46,6 +64,41 @@ fn connection_error<S: Into<String>>(reason: S) -> Error {
     Error::ConnectionError(reason.into())
 }
 
+/// Checks that the address is globally reachable, i.e. neither loopback, private,
+/// link-local nor otherwise reserved.
+fn is_public_address(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => {
+            let [first, second, ..] = address.octets();
+            // Shared address space of carrier-grade NATs, 100.64.0.0/10
+            let shared = first == 100 && second & 0xc0 == 64;
+            !(address.is_loopback()
+                || address.is_private()
+                || address.is_link_local()
+                || address.is_unspecified()
+                || address.is_broadcast()
+                || address.is_documentation()
+                || address.is_multicast()
+                || shared
+                || first == 0)
+        }
+        IpAddr::V6(address) => {
+            if let Some(mapped) = address.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(mapped));
+            }
+            let first = address.segments()[0];
+            // Unique local fc00::/7 and link-local fe80::/10 addresses
+            let unique_local = first & 0xfe00 == 0xfc00;
+            let link_local = first & 0xffc0 == 0xfe80;
+            !(address.is_loopback()
+                || address.is_unspecified()
+                || address.is_multicast()
+                || unique_local
+                || link_local)
+        }
+    }
+}
+
 impl Service<Uri> for HttpsWssConnector {
     type Response = TlsStream;
     type Error = Error;
@@ -65,6 +118,7 @@ impl Service<Uri> for HttpsWssConnector {
         }
 
         let cfg = self.tls_config.clone();
+        let public_only = self.public_only;
         let hostname = dst.host().unwrap_or_default().to_string();
         let connecting_future = self.http.call(dst);
 
@@ -72,6 +126,18 @@ impl Service<Uri> for HttpsWssConnector {
             let tcp = connecting_future
                 .await
                 .map_err(|_| connection_error("unknown"))?;
+            if public_only {
+                let address = tcp
+                    .peer_addr()
+                    .map_err(|err| connection_error(err.to_string()))?
+                    .ip();
+                if !is_public_address(address) {
+                    return Err(connection_error(format!(
+                        "{} resolves to non-public address {}",
+                        hostname, address
+                    )));
+                }
+            }
             let connector = TlsConnector::from(cfg);
             let server_name = ServerName::try_from(hostname.as_str()).expect("invalid DNS name");
             let tls = connector
@@ -83,3 +149,45 @@ impl Service<Uri> for HttpsWssConnector {
         Box::pin(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_public(address: &str) -> bool {
+        is_public_address(address.parse().unwrap())
+    }
+
+    #[test]
+    fn local_addresses_are_not_public() {
+        for address in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:192.168.0.1",
+        ] {
+            assert!(!is_public(address), "{}", address);
+        }
+    }
+
+    #[test]
+    fn global_addresses_are_public() {
+        for address in [
+            "1.1.1.1",
+            "93.184.216.34",
+            "2606:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(is_public(address), "{}", address);
+        }
+    }
+}
@@ -1,12 +1,19 @@
+mod delimited;
 mod http_client;
 mod https_wss_connector;
 mod qrcode;
 pub(crate) mod serde;
+mod time;
 mod tls_stream;
+mod web_client;
 mod wss_connection;
 
-pub(crate) use crate::utils::qrcode::qrcode_image;
+pub use crate::utils::qrcode::QrCodeFormat;
+pub(crate) use crate::utils::qrcode::{qrcode_image, qrcode_png, qrcode_svg};
+pub(crate) use delimited::read_delimited;
 pub(crate) use http_client::HttpClient;
 pub(crate) use https_wss_connector::HttpsWssConnector;
+pub(crate) use time::timestamp_millis;
 pub(crate) use tls_stream::TlsStream;
+pub(crate) use web_client::WebClient;
 pub(crate) use wss_connection::connect_wss;
@@ -1,6 +1,22 @@
+use std::path::Path;
+
+use image::{ImageFormat, Luma};
+use qrcode::render::svg;
 use qrcode::render::unicode::Dense1x2;
 use qrcode::QrCode;
 
+use crate::error::{Error, Result};
+
+/// Minimal size of rendered images. Smaller codes are hard to scan from a screen.
+const MIN_IMAGE_SIZE: u32 = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QrCodeFormat {
+    Text,
+    Png,
+    Svg,
+}
+
 pub(crate) fn qrcode_image<D: AsRef<[u8]>>(data: D, inverted: bool) -> String {
     let code = QrCode::new(data).unwrap();
     let mut rederer = code.render::<Dense1x2>();
@@ -13,3 +29,19 @@ pub(crate) fn qrcode_image<D: AsRef<[u8]>>(data: D, inverted: bool) -> String {
 
     rederer.build()
 }
+
+pub(crate) fn qrcode_svg<D: AsRef<[u8]>>(data: D) -> String {
+    let code = QrCode::new(data).unwrap();
+    code.render::<svg::Color>()
+        .min_dimensions(MIN_IMAGE_SIZE, MIN_IMAGE_SIZE)
+        .build()
+}
+
+pub(crate) fn qrcode_png<D: AsRef<[u8]>>(data: D, path: &Path) -> Result<()> {
+    let code = QrCode::new(data).unwrap();
+    code.render::<Luma<u8>>()
+        .min_dimensions(MIN_IMAGE_SIZE, MIN_IMAGE_SIZE)
+        .build()
+        .save_with_format(path, ImageFormat::Png)
+        .map_err(|err| Error::ImageError(err.to_string()))
+}
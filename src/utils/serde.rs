@@ -62,6 +62,28 @@ where
     serializer.serialize_str(&encoded)
 }
 
+pub(crate) fn deserialize_opt_byte_vec<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
+    s.map(|s| STANDARD.decode(s).map_err(D::Error::custom))
+        .transpose()
+}
+
+pub(crate) fn serialize_opt_byte_vec<S>(
+    value: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(value) => serializer.serialize_some(&STANDARD.encode(value)),
+        None => serializer.serialize_none(),
+    }
+}
+
 pub(crate) fn serialize_ciphertext_message<S>(
     value: &CiphertextMessage,
     serializer: S,
@@ -0,0 +1,9 @@
+use std::time::SystemTime;
+
+/// Milliseconds since the Unix epoch, as used for message timestamps.
+pub(crate) fn timestamp_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
+}
@@ -0,0 +1,97 @@
+use hyper::body::HttpBody;
+use hyper::header::{HeaderValue, CONTENT_TYPE, LOCATION, USER_AGENT};
+use hyper::{Body, Client, Request, Uri};
+use url::Url;
+
+use crate::common::WebConfig;
+use crate::error::{Error, Result};
+use crate::utils::HttpsWssConnector;
+
+/// Client fetching arbitrary HTTPS resources, following redirects.
+pub(crate) struct WebClient {
+    client: Client<HttpsWssConnector>,
+    config: WebConfig,
+}
+
+/// Body of the fetched resource with its content type
+pub(crate) struct WebResource {
+    pub(crate) url: Url,
+    pub(crate) content_type: Option<String>,
+    pub(crate) data: Vec<u8>,
+}
+
+impl WebClient {
+    pub(crate) fn new(config: &WebConfig) -> Result<Self> {
+        let connector = HttpsWssConnector::with_tls_config(config.rustls_config()?).public_only();
+        Ok(Self {
+            client: Client::builder().build(connector),
+            config: config.clone(),
+        })
+    }
+
+    pub(crate) async fn get(&self, url: &Url) -> Result<WebResource> {
+        tokio::time::timeout(self.config.timeout, self.get_inner(url.clone()))
+            .await
+            .map_err(|_| Error::ConnectionError(format!("Fetching {} timed out", url)))?
+    }
+
+    async fn get_inner(&self, mut url: Url) -> Result<WebResource> {
+        for _ in 0..=self.config.max_redirects {
+            if url.scheme() != "https" {
+                return Err(Error::ConnectionError(format!("{} is not HTTPS URL", url)));
+            }
+            let uri: Uri = url
+                .as_str()
+                .parse()
+                .map_err(|_| Error::ConnectionError(format!("Invalid URL {}", url)))?;
+            let request = Request::get(uri)
+                .header(
+                    USER_AGENT,
+                    HeaderValue::from_str(&self.config.user_agent)
+                        .expect("User agent contains allowed charset."),
+                )
+                .body(Body::empty())?;
+            let response = self.client.request(request).await?;
+
+            if response.status().is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .ok_or_else(|| {
+                        Error::ConnectionError(String::from("Missing redirect target"))
+                    })?;
+                url = url.join(location).map_err(|_| {
+                    Error::ConnectionError(format!("Invalid redirect {}", location))
+                })?;
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(Error::HttpError(response.status(), url.to_string()));
+            }
+
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let mut body = response.into_body();
+            let mut data = Vec::new();
+            while let Some(chunk) = body.data().await {
+                data.extend_from_slice(&chunk?);
+                if data.len() > self.config.max_size {
+                    return Err(Error::ConnectionError(format!("{} is too large", url)));
+                }
+            }
+            return Ok(WebResource {
+                url,
+                content_type,
+                data,
+            });
+        }
+        Err(Error::ConnectionError(format!(
+            "Too many redirects from {}",
+            url
+        )))
+    }
+}
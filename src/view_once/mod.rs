@@ -0,0 +1,43 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rand::rngs::OsRng;
+
+use crate::account::AccountManager;
+use crate::common::ApiConfig;
+use crate::error::Result;
+use crate::store::AccountRegistry;
+
+/// Lists received view-once messages, which were not opened yet.
+pub fn list_view_once(data_dir: PathBuf, account: Option<&str>) -> Result<()> {
+    let state_store = AccountRegistry::load(data_dir)?.open(account)?;
+    for media in state_store.view_once_media()? {
+        println!(
+            "{}\t{}\t{}\t{}",
+            media.timestamp,
+            media.sender,
+            media.content_type.as_deref().unwrap_or("-"),
+            media.data.len(),
+        );
+    }
+    Ok(())
+}
+
+/// Writes the view-once media into `output` and deletes it here and on our other devices.
+/// The media is deleted only once it is written.
+pub async fn open_view_once(
+    data_dir: PathBuf,
+    account: Option<&str>,
+    sender: &str,
+    timestamp: u64,
+    output: &Path,
+) -> Result<()> {
+    let api_config = ApiConfig::default();
+    let account_manager = AccountManager::new(data_dir, account, OsRng, &api_config)?;
+
+    let media = account_manager.unopened_view_once(sender, timestamp)?;
+    fs::write(output, &media.data)?;
+    eprintln!("View-once media written to {}.", output.display());
+    account_manager.view_once_opened(&media).await?.report();
+    Ok(())
+}